@@ -0,0 +1,14 @@
+use xca9548a::bench;
+
+fn main() {
+    let iterations = 1_000_000;
+    println!("{} iterations each", iterations);
+    for m in bench::run_all(iterations) {
+        println!(
+            "{:<32} {:>10?}/iter {:>10} bus operations",
+            m.name,
+            m.per_iteration(),
+            m.bus_operations
+        );
+    }
+}
@@ -3,7 +3,7 @@ use linux_embedded_hal::I2cdev;
 use xca9548a::{Error, SlaveAddr, Xca9548a};
 
 fn main() {
-    let slave_address = 0b010_0000; // example slave address
+    let slave_address: u8 = 0b010_0000; // example slave address
     let write_data = [0b0101_0101, 0b1010_1010]; // some data to be sent
     let dev = I2cdev::new("/dev/i2c-1").unwrap();
 
@@ -0,0 +1,69 @@
+use core::marker::PhantomData;
+use embedded_hal::i2c as ehal;
+
+/// I²C bus adapter converting the bus errors into an application error type
+///
+/// This wraps any I²C bus, for example a split part ([`I2cSlave`](crate::I2cSlave)),
+/// and converts its errors into the application error type `AE`
+/// through its `From` implementation.
+/// This allows passing the virtual I²C buses to code that standardizes
+/// on a single application error type.
+#[derive(Debug)]
+pub struct ErrorAdapter<I2C, AE> {
+    i2c: I2C,
+    _error: PhantomData<fn() -> AE>,
+}
+
+impl<I2C, AE> ErrorAdapter<I2C, AE> {
+    /// Create new adapter wrapping an I²C bus.
+    pub fn new(i2c: I2C) -> Self {
+        ErrorAdapter {
+            i2c,
+            _error: PhantomData,
+        }
+    }
+
+    /// Destroy the adapter, return the wrapped I²C bus.
+    pub fn into_inner(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C, AE> ehal::ErrorType for ErrorAdapter<I2C, AE>
+where
+    I2C: ehal::ErrorType,
+    AE: From<I2C::Error> + ehal::Error,
+{
+    type Error = AE;
+}
+
+impl<I2C, AE> ehal::I2c for ErrorAdapter<I2C, AE>
+where
+    I2C: ehal::I2c,
+    AE: From<I2C::Error> + ehal::Error,
+{
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [ehal::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.i2c.transaction(address, operations).map_err(AE::from)
+    }
+
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.i2c.read(address, read).map_err(AE::from)
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.i2c.write(address, write).map_err(AE::from)
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.i2c.write_read(address, write, read).map_err(AE::from)
+    }
+}
@@ -0,0 +1,153 @@
+//! Benchmark harness driving the devices against a simulated bus.
+//!
+//! This measures the overhead of the driver itself (channel selection,
+//! caching and the split parts) since the simulated bus does no actual I/O.
+//! The inputs are passed through `black_box()` so that the loops are not optimized away.
+//! Run it with `cargo run --release --features bench --example bench`.
+
+use crate::{SlaveAddr, Xca9548a, DEFAULT_ADDRESS};
+use core::convert::Infallible;
+use embedded_hal::i2c::{self as ehal, I2c};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Simulated I²C bus with a switch at the default address
+///
+/// Writes to the switch set its control register and reads return it.
+/// Any other slave acknowledges everything and reads as zeros.
+#[derive(Debug, Default)]
+pub struct SimulatedBus {
+    control: u8,
+    operations: usize,
+}
+
+impl SimulatedBus {
+    /// Create new simulated bus.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of operations done on the bus so far.
+    pub fn operations(&self) -> usize {
+        self.operations
+    }
+}
+
+impl ehal::ErrorType for SimulatedBus {
+    type Error = Infallible;
+}
+
+impl ehal::I2c for SimulatedBus {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [ehal::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        for operation in operations {
+            self.operations += 1;
+            match operation {
+                ehal::Operation::Write(data) if address == DEFAULT_ADDRESS => {
+                    if let Some(value) = data.last() {
+                        self.control = *value;
+                    }
+                }
+                ehal::Operation::Read(buffer) if address == DEFAULT_ADDRESS => {
+                    buffer.fill(self.control);
+                }
+                ehal::Operation::Read(buffer) => buffer.fill(0),
+                ehal::Operation::Write(_) => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Result of a benchmark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Measurement {
+    /// Benchmark name
+    pub name: &'static str,
+    /// Number of iterations run
+    pub iterations: u32,
+    /// Total time elapsed
+    pub elapsed: Duration,
+    /// Number of operations done on the bus
+    pub bus_operations: usize,
+}
+
+impl Measurement {
+    /// Average time per iteration.
+    pub fn per_iteration(&self) -> Duration {
+        self.elapsed / self.iterations.max(1)
+    }
+}
+
+const SLAVE_ADDRESS: u8 = 0x20;
+
+fn measure(
+    name: &'static str,
+    iterations: u32,
+    run: impl FnOnce(&mut Xca9548a<SimulatedBus>, u32),
+) -> Measurement {
+    let mut switch = Xca9548a::new(SimulatedBus::new(), SlaveAddr::default());
+    let start = Instant::now();
+    run(&mut switch, iterations);
+    let elapsed = start.elapsed();
+    Measurement {
+        name,
+        iterations,
+        elapsed,
+        bus_operations: switch.destroy().operations(),
+    }
+}
+
+/// Run all benchmarks with the given number of iterations each.
+pub fn run_all(iterations: u32) -> Vec<Measurement> {
+    let data = [0xAB, 0xCD];
+    vec![
+        measure("select same channel", iterations, |switch, n| {
+            for _ in 0..n {
+                switch.select_channels(black_box(0b0000_0001)).unwrap();
+            }
+        }),
+        measure("select alternating channels", iterations, |switch, n| {
+            for i in 0..n {
+                switch.select_channels(black_box(1 << (i % 2))).unwrap();
+            }
+        }),
+        measure("device write", iterations, |switch, n| {
+            for _ in 0..n {
+                switch.write(SLAVE_ADDRESS, black_box(&data)).unwrap();
+            }
+        }),
+        measure("part write same channel", iterations, |switch, n| {
+            let mut parts = switch.split();
+            for _ in 0..n {
+                parts.i2c0.write(SLAVE_ADDRESS, black_box(&data)).unwrap();
+            }
+        }),
+        measure(
+            "part write alternating channels",
+            iterations,
+            |switch, n| {
+                let mut parts = switch.split();
+                for _ in 0..n / 2 {
+                    parts.i2c0.write(SLAVE_ADDRESS, black_box(&data)).unwrap();
+                    parts.i2c1.write(SLAVE_ADDRESS, black_box(&data)).unwrap();
+                }
+            },
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_bus_operations() {
+        let results = run_all(10);
+        let operations: Vec<_> = results.iter().map(|m| m.bus_operations).collect();
+        assert_eq!(vec![1, 10, 10, 11, 20], operations);
+    }
+}
@@ -0,0 +1,127 @@
+//! Command line tool for inspecting TCA954xA/PCA954xA switches on Linux.
+//!
+//! Available with the `cli` feature:
+//!
+//! ```text
+//! cargo run --features cli -- /dev/i2c-1 scan
+//! ```
+
+use embedded_hal::i2c::I2c;
+use linux_embedded_hal::I2cdev;
+use std::{env, error::Error, process};
+use xca9548a::{Channel, SlaveAddr, Xca9548a};
+
+/// Addresses that can be strapped on the devices.
+const MUX_ADDRESSES: core::ops::RangeInclusive<u8> = 0x70..=0x77;
+/// Non-reserved addresses probed on the upstream bus.
+const SCAN_ADDRESSES: core::ops::RangeInclusive<u8> = 0x08..=0x77;
+
+const USAGE: &str = "Usage: xca954xa <i2c device> <command>
+
+Commands:
+    find                        List the switches found at 0x70-0x77
+    select <address> <mask>     Select the channels in <mask> on the switch at <address>
+    scan [address]              Print the devices found on each channel of the
+                                switch at [address] or of all switches found
+
+Numbers can be given in decimal or with a 0x/0b prefix.";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (path, command) = match args {
+        [path, command, ..] => (path, command.as_str()),
+        _ => return Err(USAGE.into()),
+    };
+    let mut bus = I2cdev::new(path)?;
+    match (command, &args[2..]) {
+        ("find", []) => {
+            for address in find_muxes(&mut bus) {
+                println!("0x{:02x}", address);
+            }
+            Ok(())
+        }
+        ("select", [address, mask]) => {
+            let mut switch = Xca9548a::new(bus, SlaveAddr::Raw(parse(address)?));
+            switch.select_channels(parse(mask)?)?;
+            Ok(())
+        }
+        ("scan", []) => {
+            let muxes = find_muxes(&mut bus);
+            if muxes.is_empty() {
+                return Err("no switch found".into());
+            }
+            scan(bus, &muxes)
+        }
+        ("scan", [address]) => scan(bus, &[parse(address)?]),
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn parse(text: &str) -> Result<u8, Box<dyn Error>> {
+    let value = if let Some(hex) = text.strip_prefix("0x") {
+        u8::from_str_radix(hex, 16)
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        u8::from_str_radix(binary, 2)
+    } else {
+        text.parse()
+    };
+    value.map_err(|_| format!("invalid number: {}", text).into())
+}
+
+fn probe(bus: &mut I2cdev, address: u8) -> bool {
+    bus.read(address, &mut [0]).is_ok()
+}
+
+/// Addresses of the devices answering in the switch address range.
+fn find_muxes(bus: &mut I2cdev) -> Vec<u8> {
+    MUX_ADDRESSES
+        .filter(|address| probe(bus, *address))
+        .collect()
+}
+
+/// Print the devices found on each channel of the switches at `muxes`.
+///
+/// All switches are deselected first so that the channels of one switch
+/// do not show up in the scan of another one. Devices answering on the
+/// upstream bus are listed separately and left out of the channels.
+fn scan(mut bus: I2cdev, muxes: &[u8]) -> Result<(), Box<dyn Error>> {
+    for address in muxes {
+        bus.write(*address, &[0])?;
+    }
+    let upstream: Vec<u8> = SCAN_ADDRESSES
+        .filter(|address| probe(&mut bus, *address))
+        .collect();
+    println!("{:<12} {}", "upstream", format_addresses(&upstream));
+    for address in muxes {
+        let mut switch = Xca9548a::new(bus, SlaveAddr::Raw(*address));
+        let result = switch.scan_all();
+        let deselect = switch.select_channels(0);
+        bus = switch.destroy();
+        let report = result?;
+        deselect?;
+        for channel in Channel::iter() {
+            let devices: Vec<u8> = report
+                .devices(channel)
+                .filter(|device| !upstream.contains(device))
+                .collect();
+            let name = format!("0x{:02x}/{}", address, channel.index());
+            println!("{:<12} {}", name, format_addresses(&devices));
+        }
+    }
+    Ok(())
+}
+
+fn format_addresses(addresses: &[u8]) -> String {
+    if addresses.is_empty() {
+        return "-".to_string();
+    }
+    let addresses: Vec<String> = addresses.iter().map(|a| format!("0x{:02x}", a)).collect();
+    addresses.join(" ")
+}
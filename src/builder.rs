@@ -0,0 +1,352 @@
+use crate::{SlaveAddr, DEFAULT_ADDRESS};
+use core::marker::PhantomData;
+
+/// Behavior of the split parts regarding the cached channel selection
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Trust the cached channel selection and only write the control register
+    /// when a different channel needs to be selected. (default)
+    #[default]
+    Trust,
+    /// Always write the control register before each transfer.
+    ///
+    /// This is more robust when the switch may be reconfigured externally
+    /// (e.g. by another master) at the cost of an additional write per transfer.
+    AlwaysSelect,
+}
+
+/// Behavior when the control register does not match the expected channels
+///
+/// Mismatches are detected by the write verification and the integrity check.
+/// See [`Builder::verify_writes()`](struct.Builder.html#method.verify_writes)
+/// and [`Builder::integrity_check_interval()`](struct.Builder.html#method.integrity_check_interval).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MismatchPolicy {
+    /// Update the cache to the channels read and return
+    /// `Error::VerificationFailed` or `Error::ExternalChange`. (default)
+    #[default]
+    Error,
+    /// Write the expected channels to the control register again and continue.
+    ///
+    /// A verified write is retried once and fails with
+    /// `Error::VerificationFailed` if the mismatch persists.
+    Rewrite,
+    /// Report the mismatch to the observer with `Observer::mismatch()`, update
+    /// the cache to the channels read and continue.
+    ///
+    /// Part transfers select their channel again if necessary.
+    Notify,
+}
+
+/// Number of retries and backoff between them for automatic recovery
+///
+/// See [`Builder::auto_recover()`](struct.Builder.html#method.auto_recover).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryPolicy {
+    /// Retry up to `retries` times waiting `delay_ns` nanoseconds before each retry.
+    Fixed {
+        /// Maximum number of retries
+        retries: u8,
+        /// Delay before each retry in nanoseconds
+        delay_ns: u32,
+    },
+    /// Retry up to `retries` times waiting `step_ns` nanoseconds more before each retry.
+    Linear {
+        /// Maximum number of retries
+        retries: u8,
+        /// Delay before the first retry and increment for each further retry in nanoseconds
+        step_ns: u32,
+    },
+    /// Retry up to `retries` times doubling the delay before each retry, starting
+    /// at `initial_ns` and up to `max_ns` nanoseconds.
+    ///
+    /// With `jitter`, a pseudo-random delay between half and the full computed
+    /// delay is used, so that several masters retrying at the same time drift apart.
+    Exponential {
+        /// Maximum number of retries
+        retries: u8,
+        /// Delay before the first retry in nanoseconds
+        initial_ns: u32,
+        /// Maximum delay in nanoseconds
+        max_ns: u32,
+        /// Whether to randomize the delays
+        jitter: bool,
+    },
+}
+
+impl Default for RetryPolicy {
+    /// A single retry without delay.
+    fn default() -> Self {
+        RetryPolicy::Fixed {
+            retries: 1,
+            delay_ns: 0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the retry number `attempt` (starting at 1) or `None` if
+    /// no more retries should be done.
+    ///
+    /// `random` is only used for the jitter.
+    pub(crate) fn backoff_ns(&self, attempt: u8, random: u32) -> Option<u32> {
+        match *self {
+            RetryPolicy::Fixed { retries, delay_ns } => (attempt <= retries).then_some(delay_ns),
+            RetryPolicy::Linear { retries, step_ns } => {
+                (attempt <= retries).then(|| step_ns.saturating_mul(u32::from(attempt)))
+            }
+            RetryPolicy::Exponential {
+                retries,
+                initial_ns,
+                max_ns,
+                jitter,
+            } => {
+                if attempt > retries {
+                    return None;
+                }
+                let factor = 1u32.checked_shl(u32::from(attempt - 1)).unwrap_or(u32::MAX);
+                let delay = initial_ns.saturating_mul(factor).min(max_ns);
+                if jitter {
+                    let half = delay / 2;
+                    Some(delay - half + random % (half + 1))
+                } else {
+                    Some(delay)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Config {
+    pub(crate) strict: bool,
+    pub(crate) single_channel: bool,
+    pub(crate) cache_policy: CachePolicy,
+    pub(crate) auto_deselect: bool,
+    pub(crate) auto_recover: bool,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) verify_writes: bool,
+    pub(crate) integrity_check_interval: u16,
+    pub(crate) mismatch_policy: MismatchPolicy,
+    /// Settle time after selecting each channel in nanoseconds.
+    pub(crate) settle_ns: [u32; 8],
+}
+
+impl Config {
+    /// Whether the cached channel selection can be used without any bus access.
+    #[inline]
+    pub(crate) fn trusts_cache(&self) -> bool {
+        self.cache_policy == CachePolicy::Trust && self.integrity_check_interval == 0
+    }
+
+    /// Longest settle time of the selected `channels`.
+    pub(crate) fn settle_time(&self, channels: u8) -> u32 {
+        self.settle_ns
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| channels & (1 << i) != 0)
+            .map(|(_, ns)| *ns)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Builder for the device drivers
+///
+/// Created with `builder()` on the device driver types, e.g. `Xca9548a::builder()`.
+///
+/// The drivers do not own any pins. The RESET pin is borrowed by the methods
+/// using it, e.g. `build_with_reset()` to reset the device before building it.
+/// The INT output of the PCA9543A/PCA9545A is left to the application, which
+/// can call `get_interrupt_status()` when it is asserted.
+#[derive(Debug, Clone, Copy)]
+pub struct Builder<DEV> {
+    pub(crate) base_address: u8,
+    pub(crate) address: SlaveAddr,
+    pub(crate) initial_channels: Option<u8>,
+    pub(crate) config: Config,
+    _device: PhantomData<fn() -> DEV>,
+}
+
+impl<DEV> Builder<DEV> {
+    pub(crate) fn new() -> Self {
+        Builder {
+            base_address: DEFAULT_ADDRESS,
+            address: SlaveAddr::default(),
+            initial_channels: None,
+            config: Config::default(),
+            _device: PhantomData,
+        }
+    }
+
+    /// Set the slave address. (default: `SlaveAddr::Default`)
+    pub fn address(mut self, address: SlaveAddr) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Set the base address for compatible devices. (default: `0b111_0000`)
+    pub fn base_address(mut self, base_address: u8) -> Self {
+        self.base_address = base_address;
+        self
+    }
+
+    /// Select these channels when building the device.
+    ///
+    /// Each bit corresponds to a channel. The control register is always
+    /// written, even to deselect all channels, so that the cache matches the
+    /// device from the start. By default the control register is not written.
+    pub fn initial_channels(mut self, channels: u8) -> Self {
+        self.initial_channels = Some(channels);
+        self
+    }
+
+    /// Enable strict mode. (default: disabled)
+    ///
+    /// In strict mode, selecting channels that do not exist on the device
+    /// returns `Error::InvalidChannels` instead of ignoring them.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.config.strict = strict;
+        self
+    }
+
+    /// Enable single-channel mode. (default: disabled)
+    ///
+    /// In single-channel mode, selecting more than one channel at the same time
+    /// returns `Error::MultipleChannels` without accessing the bus. This is
+    /// meant for systems where downstream devices with the same address must
+    /// never be connected simultaneously. Operations selecting several
+    /// channels at once, like `broadcast_write()`, are rejected as well.
+    pub fn single_channel(mut self, single_channel: bool) -> Self {
+        self.config.single_channel = single_channel;
+        self
+    }
+
+    /// Set the cache policy of the split parts. (default: `CachePolicy::Trust`)
+    pub fn cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.config.cache_policy = cache_policy;
+        self
+    }
+
+    /// Deselect all channels after each transfer done through a split part.
+    /// (default: disabled)
+    pub fn auto_deselect(mut self, auto_deselect: bool) -> Self {
+        self.config.auto_deselect = auto_deselect;
+        self
+    }
+
+    /// Recover automatically from a failed transfer done through a split part.
+    /// (default: disabled)
+    ///
+    /// When a transfer fails because of the switch or the bus, i.e. with
+    /// `Error::ChannelSelect`, `Error::VerificationFailed`, `Error::ExternalChange`
+    /// or a bus or arbitration loss error, the cached channel selection is
+    /// invalidated, the control register is read to check that the device
+    /// responds, the channel is selected again and the transfer is retried
+    /// before returning the error. Other errors, like a NACK from the device
+    /// behind the switch during acknowledge polling, are returned right away.
+    /// See [`retry_policy()`](#method.retry_policy).
+    /// This lets transient faults like connector bounce or brown-outs heal themselves.
+    pub fn auto_recover(mut self, auto_recover: bool) -> Self {
+        self.config.auto_recover = auto_recover;
+        self
+    }
+
+    /// Set the number of retries and the backoff between them for automatic
+    /// recovery. (default: a single retry without delay)
+    ///
+    /// The delays are done with the settle delay provider, so they have no
+    /// effect unless one is set with `with_settle_delay()`.
+    /// Only relevant with [`auto_recover()`](#method.auto_recover).
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.config.retry_policy = retry_policy;
+        self
+    }
+
+    /// Read back the control register after each write and compare the
+    /// selected channels. (default: disabled)
+    ///
+    /// A mismatch is handled according to the mismatch policy, by default
+    /// it is reported with `Error::VerificationFailed`.
+    /// This costs an additional read for each channel selection.
+    pub fn verify_writes(mut self, verify_writes: bool) -> Self {
+        self.config.verify_writes = verify_writes;
+        self
+    }
+
+    /// Read the control register every `interval` transfers done through the
+    /// split parts and compare it with the cached channel selection.
+    /// (default: `0`, disabled)
+    ///
+    /// This detects channel changes done by other masters or unexpected resets.
+    /// A divergence is handled according to the mismatch policy, by default
+    /// it is reported with `Error::ExternalChange`.
+    /// Only relevant with `CachePolicy::Trust`.
+    pub fn integrity_check_interval(mut self, interval: u16) -> Self {
+        self.config.integrity_check_interval = interval;
+        self
+    }
+
+    /// Set the behavior when the control register does not match the
+    /// expected channels. (default: `MismatchPolicy::Error`)
+    ///
+    /// See [`MismatchPolicy`].
+    pub fn mismatch_policy(mut self, mismatch_policy: MismatchPolicy) -> Self {
+        self.config.mismatch_policy = mismatch_policy;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+
+    #[test]
+    fn fixed_and_linear_backoff() {
+        let fixed = RetryPolicy::Fixed {
+            retries: 2,
+            delay_ns: 100,
+        };
+        assert_eq!(Some(100), fixed.backoff_ns(1, 0));
+        assert_eq!(Some(100), fixed.backoff_ns(2, 0));
+        assert_eq!(None, fixed.backoff_ns(3, 0));
+        let linear = RetryPolicy::Linear {
+            retries: 3,
+            step_ns: 100,
+        };
+        assert_eq!(Some(300), linear.backoff_ns(3, 0));
+        assert_eq!(None, linear.backoff_ns(4, 0));
+    }
+
+    #[test]
+    fn exponential_backoff_is_capped() {
+        let policy = RetryPolicy::Exponential {
+            retries: 40,
+            initial_ns: 1000,
+            max_ns: 5000,
+            jitter: false,
+        };
+        assert_eq!(Some(1000), policy.backoff_ns(1, 0));
+        assert_eq!(Some(2000), policy.backoff_ns(2, 0));
+        assert_eq!(Some(4000), policy.backoff_ns(3, 0));
+        assert_eq!(Some(5000), policy.backoff_ns(4, 0));
+        assert_eq!(Some(5000), policy.backoff_ns(40, 0));
+        assert_eq!(None, policy.backoff_ns(41, 0));
+    }
+
+    #[test]
+    fn jitter_stays_between_half_and_full_delay() {
+        let policy = RetryPolicy::Exponential {
+            retries: 1,
+            initial_ns: 1000,
+            max_ns: 1000,
+            jitter: true,
+        };
+        assert_eq!(Some(500), policy.backoff_ns(1, 0));
+        assert_eq!(Some(1000), policy.backoff_ns(1, 500));
+        for random in [1, 7, 12345, u32::MAX] {
+            let delay = policy.backoff_ns(1, random).unwrap();
+            assert!((500..=1000).contains(&delay));
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Control register encoding and decoding
+//!
+//! These are the functions used by the driver to build and interpret the
+//! control register value, exposed as `const fn`s without any I²C access so
+//! that host-side tools, fuzzers and bindings can reuse the same bit logic.
+//!
+//! The bits of the channels present on a device are given by its channel mask.
+//! On the devices with less than 8 channels, the interrupt state of each channel
+//! is reported in the upper nibble of the control register.
+//!
+//! ```
+//! use xca9548a::codec;
+//!
+//! const CONTROL: u8 = codec::encode_channels(0b0101, codec::XCA9543A_CHANNEL_MASK);
+//! assert_eq!(0b0001, CONTROL);
+//! assert_eq!(0b0100, codec::invalid_channels(0b0101, codec::XCA9543A_CHANNEL_MASK));
+//! assert_eq!(0b10, codec::decode_interrupts(0b0010_0001, codec::XCA9543A_CHANNEL_MASK));
+//! ```
+//!
+//! The channel selection logic is also available generically over the width
+//! of the channel mask through the [`Mask`] trait. The device drivers and
+//! `MuxGroup` select channels through it with `u8` masks. The `u16`
+//! implementation is meant for switches with more than 8 channels, none of
+//! which is supported yet.
+
+use crate::private;
+use core::ops::{BitAnd, BitOr, Not};
+
+/// Channel mask of the TCA9548A/PCA9548A (8 channels)
+pub const XCA9548A_CHANNEL_MASK: u8 = 0xff;
+/// Channel mask of the TCA9545A/PCA9545A (4 channels)
+pub const XCA9545A_CHANNEL_MASK: u8 = 0x0f;
+/// Channel mask of the TCA9543A/PCA9543A (2 channels)
+pub const XCA9543A_CHANNEL_MASK: u8 = 0x03;
+
+/// Control register bit of the channel with the given index, if it exists.
+pub const fn channel_bit(index: u8) -> Option<u8> {
+    if index < 8 {
+        Some(1 << index)
+    } else {
+        None
+    }
+}
+
+/// Control register value selecting `channels`.
+///
+/// Channels not present on the device are dropped.
+pub const fn encode_channels(channels: u8, channel_mask: u8) -> u8 {
+    channels & channel_mask
+}
+
+/// Channels in `channels` not present on the device.
+///
+/// Strict mode rejects a selection if this is not zero.
+pub const fn invalid_channels(channels: u8, channel_mask: u8) -> u8 {
+    channels & !channel_mask
+}
+
+/// Channels selected according to the control register value.
+pub const fn decode_channels(control: u8, channel_mask: u8) -> u8 {
+    control & channel_mask
+}
+
+/// Whether the channel with the given index is selected according to the
+/// control register value.
+pub const fn is_channel_enabled(control: u8, channel_mask: u8, index: u8) -> bool {
+    match channel_bit(index) {
+        Some(bit) => decode_channels(control, channel_mask) & bit != 0,
+        None => false,
+    }
+}
+
+/// Channels with their interrupt line asserted according to the control
+/// register value. Always zero for devices without interrupt bits.
+pub const fn decode_interrupts(control: u8, channel_mask: u8) -> u8 {
+    if has_interrupts(channel_mask) {
+        (control >> 4) & channel_mask
+    } else {
+        0
+    }
+}
+
+/// Control register bits that do not correspond to any channel or interrupt
+/// present on the device and should therefore always read as `0`.
+pub const fn reserved_bits(control: u8, channel_mask: u8) -> u8 {
+    let valid = if has_interrupts(channel_mask) {
+        channel_mask | (channel_mask << 4)
+    } else {
+        channel_mask
+    };
+    control & !valid
+}
+
+/// Whether a device with the given channel mask reports interrupts in the
+/// upper nibble of the control register.
+pub const fn has_interrupts(channel_mask: u8) -> bool {
+    channel_mask & 0xf0 == 0
+}
+
+/// Channel mask of a switch with up to 8 (`u8`) or 16 (`u16`) channels
+///
+/// Bit `n` corresponds to the channel with index `n`.
+///
+/// ```
+/// use xca9548a::codec::Mask;
+///
+/// let channel_mask: u16 = 0x0fff;
+/// assert_eq!(0x0001, 0x1001.encode(channel_mask));
+/// assert_eq!(0x1000, 0x1001.invalid(channel_mask));
+/// assert_eq!(vec![0, 12], 0x1001u16.indices().collect::<Vec<_>>());
+/// ```
+pub trait Mask:
+    Copy + Eq + BitAnd<Output = Self> + BitOr<Output = Self> + Not<Output = Self> + private::Sealed
+{
+    /// Maximum number of channels
+    const CHANNELS: u8;
+
+    /// Mask without any channel
+    const EMPTY: Self;
+
+    /// Bit of the channel with the given index, if it fits in the mask.
+    fn channel_bit(index: u8) -> Option<Self>;
+
+    /// Number of channels in the mask.
+    fn count(self) -> u32;
+
+    /// Mask selecting these channels on a device with the given channel mask.
+    ///
+    /// Channels not present on the device are dropped.
+    fn encode(self, channel_mask: Self) -> Self {
+        self & channel_mask
+    }
+
+    /// Channels in the mask not present on a device with the given channel mask.
+    fn invalid(self, channel_mask: Self) -> Self {
+        self & !channel_mask
+    }
+
+    /// Whether the channel with the given index is in the mask.
+    fn contains(self, index: u8) -> bool {
+        match Self::channel_bit(index) {
+            Some(bit) => self & bit != Self::EMPTY,
+            None => false,
+        }
+    }
+
+    /// Indices of the channels in the mask in ascending order.
+    fn indices(self) -> impl Iterator<Item = u8> {
+        (0..Self::CHANNELS).filter(move |index| self.contains(*index))
+    }
+}
+
+macro_rules! impl_mask {
+    ($($t:ty),+) => {
+        $(
+            impl private::Sealed for $t {}
+
+            impl Mask for $t {
+                const CHANNELS: u8 = <$t>::BITS as u8;
+                const EMPTY: Self = 0;
+
+                fn channel_bit(index: u8) -> Option<Self> {
+                    if index < Self::CHANNELS {
+                        Some(1 << index)
+                    } else {
+                        None
+                    }
+                }
+
+                fn count(self) -> u32 {
+                    self.count_ones()
+                }
+            }
+        )+
+    };
+}
+impl_mask!(u8, u16);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_interrupts_only_for_devices_with_interrupt_bits() {
+        assert_eq!(
+            0b0101,
+            decode_interrupts(0b0101_0000, XCA9545A_CHANNEL_MASK)
+        );
+        assert_eq!(0b01, decode_interrupts(0b0101_0000, XCA9543A_CHANNEL_MASK));
+        assert_eq!(0, decode_interrupts(0b0101_0000, XCA9548A_CHANNEL_MASK));
+    }
+
+    #[test]
+    fn finds_reserved_bits() {
+        assert_eq!(0b1100_1100, reserved_bits(0xff, XCA9543A_CHANNEL_MASK));
+        assert_eq!(0, reserved_bits(0xff, XCA9545A_CHANNEL_MASK));
+        assert_eq!(0, reserved_bits(0xff, XCA9548A_CHANNEL_MASK));
+    }
+
+    #[test]
+    fn checks_single_channel() {
+        assert!(is_channel_enabled(0b10, XCA9543A_CHANNEL_MASK, 1));
+        assert!(!is_channel_enabled(0b10, XCA9543A_CHANNEL_MASK, 0));
+        assert!(!is_channel_enabled(0xff, XCA9543A_CHANNEL_MASK, 2));
+        assert!(!is_channel_enabled(0xff, XCA9548A_CHANNEL_MASK, 8));
+        assert_eq!(None, channel_bit(8));
+    }
+
+    #[test]
+    fn generic_masks_match_control_register_encoding() {
+        for channels in [0x00, 0x05, 0xff] {
+            for channel_mask in [XCA9543A_CHANNEL_MASK, XCA9548A_CHANNEL_MASK] {
+                assert_eq!(
+                    encode_channels(channels, channel_mask),
+                    channels.encode(channel_mask)
+                );
+                assert_eq!(
+                    invalid_channels(channels, channel_mask),
+                    channels.invalid(channel_mask)
+                );
+            }
+        }
+        assert_eq!(Some(0x8000), u16::channel_bit(15));
+        assert_eq!(None, u16::channel_bit(16));
+        assert!(0x8000u16.contains(15));
+        assert_eq!(2, 0x8001u16.count());
+    }
+}
@@ -1,155 +1,1929 @@
+#[cfg(feature = "history")]
+use crate::history::{History, HistoryEntry};
+#[cfg(feature = "stats")]
+use crate::stats::Stats;
 use crate::{
-    parts::{Parts, Parts2, Parts4},
-    private, Error, SlaveAddr, Xca9543a, Xca9545a, Xca9548a, DEVICE_BASE_ADDRESS,
+    builder::{Builder, CachePolicy, Config, MismatchPolicy},
+    codec::{self, Mask},
+    lock::{ChannelGuard, Lockable},
+    parts::{I2cSlave, Parts, Parts2, Parts4, UpstreamBus},
+    private,
+    router::Router,
+    AccessContext, AccessKind, AddressError, Channel, ChannelBus, ChannelPath, ChannelResults,
+    DeviceInfo, DeviceState, DeviceType, Error, LastError, NoDelay, NoObserver, Observer, Parked,
+    RoutingTable, ScanConfig, ScanReport, SelfTestReport, SlaveAddr, Step, Watchdog, Xca9543a,
+    Xca9545a, Xca9548a, DEFAULT_ADDRESS,
 };
 use core::cell;
-use embedded_hal::i2c as ehal;
+use embedded_hal::{delay::DelayNs, digital::OutputPin, i2c as ehal};
+
+/// Initial state of the retry backoff jitter, combined with the device address.
+const JITTER_SEED: u32 = 0x9E37_79B9;
 
 #[doc(hidden)]
 #[derive(Debug)]
-pub struct Xca954xaData<I2C> {
+pub struct Xca954xaData<I2C, D = NoDelay, O = NoObserver> {
     /// The concrete I²C device implementation.
     pub(crate) i2c: I2C,
     /// The I²C device address.
     pub(crate) address: u8,
+    /// The channels present on the device.
+    pub(crate) channel_mask: u8,
     pub(crate) selected_channel_mask: u8,
+    /// Whether the channel selection cache may not match the device.
+    pub(crate) cache_dirty: bool,
+    pub(crate) config: Config,
+    /// Transfers done through the split parts since the last integrity check.
+    pub(crate) transfers_since_check: u16,
+    /// Pseudo-random state for the retry backoff jitter.
+    pub(crate) jitter_state: u32,
+    /// Channels whose warm-up hook has been called since construction or reset.
+    pub(crate) warmed_up: u8,
+    /// Whether the split parts must be invalidated once the device is released,
+    /// e.g. after a reset through the RESET pin.
+    pub(crate) parts_stale: bool,
+    /// Last error seen on each channel.
+    pub(crate) last_errors: [Option<LastError>; 8],
+    /// Number of errors recorded, used to order the last errors.
+    pub(crate) error_sequence: u32,
+    /// Delay used for settling after channel changes.
+    pub(crate) delay: D,
+    /// Hooks called around channel selections and transfers.
+    pub(crate) observer: O,
+    #[cfg(feature = "history")]
+    pub(crate) history: History,
+    #[cfg(feature = "stats")]
+    pub(crate) stats: Stats,
+}
+
+impl<I2C> Xca954xaData<I2C> {
+    pub(crate) fn new(i2c: I2C, address: u8, channel_mask: u8, config: Config) -> Self {
+        Xca954xaData {
+            i2c,
+            address,
+            channel_mask,
+            selected_channel_mask: 0,
+            // The state of the device is unknown until the control register is
+            // written or read for the first time.
+            cache_dirty: true,
+            config,
+            transfers_since_check: 0,
+            jitter_state: JITTER_SEED ^ u32::from(address),
+            warmed_up: 0,
+            parts_stale: false,
+            last_errors: [None; 8],
+            error_sequence: 0,
+            delay: NoDelay,
+            observer: NoObserver,
+            #[cfg(feature = "history")]
+            history: History::default(),
+            #[cfg(feature = "stats")]
+            stats: Stats::default(),
+        }
+    }
+
+    pub(crate) fn from_state(i2c: I2C, channel_mask: u8, state: DeviceState) -> Self {
+        Xca954xaData {
+            i2c,
+            address: state.address,
+            channel_mask,
+            selected_channel_mask: state.selected_channel_mask,
+            cache_dirty: state.cache_dirty,
+            config: state.config,
+            transfers_since_check: 0,
+            jitter_state: JITTER_SEED ^ u32::from(state.address),
+            warmed_up: 0,
+            parts_stale: false,
+            last_errors: [None; 8],
+            error_sequence: 0,
+            delay: NoDelay,
+            observer: NoObserver,
+            #[cfg(feature = "history")]
+            history: History::default(),
+            #[cfg(feature = "stats")]
+            stats: Stats::default(),
+        }
+    }
+}
+
+impl<I2C, D, O> Xca954xaData<I2C, D, O> {
+    /// Replace the settle delay and the observer with the ones returned by `f`.
+    fn map_hooks<D2, O2, R>(
+        self,
+        f: impl FnOnce(D, O) -> (D2, O2, R),
+    ) -> (Xca954xaData<I2C, D2, O2>, R) {
+        let (delay, observer, result) = f(self.delay, self.observer);
+        let data = Xca954xaData {
+            i2c: self.i2c,
+            address: self.address,
+            channel_mask: self.channel_mask,
+            selected_channel_mask: self.selected_channel_mask,
+            cache_dirty: self.cache_dirty,
+            config: self.config,
+            transfers_since_check: self.transfers_since_check,
+            jitter_state: self.jitter_state,
+            warmed_up: self.warmed_up,
+            parts_stale: self.parts_stale,
+            last_errors: self.last_errors,
+            error_sequence: self.error_sequence,
+            delay,
+            observer,
+            #[cfg(feature = "history")]
+            history: self.history,
+            #[cfg(feature = "stats")]
+            stats: self.stats,
+        };
+        (data, result)
+    }
+
+    /// Replace the settle delay, returning the previous one.
+    ///
+    /// `settle_ns` is used for the channels without a settle time of their own.
+    pub(crate) fn with_delay<D2>(self, delay: D2, settle_ns: u32) -> (Xca954xaData<I2C, D2, O>, D) {
+        let (mut data, previous) = self.map_hooks(|previous, observer| (delay, observer, previous));
+        for ns in data.config.settle_ns.iter_mut().filter(|ns| **ns == 0) {
+            *ns = settle_ns;
+        }
+        (data, previous)
+    }
+
+    /// Replace the observer, returning the previous one.
+    pub(crate) fn with_observer<O2>(self, observer: O2) -> (Xca954xaData<I2C, D, O2>, O) {
+        self.map_hooks(|delay, previous| (delay, observer, previous))
+    }
+
+    /// Update the channel selection cache, marking it as matching the device.
+    pub(crate) fn set_cached_channels(&mut self, channels: u8) {
+        self.selected_channel_mask = channels;
+        self.cache_dirty = false;
+    }
+
+    pub(crate) fn state(&self) -> DeviceState {
+        DeviceState {
+            address: self.address,
+            selected_channel_mask: self.selected_channel_mask,
+            cache_dirty: self.cache_dirty,
+            config: self.config,
+        }
+    }
+
+    pub(crate) fn into_state(self) -> (I2C, DeviceState) {
+        let state = self.state();
+        (self.i2c, state)
+    }
+}
+
+impl<I2C, D, O, E> Xca954xaData<I2C, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    /// Select channels from the device itself.
+    ///
+    /// Channels not present on the device are ignored or rejected in strict mode.
+    /// Unless `force` is set, the write is skipped if the channels are already
+    /// selected according to the cache and the cache policy.
+    pub(crate) fn select_device_channels(
+        &mut self,
+        channels: u8,
+        force: bool,
+    ) -> Result<(), Error<E>> {
+        // Not generic over the bus type so that it is not duplicated for each of them.
+        // Returns the channels to write, if any, or the invalid channels.
+        fn inner<M: Mask>(
+            config: &Config,
+            channel_mask: M,
+            selected: Option<M>,
+            channels: M,
+            force: bool,
+        ) -> Result<Option<M>, M> {
+            if config.strict && channels.invalid(channel_mask) != M::EMPTY {
+                return Err(channels);
+            }
+            let channels = channels.encode(channel_mask);
+            if !force && config.cache_policy == CachePolicy::Trust && selected == Some(channels) {
+                Ok(None)
+            } else {
+                Ok(Some(channels))
+            }
+        }
+
+        let selected = (!self.cache_dirty).then_some(self.selected_channel_mask);
+        match inner(&self.config, self.channel_mask, selected, channels, force) {
+            Ok(Some(channels)) => self.select_channels(channels),
+            Ok(None) => Ok(()),
+            Err(channels) => Err(Error::InvalidChannels(channels)),
+        }
+    }
+
+    /// Channels currently selected, reading the control register only if
+    /// the cache may not match the device.
+    pub(crate) fn current_channels(&mut self) -> Result<u8, Error<E>> {
+        if self.cache_dirty {
+            let value = self.read_control_register()?;
+            self.set_cached_channels(codec::decode_channels(value, self.channel_mask));
+        }
+        Ok(self.selected_channel_mask)
+    }
+
+    /// Transfer data through the split part of `channel`, selecting it beforehand if necessary.
+    ///
+    /// In the common case of the channel being already selected, only the cache is compared.
+    /// With automatic recovery, a transfer failed because of the switch or the
+    /// bus is retried according to the retry policy after recovering the device.
+    /// Errors of the device behind the switch, like a NACK, are returned as is.
+    #[inline]
+    pub(crate) fn part_transfer(
+        &mut self,
+        channel: Channel,
+        kind: AccessKind,
+        address: u16,
+        mut transfer: impl FnMut(&mut I2C) -> Result<(), E>,
+    ) -> Result<(), Error<E>> {
+        let mut result = self.select_and_transfer(channel, kind, address, &mut transfer);
+        let mut attempt = 0;
+        while matches!(&result, Err(e) if Self::needs_recovery(e))
+            && self.config.auto_recover
+            && attempt < u8::MAX
+        {
+            attempt += 1;
+            if !self.back_off(attempt) {
+                break;
+            }
+            if self.recover(channel).is_ok() {
+                result = self.transfer(kind, address, &mut transfer);
+            }
+        }
+        if self.config.auto_deselect {
+            let deselect = self.select_channels(0);
+            result.and(deselect)
+        } else {
+            result
+        }
+    }
+
+    #[inline]
+    fn select_and_transfer(
+        &mut self,
+        channel: Channel,
+        kind: AccessKind,
+        address: u16,
+        transfer: impl FnOnce(&mut I2C) -> Result<(), E>,
+    ) -> Result<(), Error<E>> {
+        self.select_part(channel)?;
+        self.transfer(kind, address, transfer)
+    }
+
+    /// Select `channel` for an access through its split part, unless the cache
+    /// shows it is already selected.
+    #[inline]
+    pub(crate) fn select_part(&mut self, channel: Channel) -> Result<(), Error<E>> {
+        if !self.config.trusts_cache()
+            || self.cache_dirty
+            || self.selected_channel_mask != channel.mask()
+        {
+            self.prepare_part_transfer(channel)?;
+        }
+        Ok(())
+    }
+
+    /// Deselect `channel` if it is selected, leaving other channels selected.
+    ///
+    /// The control register is read beforehand only if the cache may not
+    /// match the device.
+    pub(crate) fn release_part(&mut self, channel: Channel) -> Result<(), Error<E>> {
+        let selected = self.current_channels()?;
+        if selected & channel.mask() != 0 {
+            self.select_channels(selected & !channel.mask())?;
+        }
+        Ok(())
+    }
+
+    /// Whether `error` was caused by the switch or the bus rather than by the
+    /// device behind it, so that recovering the switch may help.
+    fn needs_recovery(error: &Error<E>) -> bool {
+        match error {
+            Error::ChannelSelect(_)
+            | Error::VerificationFailed { .. }
+            | Error::ExternalChange { .. } => true,
+            Error::I2C(_) => error.is_bus_error() || error.is_arbitration_loss(),
+            _ => false,
+        }
+    }
+
+    /// Wait before the retry number `attempt` according to the retry policy.
+    ///
+    /// Returns whether the retry should be done.
+    #[inline(never)]
+    fn back_off(&mut self, attempt: u8) -> bool {
+        // xorshift32
+        let mut random = self.jitter_state;
+        random ^= random << 13;
+        random ^= random >> 17;
+        random ^= random << 5;
+        self.jitter_state = random;
+        match self.config.retry_policy.backoff_ns(attempt, random) {
+            Some(0) => true,
+            Some(delay_ns) => {
+                self.delay.delay_ns(delay_ns);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Invalidate the cache, probe the device and select `channel` again
+    /// after a failure.
+    ///
+    /// The split parts stay valid, so that the drivers using them keep
+    /// working after the recovery.
+    ///
+    /// Kept out of line so that it is not duplicated for each kind of transfer.
+    #[inline(never)]
+    fn recover(&mut self, channel: Channel) -> Result<(), Error<E>> {
+        self.cache_dirty = true;
+        self.current_channels()?;
+        self.select_channels(channel.mask())
+    }
+
+    /// Transfer data on the upstream bus, deselecting all channels beforehand
+    /// unless the cache shows that no channel is selected.
+    ///
+    /// The cache is only trusted after the control register has been written or
+    /// read, so all channels are always deselected on a new driver or after
+    /// a failed selection.
+    pub(crate) fn upstream_transfer(
+        &mut self,
+        kind: AccessKind,
+        address: u16,
+        transfer: impl FnOnce(&mut I2C) -> Result<(), E>,
+    ) -> Result<(), Error<E>> {
+        if self.config.cache_policy == CachePolicy::AlwaysSelect
+            || self.cache_dirty
+            || self.selected_channel_mask != 0
+        {
+            self.select_channels(0)?;
+        }
+        self.transfer(kind, address, transfer)
+    }
+
+    /// Check the integrity and select `channel` as needed before a part transfer.
+    ///
+    /// Kept out of line so that it is not duplicated for each kind of transfer.
+    #[inline(never)]
+    fn prepare_part_transfer(&mut self, channel: Channel) -> Result<(), Error<E>> {
+        if self.config.cache_policy == CachePolicy::Trust && !self.cache_dirty {
+            self.check_integrity()?;
+        }
+        if self.config.cache_policy == CachePolicy::AlwaysSelect
+            || self.cache_dirty
+            || self.selected_channel_mask != channel.mask()
+        {
+            self.select_channels(channel.mask())?;
+        }
+        Ok(())
+    }
+
+    /// Compare the cached channel selection with the control register every
+    /// `integrity_check_interval` transfers.
+    ///
+    /// On divergence the cache is updated to the hardware state.
+    fn check_integrity(&mut self) -> Result<(), Error<E>> {
+        // Not generic so that it is not duplicated for each bus type.
+        fn inner(transfers_since_check: &mut u16, interval: u16) -> bool {
+            if interval == 0 {
+                return false;
+            }
+            *transfers_since_check = transfers_since_check.saturating_add(1);
+            if *transfers_since_check < interval {
+                return false;
+            }
+            *transfers_since_check = 0;
+            true
+        }
+
+        let interval = self.config.integrity_check_interval;
+        if !inner(&mut self.transfers_since_check, interval) {
+            return Ok(());
+        }
+        let cached = self.selected_channel_mask & self.channel_mask;
+        let actual = self.read_control_register()? & self.channel_mask;
+        if actual == cached {
+            return Ok(());
+        }
+        match self.config.mismatch_policy {
+            MismatchPolicy::Error => {
+                self.set_cached_channels(actual);
+                Err(Error::ExternalChange { cached, actual })
+            }
+            MismatchPolicy::Rewrite => self.select_channels(cached),
+            MismatchPolicy::Notify => {
+                self.observer.mismatch(cached, actual);
+                self.set_cached_channels(actual);
+                Ok(())
+            }
+        }
+    }
 }
 
-impl<I2C, E> SelectChannels for Xca954xaData<I2C>
-where
-    I2C: ehal::I2c<Error = E>,
-    E: core::fmt::Debug,
-{
-    type Error = Error<E>;
-    fn select_channels(&mut self, channels: u8) -> Result<(), Self::Error> {
-        self.i2c
-            .write(self.address, &[channels])
-            .map_err(Error::I2C)?;
-        self.selected_channel_mask = channels;
-        Ok(())
-    }
-}
+impl<I2C, D, O, E> Xca954xaData<I2C, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    O: Observer,
+    E: ehal::Error,
+{
+    /// Transfer data on the bus with the currently selected channels.
+    pub(crate) fn transfer(
+        &mut self,
+        kind: AccessKind,
+        address: u16,
+        transfer: impl FnOnce(&mut I2C) -> Result<(), E>,
+    ) -> Result<(), Error<E>> {
+        let channels = self.selected_channel_mask;
+        self.observer.before_transfer(channels, address, kind);
+        let result = transfer(&mut self.i2c).map_err(Error::I2C);
+        let kind_result = result.as_ref().map_err(ehal::Error::kind).copied();
+        self.observer
+            .after_transfer(channels, address, kind, kind_result);
+        self.record(channels, address, kind, &result);
+        result
+    }
+
+    /// Record an operation in the last errors and, if enabled, in the history
+    /// and statistics.
+    #[inline]
+    #[cfg_attr(not(feature = "history"), allow(unused_variables))]
+    fn record(
+        &mut self,
+        channels: u8,
+        address: u16,
+        kind: AccessKind,
+        result: &Result<(), Error<E>>,
+    ) {
+        if let Err(e) = result {
+            self.error_sequence = self.error_sequence.wrapping_add(1);
+            let last_error = LastError {
+                kind: ehal::Error::kind(e),
+                sequence: self.error_sequence,
+            };
+            for (i, slot) in self.last_errors.iter_mut().enumerate() {
+                if channels & (1 << i) != 0 {
+                    *slot = Some(last_error);
+                }
+            }
+        }
+        #[cfg(feature = "history")]
+        self.history.push(HistoryEntry {
+            channels,
+            address,
+            kind,
+            error: result.as_ref().err().map(ehal::Error::kind),
+        });
+        #[cfg(feature = "stats")]
+        self.stats.record(channels, kind, result.is_err());
+    }
+
+    /// Read the raw control register.
+    pub(crate) fn read_control_register(&mut self) -> Result<u8, Error<E>> {
+        let mut data = [0];
+        self.i2c.read(self.address, &mut data).map_err(Error::I2C)?;
+        Ok(data[0])
+    }
+}
+
+impl<I2C, D, O, E> SelectChannels for Xca954xaData<I2C, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    type Error = Error<E>;
+    fn select_channels(&mut self, channels: u8) -> Result<(), Self::Error> {
+        if self.config.single_channel && channels.encode(self.channel_mask).count() > 1 {
+            return Err(Error::MultipleChannels(channels));
+        }
+        self.observer.before_select(channels);
+        let result = self.write_channels(channels);
+        let kind_result = result.as_ref().map_err(ehal::Error::kind).copied();
+        self.observer.after_select(channels, kind_result);
+        let address = u16::from(self.address);
+        self.record(channels, address, AccessKind::SelectChannels, &result);
+        result?;
+        self.warm_up(channels)
+    }
+}
+
+impl<I2C, D, O, E> Xca954xaData<I2C, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    /// Call the observer warm-up hook for the selected `channels` used for the first time.
+    ///
+    /// Channels whose warm-up fails are tried again on their next selection.
+    fn warm_up(&mut self, channels: u8) -> Result<(), Error<E>> {
+        let cold = channels & self.channel_mask & !self.warmed_up;
+        for channel in Channel::in_mask(cold) {
+            self.observer
+                .warm_up(channel, &mut self.i2c)
+                .map_err(Error::I2C)?;
+            self.warmed_up |= channel.mask();
+        }
+        Ok(())
+    }
+
+    /// Write the channel selection, wait for it to settle and verify it if configured.
+    fn write_channels(&mut self, channels: u8) -> Result<(), Error<E>> {
+        let mut rewritten = false;
+        loop {
+            if let Err(e) = self.i2c.write(self.address, &[channels]) {
+                // The write may have reached the device anyway.
+                self.cache_dirty = true;
+                return Err(Error::ChannelSelect(e));
+            }
+            let settle_ns = self.config.settle_time(channels);
+            if settle_ns != 0 {
+                self.delay.delay_ns(settle_ns);
+            }
+            if self.config.verify_writes {
+                let expected = channels & self.channel_mask;
+                let actual = self.read_control_register()? & self.channel_mask;
+                if actual != expected {
+                    match self.config.mismatch_policy {
+                        MismatchPolicy::Rewrite if !rewritten => {
+                            rewritten = true;
+                            continue;
+                        }
+                        MismatchPolicy::Notify => self.observer.mismatch(expected, actual),
+                        MismatchPolicy::Error | MismatchPolicy::Rewrite => {
+                            self.set_cached_channels(actual);
+                            return Err(Error::VerificationFailed { expected, actual });
+                        }
+                    }
+                    self.set_cached_channels(actual);
+                    return Ok(());
+                }
+            }
+            self.set_cached_channels(channels);
+            return Ok(());
+        }
+    }
+}
+
+#[doc(hidden)]
+pub trait DoOnAcquired<I2C>: private::Sealed {
+    type Delay;
+    type Observer;
+    fn address(&self) -> u8;
+    fn channel_mask(&self) -> u8;
+    fn generation(&self) -> u32;
+    fn do_on_acquired<R, E: ehal::Error>(
+        &self,
+        access: AccessContext,
+        f: impl FnOnce(
+            cell::RefMut<Xca954xaData<I2C, Self::Delay, Self::Observer>>,
+        ) -> Result<R, Error<E>>,
+    ) -> Result<R, Error<E>>;
+}
+
+#[doc(hidden)]
+pub trait SelectChannels: private::Sealed {
+    type Error;
+    fn select_channels(&mut self, mask: u8) -> Result<(), Self::Error>;
+}
+
+impl<E> ehal::Error for Error<E>
+where
+    E: ehal::Error,
+{
+    fn kind(&self) -> ehal::ErrorKind {
+        match self {
+            Error::I2C(e) | Error::ChannelSelect(e) => e.kind(),
+            Error::CouldNotAcquireDevice(_)
+            | Error::InvalidAddress(_)
+            | Error::InvalidChannels(_)
+            | Error::MultipleChannels(_)
+            | Error::NotPowerOnDefault(_)
+            | Error::Pin
+            | Error::VerificationFailed { .. }
+            | Error::ExternalChange { .. }
+            | Error::InvalidPath(_)
+            | Error::StaleHandle(_)
+            | Error::AmbiguousAddress(_)
+            | Error::NoSuchDevice(_) => ehal::ErrorKind::Other,
+        }
+    }
+}
+
+impl<E> Error<E>
+where
+    E: ehal::Error,
+{
+    /// Whether the error was caused by a missing acknowledge (NACK)
+    /// of the address or the data.
+    pub fn is_nack(&self) -> bool {
+        matches!(ehal::Error::kind(self), ehal::ErrorKind::NoAcknowledge(_))
+    }
+
+    /// Whether the error was caused by a bus arbitration loss.
+    pub fn is_arbitration_loss(&self) -> bool {
+        ehal::Error::kind(self) == ehal::ErrorKind::ArbitrationLoss
+    }
+
+    /// Whether the error was caused by a bus error (e.g. misplaced START or STOP condition).
+    pub fn is_bus_error(&self) -> bool {
+        ehal::Error::kind(self) == ehal::ErrorKind::Bus
+    }
+}
+
+macro_rules! i2c_traits {
+    ( $name:ident, $mask:expr ) => {
+        impl<I2C, D, O> DoOnAcquired<I2C> for $name<I2C, D, O> {
+            type Delay = D;
+            type Observer = O;
+
+            fn address(&self) -> u8 {
+                self.address
+            }
+
+            fn channel_mask(&self) -> u8 {
+                $mask
+            }
+
+            fn generation(&self) -> u32 {
+                self.generation.get()
+            }
+
+            #[inline]
+            fn do_on_acquired<R, E: ehal::Error>(
+                &self,
+                access: AccessContext,
+                f: impl FnOnce(cell::RefMut<Xca954xaData<I2C, D, O>>) -> Result<R, Error<E>>,
+            ) -> Result<R, Error<E>> {
+                let result = f(self.acquire(access)?);
+                self.release();
+                result
+            }
+        }
+
+        impl<I2C, D, O> Lockable for $name<I2C, D, O> {
+            fn generation(&self) -> u32 {
+                self.generation.get()
+            }
+
+            fn release(&self) {
+                self.invalidate_stale_parts();
+                self.run_deferred_read();
+            }
+        }
+
+        impl<I2C, D, O> $name<I2C, D, O> {
+            /// Borrow the device data according to the acquire policy.
+            fn acquire<E: ehal::Error>(
+                &self,
+                access: AccessContext,
+            ) -> Result<cell::RefMut<'_, Xca954xaData<I2C, D, O>>, Error<E>> {
+                self.data.try_borrow_mut().map_err(|_| {
+                    #[cfg(feature = "stats")]
+                    self.borrow_failures
+                        .set(self.borrow_failures.get().wrapping_add(1));
+                    Error::CouldNotAcquireDevice(access)
+                })
+            }
+
+            /// Read the device data without bus access.
+            ///
+            /// Fails with `Error::CouldNotAcquireDevice` while the device is in
+            /// use, e.g. through a `ChannelGuard`.
+            fn inspect<R, E: ehal::Error>(
+                &self,
+                f: impl FnOnce(&Xca954xaData<I2C, D, O>) -> R,
+            ) -> Result<R, Error<E>> {
+                match self.data.try_borrow() {
+                    Ok(dev) => Ok(f(&dev)),
+                    Err(_) => {
+                        #[cfg(feature = "stats")]
+                        self.borrow_failures
+                            .set(self.borrow_failures.get().wrapping_add(1));
+                        Err(Error::CouldNotAcquireDevice(AccessContext::device(
+                            AccessKind::Inspect,
+                        )))
+                    }
+                }
+            }
+
+            /// Invalidate the split parts if the last access asked for it.
+            fn invalidate_stale_parts(&self) {
+                // still in use by an outer access otherwise, which will do it
+                if let Ok(mut dev) = self.data.try_borrow_mut() {
+                    if core::mem::take(&mut dev.parts_stale) {
+                        self.generation.set(self.generation.get().wrapping_add(1));
+                    }
+                }
+            }
+
+            /// Do the control register read queued while the device was in use, if any.
+            fn run_deferred_read(&self) {
+                if let Some(read) = self.deferred_read.take() {
+                    match self.data.try_borrow_mut() {
+                        Ok(mut dev) => self.deferred_status.set(Some(read(&mut dev))),
+                        // still in use by an outer access, which will do it
+                        Err(_) => self.deferred_read.set(Some(read)),
+                    }
+                }
+            }
+        }
+
+        impl<I2C, D, O, E> ehal::ErrorType for $name<I2C, D, O>
+        where
+            I2C: ehal::I2c<Error = E>,
+            E: ehal::Error,
+        {
+            type Error = Error<E>;
+        }
+
+        impl<I2C, D, O, E> ehal::ErrorType for &$name<I2C, D, O>
+        where
+            I2C: ehal::I2c<Error = E>,
+            E: ehal::Error,
+        {
+            type Error = Error<E>;
+        }
+
+        /// The device can also be used through a shared reference thanks to its
+        /// interior mutability. Concurrent accesses return `Error::CouldNotAcquireDevice`.
+        impl<I2C, D, O, E> ehal::I2c for &$name<I2C, D, O>
+        where
+            I2C: ehal::I2c<Error = E>,
+            O: Observer,
+            E: ehal::Error,
+        {
+            fn transaction(
+                &mut self,
+                address: u8,
+                operations: &mut [ehal::Operation<'_>],
+            ) -> Result<(), Error<E>> {
+                self.do_on_acquired(AccessContext::device(AccessKind::Transaction), |mut dev| {
+                    dev.transfer(AccessKind::Transaction, u16::from(address), |i2c| {
+                        i2c.transaction(address, operations)
+                    })
+                })
+            }
+
+            fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+                self.do_on_acquired(AccessContext::device(AccessKind::Read), |mut dev| {
+                    dev.transfer(AccessKind::Read, u16::from(address), |i2c| {
+                        i2c.read(address, read)
+                    })
+                })
+            }
+
+            fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+                self.do_on_acquired(AccessContext::device(AccessKind::Write), |mut dev| {
+                    dev.transfer(AccessKind::Write, u16::from(address), |i2c| {
+                        i2c.write(address, write)
+                    })
+                })
+            }
+
+            fn write_read(
+                &mut self,
+                address: u8,
+                write: &[u8],
+                read: &mut [u8],
+            ) -> Result<(), Self::Error> {
+                self.do_on_acquired(AccessContext::device(AccessKind::WriteRead), |mut dev| {
+                    dev.transfer(AccessKind::WriteRead, u16::from(address), |i2c| {
+                        i2c.write_read(address, write, read)
+                    })
+                })
+            }
+        }
+
+        #[cfg(feature = "ten-bit-address")]
+        impl<I2C, D, O, E> ehal::I2c<ehal::TenBitAddress> for &$name<I2C, D, O>
+        where
+            I2C: ehal::I2c<Error = E> + ehal::I2c<ehal::TenBitAddress>,
+            O: Observer,
+            E: ehal::Error,
+        {
+            fn transaction(
+                &mut self,
+                address: ehal::TenBitAddress,
+                operations: &mut [ehal::Operation<'_>],
+            ) -> Result<(), Error<E>> {
+                self.do_on_acquired(AccessContext::device(AccessKind::Transaction), |mut dev| {
+                    dev.transfer(AccessKind::Transaction, address, |i2c| {
+                        ehal::I2c::<ehal::TenBitAddress>::transaction(i2c, address, operations)
+                    })
+                })
+            }
+        }
+
+        impl<I2C, D, O, E> ehal::I2c for $name<I2C, D, O>
+        where
+            I2C: ehal::I2c<Error = E>,
+            O: Observer,
+            E: ehal::Error,
+        {
+            fn transaction(
+                &mut self,
+                address: u8,
+                operations: &mut [ehal::Operation<'_>],
+            ) -> Result<(), Error<E>> {
+                ehal::I2c::transaction(&mut &*self, address, operations)
+            }
+
+            fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+                ehal::I2c::read(&mut &*self, address, read)
+            }
+
+            fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+                ehal::I2c::write(&mut &*self, address, write)
+            }
+
+            fn write_read(
+                &mut self,
+                address: u8,
+                write: &[u8],
+                read: &mut [u8],
+            ) -> Result<(), Self::Error> {
+                ehal::I2c::write_read(&mut &*self, address, write, read)
+            }
+        }
+
+        #[cfg(feature = "ten-bit-address")]
+        impl<I2C, D, O, E> ehal::I2c<ehal::TenBitAddress> for $name<I2C, D, O>
+        where
+            I2C: ehal::I2c<Error = E> + ehal::I2c<ehal::TenBitAddress>,
+            O: Observer,
+            E: ehal::Error,
+        {
+            fn transaction(
+                &mut self,
+                address: ehal::TenBitAddress,
+                operations: &mut [ehal::Operation<'_>],
+            ) -> Result<(), Error<E>> {
+                ehal::I2c::<ehal::TenBitAddress>::transaction(&mut &*self, address, operations)
+            }
+        }
+    };
+}
+
+macro_rules! impl_device {
+    ( $name:ident, $parts:ident, $mask:expr, $part_name:expr ) => {
+        impl<I2C, D, O> DeviceType for $name<I2C, D, O> {
+            const CHANNELS: u8 = ($mask as u8).count_ones() as u8;
+            const CHANNEL_MASK: u8 = $mask;
+            const NAME: &'static str = $part_name;
+            const HAS_INTERRUPTS: bool = codec::has_interrupts($mask);
+        }
+
+        impl<I2C> $name<I2C> {
+            /// Create new instance of the device
+            pub fn new(i2c: I2C, address: SlaveAddr) -> Self {
+                Self::new_with_base_address(i2c, DEFAULT_ADDRESS, address)
+            }
+
+            /// Create new instance of the device using a custom base address.
+            ///
+            /// This is useful for compatible parts and clones whose base address
+            /// differs from `0b111_0000`. The A2, A1 and A0 bits of
+            /// `SlaveAddr::Alternative` are combined with the base address.
+            pub fn new_with_base_address(i2c: I2C, base_address: u8, address: SlaveAddr) -> Self {
+                let data =
+                    Xca954xaData::new(i2c, address.address(base_address), $mask, Config::default());
+                $name::from_data(data)
+            }
+
+            /// Create new instance of the device validating the address.
+            ///
+            /// Addresses that do not fit in 7 bits or are reserved by the I²C
+            /// specification are rejected since the device could never acknowledge them.
+            /// If the address is invalid, the error is returned together with
+            /// the I²C bus instance.
+            pub fn try_new(i2c: I2C, address: SlaveAddr) -> Result<Self, (I2C, AddressError)> {
+                Self::try_new_with_base_address(i2c, DEFAULT_ADDRESS, address)
+            }
+
+            /// Create new instance of the device using a custom base address
+            /// and validating the resulting address.
+            ///
+            /// See [`new_with_base_address()`](#method.new_with_base_address) and
+            /// [`try_new()`](#method.try_new).
+            pub fn try_new_with_base_address(
+                i2c: I2C,
+                base_address: u8,
+                address: SlaveAddr,
+            ) -> Result<Self, (I2C, AddressError)> {
+                match address.validated_address(base_address) {
+                    Ok(_) => Ok(Self::new_with_base_address(i2c, base_address, address)),
+                    Err(e) => Err((i2c, e)),
+                }
+            }
+
+            /// Create a builder for configuring the device.
+            ///
+            /// See [`Builder`](struct.Builder.html).
+            pub fn builder() -> Builder<Self> {
+                Builder::new()
+            }
+
+            /// Rebuild the driver instance from an I²C bus instance and
+            /// a driver state returned by [`destroy_with_state()`](#method.destroy_with_state).
+            ///
+            /// The channel selection cache is assumed to still match the device,
+            /// unless it was already known not to when the state was taken.
+            /// Use [`invalidate_cache()`](#method.invalidate_cache) afterwards if the
+            /// device may have been reset in the meantime.
+            pub fn from_parts(i2c: I2C, state: DeviceState) -> Self {
+                $name::from_data(Xca954xaData::from_state(i2c, $mask, state))
+            }
+        }
+
+        impl<I2C, O> $name<I2C, NoDelay, O> {
+            /// Use `delay` to wait `settle_ns` nanoseconds after each channel selection.
+            ///
+            /// This gives the downstream segments (e.g. long cables or level shifters)
+            /// some time to settle after the switch closes. Deselecting all channels
+            /// is not delayed.
+            /// The settle time can be adjusted for each channel with
+            /// [`set_settle_time()`](#method.set_settle_time). Channels whose settle
+            /// time was already set keep it and `settle_ns` applies to the others.
+            pub fn with_settle_delay<D: DelayNs>(
+                self,
+                delay: D,
+                settle_ns: u32,
+            ) -> $name<I2C, D, O> {
+                let (data, _) = self.data.into_inner().with_delay(delay, settle_ns);
+                $name::from_data(data)
+            }
+        }
+
+        impl<I2C, D> $name<I2C, D, NoObserver> {
+            /// Register an observer whose hooks are called around every channel
+            /// selection and transfer.
+            ///
+            /// See [`Observer`].
+            pub fn with_observer<O: Observer>(self, observer: O) -> $name<I2C, D, O> {
+                let (data, _) = self.data.into_inner().with_observer(observer);
+                $name::from_data(data)
+            }
+        }
+
+        impl<I2C, D, O> $name<I2C, D, O> {
+            fn from_data(data: Xca954xaData<I2C, D, O>) -> Self {
+                $name {
+                    address: data.address,
+                    data: cell::RefCell::new(data),
+                    deferred_read: cell::Cell::new(None),
+                    deferred_status: cell::Cell::new(None),
+                    generation: cell::Cell::new(0),
+                    #[cfg(feature = "stats")]
+                    borrow_failures: cell::Cell::new(0),
+                }
+            }
+
+            /// Remove the settle delay, returning the driver instance without it
+            /// and the delay instance.
+            ///
+            /// The settle times of the channels are kept for a later settle delay.
+            pub fn release_settle_delay(self) -> ($name<I2C, NoDelay, O>, D) {
+                let (data, delay) = self.data.into_inner().with_delay(NoDelay, 0);
+                ($name::from_data(data), delay)
+            }
+
+            /// Remove the observer, returning the driver instance without it
+            /// and the observer instance.
+            pub fn release_observer(self) -> ($name<I2C, D>, O) {
+                let (data, observer) = self.data.into_inner().with_observer(NoObserver);
+                ($name::from_data(data), observer)
+            }
+
+            /// Access the observer, for example to read the metrics it collected.
+            pub fn observer_mut(&mut self) -> &mut O {
+                &mut self.data.get_mut().observer
+            }
+
+            /// Set the settle time of `channel` in nanoseconds.
+            ///
+            /// When several channels are selected at once, the longest settle time applies.
+            /// This only has an effect with a settle delay.
+            /// See [`with_settle_delay()`](#method.with_settle_delay).
+            pub fn set_settle_time(&mut self, channel: Channel, settle_ns: u32) {
+                self.data.get_mut().config.settle_ns[usize::from(channel.index())] = settle_ns;
+            }
+
+            /// Clear the history of operations.
+            ///
+            /// Available with the `history` feature.
+            #[cfg(feature = "history")]
+            pub fn clear_history(&mut self) {
+                self.data.get_mut().history = History::default();
+            }
+
+            /// Reset all the counters to zero.
+            ///
+            /// Available with the `stats` feature.
+            #[cfg(feature = "stats")]
+            pub fn reset_stats(&mut self) {
+                self.data.get_mut().stats = Stats::default();
+                self.borrow_failures.set(0);
+            }
+
+            /// Address and properties of the device, e.g. for identifying the
+            /// chip an error came from in systems with several multiplexers.
+            pub fn device_info(&self) -> DeviceInfo {
+                DeviceInfo {
+                    address: self.address,
+                    name: <Self as DeviceType>::NAME,
+                    channels: <Self as DeviceType>::CHANNELS,
+                    has_interrupts: <Self as DeviceType>::HAS_INTERRUPTS,
+                }
+            }
+
+            /// Destroy driver instance, return I²C bus instance.
+            pub fn destroy(self) -> I2C {
+                self.data.into_inner().i2c
+            }
+
+            /// Destroy driver instance, return I²C bus instance and the driver state.
+            ///
+            /// This allows temporarily releasing the I²C bus and rebuilding the
+            /// driver afterwards with [`from_parts()`](#method.from_parts) without
+            /// losing the channel selection cache.
+            pub fn destroy_with_state(self) -> (I2C, DeviceState) {
+                self.data.into_inner().into_state()
+            }
+
+            /// Get temporary direct access to the I²C bus.
+            ///
+            /// This allows one-off operations that cannot be expressed through the
+            /// driver, for example firmware update protocols.
+            /// Since the closure may change the channel selection, the cache is
+            /// marked as dirty and the channels are selected again before the next transfer.
+            pub fn with_bus<R>(&mut self, f: impl FnOnce(&mut I2C) -> R) -> R {
+                let data = self.data.get_mut();
+                data.cache_dirty = true;
+                f(&mut data.i2c)
+            }
+
+            /// Forget which channels have been warmed up, so that the
+            /// [`Observer::warm_up()`] hook is called again the next time each
+            /// channel is selected, e.g. after the device or the downstream
+            /// segments have been power-cycled.
+            pub fn reset_warm_up(&mut self) {
+                self.data.get_mut().warmed_up = 0;
+            }
+
+            /// Forget the last errors of all channels.
+            pub fn clear_last_errors(&mut self) {
+                self.data.get_mut().last_errors = [None; 8];
+            }
+
+            /// Mark the cached channel selection as not matching the device, so
+            /// that the channels are selected again before the next transfer.
+            ///
+            /// Use this when the device may have been reset or written by someone
+            /// else, e.g. after a bus recovery procedure.
+            pub fn invalidate_cache(&mut self) {
+                self.data.get_mut().cache_dirty = true;
+            }
+
+            /// Split device into individual I2C devices
+            ///
+            /// It is not possible to know the compatibilities between channels
+            /// so when talking to a split I2C device, only its channel
+            /// will be selected.
+            ///
+            /// Splitting the device again invalidates the parts created before,
+            /// see [`invalidate_parts()`](#method.invalidate_parts).
+            pub fn split(&self) -> $parts<'_, $name<I2C, D, O>, I2C> {
+                self.invalidate_parts();
+                $parts::new(&self, self.generation.get())
+            }
+
+            /// Invalidate all split parts created so far.
+            ///
+            /// Transfers through them fail with `Error::StaleHandle` from then on.
+            /// This is useful after resetting the device externally, so that
+            /// drivers holding old parts notice instead of operating on a device
+            /// whose state they no longer match.
+            pub fn invalidate_parts(&self) {
+                self.generation.set(self.generation.get().wrapping_add(1));
+            }
+
+            /// Get the channel with index `N`, checking at compile time that it
+            /// exists on this device.
+            ///
+            /// This allows describing the board wiring with constants:
+            ///
+            /// ```
+            /// use linux_embedded_hal::I2cdev;
+            /// use xca9548a::{Channel, Xca9543a};
+            ///
+            /// type Mux = Xca9543a<I2cdev>;
+            /// const SENSOR: Channel = Mux::channel::<1>();
+            /// ```
+            ///
+            /// Referencing a channel that does not exist fails to compile:
+            ///
+            /// ```compile_fail
+            /// use linux_embedded_hal::I2cdev;
+            /// use xca9548a::{Channel, Xca9543a};
+            ///
+            /// type Mux = Xca9543a<I2cdev>;
+            /// const SENSOR: Channel = Mux::channel::<5>();
+            /// ```
+            pub const fn channel<const N: u8>() -> Channel {
+                const {
+                    assert!(
+                        N < ($mask as u8).count_ones() as u8,
+                        "channel not present on this device"
+                    )
+                };
+                Channel::from_checked_index(N)
+            }
+
+            /// Get the split I2C device of the channel with index `N`, checking at
+            /// compile time that it exists on this device.
+            ///
+            /// See [`channel()`](#method.channel) and [`split()`](#method.split).
+            pub fn part<const N: u8>(&self) -> I2cSlave<'_, $name<I2C, D, O>, I2C> {
+                I2cSlave::new(self, Self::channel::<N>(), self.generation.get())
+            }
 
-#[doc(hidden)]
-pub trait DoOnAcquired<I2C>: private::Sealed {
-    fn do_on_acquired<R, E: ehal::Error>(
-        &self,
-        f: impl FnOnce(cell::RefMut<Xca954xaData<I2C>>) -> Result<R, Error<E>>,
-    ) -> Result<R, Error<E>>;
-}
+            /// Build one driver for each of the first `N` channels with `f`, checking
+            /// at compile time that they exist on this device.
+            ///
+            /// This suits the common case of identical devices with the same
+            /// address on each channel, e.g. an array of temperature sensors:
+            ///
+            /// ```
+            /// use xca9548a::{SlaveAddr, Xca9548a};
+            /// # use embedded_hal_mock::eh1::i2c::Mock as I2cMock;
+            /// # struct Sensor<I2C>(I2C);
+            /// # let dev = I2cMock::new(&[]);
+            ///
+            /// let switch = Xca9548a::new(dev, SlaveAddr::default());
+            /// let sensors: [Sensor<_>; 8] = switch.build_array(Sensor);
+            /// # drop(sensors);
+            /// # switch.destroy().done();
+            /// ```
+            ///
+            /// Building more drivers than channels present fails to compile:
+            ///
+            /// ```compile_fail
+            /// use xca9548a::{SlaveAddr, Xca9543a};
+            /// # use embedded_hal_mock::eh1::i2c::Mock as I2cMock;
+            /// # struct Sensor<I2C>(I2C);
+            ///
+            /// let switch = Xca9543a::new(I2cMock::new(&[]), SlaveAddr::default());
+            /// let sensors: [Sensor<_>; 3] = switch.build_array(Sensor);
+            /// ```
+            pub fn build_array<'a, R, const N: usize>(
+                &'a self,
+                mut f: impl FnMut(I2cSlave<'a, $name<I2C, D, O>, I2C>) -> R,
+            ) -> [R; N] {
+                const {
+                    assert!(
+                        N <= ($mask as u8).count_ones() as usize,
+                        "more channels than present on this device"
+                    )
+                };
+                let generation = self.generation.get();
+                core::array::from_fn(|i| {
+                    let channel = Channel::from_checked_index(i as u8);
+                    f(I2cSlave::new(self, channel, generation))
+                })
+            }
 
-#[doc(hidden)]
-pub trait SelectChannels: private::Sealed {
-    type Error;
-    fn select_channels(&mut self, mask: u8) -> Result<(), Self::Error>;
-}
+            /// Get an I2C device for talking to other devices on the upstream bus.
+            ///
+            /// All channels are deselected before each transfer through it, so that
+            /// no downstream device can shadow an upstream device with the same address.
+            pub fn upstream(&self) -> UpstreamBus<'_, $name<I2C, D, O>, I2C> {
+                UpstreamBus::new(&self)
+            }
 
-impl<E> ehal::Error for Error<E>
-where
-    E: ehal::Error,
-{
-    fn kind(&self) -> ehal::ErrorKind {
-        match self {
-            Error::I2C(e) => e.kind(),
-            Error::CouldNotAcquireDevice => ehal::ErrorKind::Other,
+            /// Turn the device into a router sending each transfer to the
+            /// channel of its target address, without any configured routes.
+            ///
+            /// The routes can be learned with [`Router::learn()`].
+            /// See [`Router`].
+            pub fn into_router(self) -> Router<'static, $name<I2C, D, O>, I2C> {
+                Router::new(self, RoutingTable::new(&[]))
+            }
+
+            /// Turn the device into a router sending each transfer to the
+            /// channel given by `table` for its target address.
+            ///
+            /// Check the table against the address of this device with
+            /// [`Router::validate()`]. See [`Router`].
+            pub fn into_router_with_table<'a, ID: PartialEq>(
+                self,
+                table: RoutingTable<'a, ID>,
+            ) -> Router<'a, $name<I2C, D, O>, I2C, ID> {
+                Router::new(self, table)
+            }
         }
-    }
-}
 
-macro_rules! i2c_traits {
-    ( $name:ident ) => {
-        impl<I2C> DoOnAcquired<I2C> for $name<I2C> {
-            fn do_on_acquired<R, E: ehal::Error>(
-                &self,
-                f: impl FnOnce(cell::RefMut<Xca954xaData<I2C>>) -> Result<R, Error<E>>,
-            ) -> Result<R, Error<E>> {
-                let dev = self
-                    .data
-                    .try_borrow_mut()
-                    .map_err(|_| Error::CouldNotAcquireDevice)?;
-                f(dev)
+        impl<I2C, D, O, E> $name<I2C, D, O>
+        where
+            I2C: ehal::I2c<Error = E>,
+            E: ehal::Error,
+        {
+            /// Last channel selections and transfers done, for post-mortem debugging.
+            ///
+            /// Available with the `history` feature.
+            #[cfg(feature = "history")]
+            pub fn history(&self) -> Result<History, Error<E>> {
+                self.inspect(|dev| dev.history)
+            }
+
+            /// Bus health counters: channel switches, transfers and errors per
+            /// channel and accesses rejected because the device was in use.
+            ///
+            /// Available with the `stats` feature.
+            #[cfg(feature = "stats")]
+            pub fn stats(&self) -> Result<Stats, Error<E>> {
+                let mut stats = self.inspect(|dev| dev.stats)?;
+                stats.borrow_failures = self.borrow_failures.get();
+                Ok(stats)
+            }
+
+            /// Channels selected according to the cache, without any bus access.
+            ///
+            /// Available with the `test-util` feature for asserting the state
+            /// of the driver at the end of a test scenario.
+            #[cfg(feature = "test-util")]
+            pub fn cached_channels(&self) -> Result<u8, Error<E>> {
+                self.inspect(|dev| dev.selected_channel_mask)
+            }
+
+            /// Number of transfers done through the split parts since the last
+            /// integrity check. See [`Builder::integrity_check_interval()`](struct.Builder.html#method.integrity_check_interval).
+            ///
+            /// Available with the `test-util` feature.
+            #[cfg(feature = "test-util")]
+            pub fn transfers_since_check(&self) -> Result<u16, Error<E>> {
+                self.inspect(|dev| dev.transfers_since_check)
+            }
+
+            /// Current driver state, e.g. for persisting it with
+            /// [`DeviceState::save()`] and rebuilding the driver after a reboot
+            /// with [`from_parts()`](#method.from_parts).
+            pub fn state(&self) -> Result<DeviceState, Error<E>> {
+                self.inspect(|dev| dev.state())
+            }
+
+            /// Channels whose [`Observer::warm_up()`] hook has been called since
+            /// the construction of the driver or the last reset.
+            pub fn warmed_up_channels(&self) -> Result<u8, Error<E>> {
+                self.inspect(|dev| dev.warmed_up)
+            }
+
+            /// Last error seen on `channel`, whether during a transfer or while
+            /// selecting it, since the construction of the driver or the last
+            /// [`clear_last_errors()`](#method.clear_last_errors).
+            ///
+            /// This is kept even if the error itself was consumed elsewhere, e.g. by
+            /// a downstream driver.
+            pub fn last_error(&self, channel: Channel) -> Result<Option<LastError>, Error<E>> {
+                self.inspect(|dev| dev.last_errors[usize::from(channel.index())])
+            }
+
+            /// Whether the cached channel selection is considered to match the device.
+            ///
+            /// While the cache is clean, selecting channels that are already
+            /// selected does not access the bus (depending on the cache policy).
+            /// Otherwise, the channels are selected again before the next transfer.
+            pub fn is_cache_clean(&self) -> Result<bool, Error<E>> {
+                self.inspect(|dev| !dev.cache_dirty)
+            }
+
+            /// Read the control register and report whether `channel` is enabled.
+            ///
+            /// Channels not present on the device are always reported as disabled.
+            pub fn is_channel_enabled(&self, channel: Channel) -> Result<bool, Error<E>> {
+                let mut data = [0];
+                self.do_on_acquired(AccessContext::device(AccessKind::ReadStatus), |mut dev| {
+                    let address = dev.address;
+                    dev.i2c.read(address, &mut data).map_err(Error::I2C)?;
+                    Ok(codec::is_channel_enabled(data[0], $mask, channel.index()))
+                })
+            }
+
+            /// Read the raw control register value, deferring the read if the device
+            /// is in use.
+            ///
+            /// If the device is in use, e.g. because this is called from within a
+            /// transfer done through a split part, the read is queued and done as soon
+            /// as the device is released, and `Ok(None)` is returned.
+            /// The result can then be retrieved with
+            /// [`take_deferred_status()`](#method.take_deferred_status).
+            /// The channel and interrupt bits can be extracted with the [`codec`] functions.
+            pub fn get_status_deferred(&self) -> Result<Option<u8>, Error<E>> {
+                match self
+                    .do_on_acquired(AccessContext::device(AccessKind::ReadStatus), |mut dev| {
+                        Self::read_now(&mut dev)
+                    }) {
+                    Err(Error::CouldNotAcquireDevice(_)) => {
+                        self.deferred_read.set(Some(Self::read_deferred));
+                        Ok(None)
+                    }
+                    result => result.map(Some),
+                }
+            }
+
+            /// Take the result of the last control register read deferred by
+            /// [`get_status_deferred()`](#method.get_status_deferred).
+            ///
+            /// Returns `None` if no deferred read has been done since the last call.
+            pub fn take_deferred_status(&self) -> Option<Result<u8, ehal::ErrorKind>> {
+                self.deferred_status.take()
+            }
+
+            fn read_now(dev: &mut Xca954xaData<I2C, D, O>) -> Result<u8, Error<E>> {
+                let mut data = [0];
+                let address = dev.address;
+                dev.i2c.read(address, &mut data).map_err(Error::I2C)?;
+                Ok(data[0])
+            }
+
+            fn read_deferred(dev: &mut Xca954xaData<I2C, D, O>) -> Result<u8, ehal::ErrorKind> {
+                Self::read_now(dev).map_err(|e| ehal::Error::kind(&e))
+            }
+
+            /// Read the control register and return the enabled channels in ascending order.
+            pub fn enabled_channels(&self) -> Result<impl Iterator<Item = Channel>, Error<E>> {
+                let mut data = [0];
+                self.do_on_acquired(AccessContext::device(AccessKind::ReadStatus), |mut dev| {
+                    let address = dev.address;
+                    dev.i2c.read(address, &mut data).map_err(Error::I2C)?;
+                    Ok(Channel::in_mask(codec::decode_channels(data[0], $mask)))
+                })
             }
         }
 
-        impl<I2C, E> ehal::ErrorType for $name<I2C>
+        impl<I2C, E> $name<I2C>
         where
             I2C: ehal::I2c<Error = E>,
             E: ehal::Error,
         {
-            type Error = Error<E>;
+            /// Create new instance of the device and select the given channels.
+            ///
+            /// The address is validated like in [`try_new()`](#method.try_new).
+            /// In case of error, the I²C bus instance is returned together with it.
+            pub fn new_with_channels(
+                i2c: I2C,
+                address: SlaveAddr,
+                channels: u8,
+            ) -> Result<Self, (I2C, Error<E>)> {
+                Self::builder()
+                    .address(address)
+                    .initial_channels(channels)
+                    .build(i2c)
+            }
         }
 
-        impl<I2C, E> ehal::I2c for $name<I2C>
+        impl<I2C, D, O, E> $name<I2C, D, O>
         where
             I2C: ehal::I2c<Error = E>,
+            D: DelayNs,
+            O: Observer,
             E: ehal::Error,
         {
-            fn transaction(
+            /// Verify that the device is present and in its power-on default state.
+            ///
+            /// The control register is read and no channel must be selected.
+            /// This catches wrong addresses (the read is not acknowledged) as well as
+            /// devices that were not properly reset.
+            /// Returns `Error::NotPowerOnDefault` with the selected channels otherwise.
+            pub fn init(&mut self) -> Result<(), Error<E>> {
+                self.do_on_acquired(AccessContext::device(AccessKind::ReadStatus), |mut dev| {
+                    let mut data = [0];
+                    let address = dev.address;
+                    dev.i2c.read(address, &mut data).map_err(Error::I2C)?;
+                    let channels = codec::decode_channels(data[0], $mask);
+                    dev.set_cached_channels(channels);
+                    if channels == 0 {
+                        Ok(())
+                    } else {
+                        Err(Error::NotPowerOnDefault(channels))
+                    }
+                })
+            }
+
+            /// Deselect all channels and destroy the driver instance, returning
+            /// the I²C bus instance.
+            ///
+            /// This avoids handing back the bus with stale channel routing.
+            /// In case of error, the I²C bus instance is returned together with it.
+            pub fn shutdown(self) -> Result<I2C, (I2C, Error<E>)> {
+                let mut data = self.data.into_inner();
+                match data.select_channels(0) {
+                    Ok(()) => Ok(data.i2c),
+                    Err(e) => Err((data.i2c, e)),
+                }
+            }
+
+            /// Deselect all channels, hold the device in reset through its
+            /// RESET pin (active low) and destroy the driver instance, returning
+            /// the I²C bus instance.
+            ///
+            /// The RESET pin is asserted even if deselecting the channels fails.
+            /// In case of error, the I²C bus instance is returned together with it.
+            pub fn shutdown_with_reset<P: OutputPin>(
+                self,
+                reset: &mut P,
+            ) -> Result<I2C, (I2C, Error<E>)> {
+                let mut data = self.data.into_inner();
+                let deselect = data.select_channels(0);
+                let result = reset.set_low().map_err(|_| Error::Pin).and(deselect);
+                match result {
+                    Ok(()) => Ok(data.i2c),
+                    Err(e) => Err((data.i2c, e)),
+                }
+            }
+
+            /// Select which channels are enabled and return the channels
+            /// selected before.
+            ///
+            /// This allows temporarily switching channels and restoring the previous
+            /// selection afterwards. The previous selection is taken from the cache,
+            /// unless it may not match the device, in which case the control register is read.
+            /// See [`select_channels()`](#method.select_channels).
+            pub fn replace_channels(&mut self, channels: u8) -> Result<u8, Error<E>> {
+                self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| {
+                        let previous = dev.current_channels()?;
+                        dev.select_device_channels(channels, false)?;
+                        Ok(previous)
+                    },
+                )
+            }
+
+            /// Select `channel` and keep the device acquired until the returned
+            /// guard is dropped, giving exclusive access to the channel.
+            ///
+            /// Fails with `Error::InvalidChannels` if the device does not have `channel`.
+            ///
+            /// See [`ChannelGuard`].
+            pub fn lock(&self, channel: Channel) -> Result<ChannelGuard<'_, I2C, D, O>, Error<E>> {
+                if channel.mask() & $mask == 0 {
+                    return Err(Error::InvalidChannels(channel.mask()));
+                }
+                let dev = self.acquire(AccessContext::device(AccessKind::SelectChannels))?;
+                let mut guard = ChannelGuard::new(dev, self, channel);
+                guard.dev()?.select_device_channels(channel.mask(), false)?;
+                Ok(guard)
+            }
+
+            /// Deselect all channels before entering a low-power mode, returning
+            /// the previous selection to be restored with [`unpark()`](#method.unpark).
+            pub fn park_low_power(&mut self) -> Result<Parked, Error<E>> {
+                self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| {
+                        let channels = dev.current_channels()?;
+                        dev.select_channels(0)?;
+                        Ok(Parked { channels })
+                    },
+                )
+            }
+
+            /// Deselect all channels and hold the device in reset through its
+            /// RESET pin (active low) before entering a low-power mode, returning
+            /// the previous selection to be restored with
+            /// [`unpark_with_reset()`](#method.unpark_with_reset).
+            ///
+            /// The RESET pin is asserted even if deselecting the channels fails.
+            /// The split parts created before are invalidated.
+            pub fn park_low_power_with_reset<P: OutputPin>(
+                &mut self,
+                reset: &mut P,
+            ) -> Result<Parked, Error<E>> {
+                self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| {
+                        let channels = dev.current_channels()?;
+                        let deselect = dev.select_channels(0);
+                        reset.set_low().map_err(|_| Error::Pin)?;
+                        // The device is in its power-on default state while in reset.
+                        dev.set_cached_channels(0);
+                        dev.warmed_up = 0;
+                        dev.parts_stale = true;
+                        deselect.and(Ok(Parked { channels }))
+                    },
+                )
+            }
+
+            /// Restore the channel selection after [`park_low_power()`](#method.park_low_power).
+            pub fn unpark(&mut self, parked: Parked) -> Result<(), Error<E>> {
+                self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| dev.select_device_channels(parked.channels, false),
+                )
+            }
+
+            /// Release the device from reset through its RESET pin and restore
+            /// the channel selection after
+            /// [`park_low_power_with_reset()`](#method.park_low_power_with_reset).
+            ///
+            /// The split parts created before are invalidated.
+            pub fn unpark_with_reset<P: OutputPin>(
+                &mut self,
+                parked: Parked,
+                reset: &mut P,
+            ) -> Result<(), Error<E>> {
+                self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| {
+                        reset.set_high().map_err(|_| Error::Pin)?;
+                        dev.set_cached_channels(0);
+                        dev.parts_stale = true;
+                        dev.select_device_channels(parked.channels, false)
+                    },
+                )
+            }
+
+            /// Read the raw control register value.
+            ///
+            /// Advanced: The channel bits are used to update the cached channel selection.
+            /// Prefer `get_channel_status()` for normal use.
+            pub fn read_control_register(&mut self) -> Result<u8, Error<E>> {
+                self.do_on_acquired(AccessContext::device(AccessKind::ReadStatus), |mut dev| {
+                    let value = dev.read_control_register()?;
+                    dev.set_cached_channels(codec::decode_channels(value, $mask));
+                    Ok(value)
+                })
+            }
+
+            /// Write a raw value to the control register.
+            ///
+            /// Advanced: The value is written as-is, without any masking or strict
+            /// mode checks, although single-channel mode is still enforced.
+            /// The channel bits are used to update the cached channel selection.
+            /// Prefer `select_channels()` for normal use.
+            pub fn write_control_register(&mut self, value: u8) -> Result<(), Error<E>> {
+                self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| {
+                        dev.select_channels(value)?;
+                        dev.set_cached_channels(codec::decode_channels(value, $mask));
+                        Ok(())
+                    },
+                )
+            }
+
+            /// Write the same data to identical slaves on several channels at once.
+            ///
+            /// All the `channels` are selected at the same time, `bytes` are written
+            /// to `address` in a single transaction and the previous channel
+            /// selection is restored afterwards.
+            /// Only use this for writes, since several slaves answering a read
+            /// at the same time would corrupt the data.
+            pub fn broadcast_write(
                 &mut self,
+                channels: u8,
                 address: u8,
-                operations: &mut [ehal::Operation<'_>],
+                bytes: &[u8],
             ) -> Result<(), Error<E>> {
-                self.do_on_acquired(|mut dev| {
-                    dev.i2c.transaction(address, operations).map_err(Error::I2C)
+                self.do_on_acquired(AccessContext::device(AccessKind::Write), |mut dev| {
+                    dev.broadcast_write(channels, address, bytes)
                 })
             }
 
-            fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
-                self.do_on_acquired(|mut dev| dev.i2c.read(address, read).map_err(Error::I2C))
+            /// Write `bytes` to `address` on `channel` without splitting the device.
+            ///
+            /// The channel is selected on its own and the previous channel selection
+            /// is restored afterwards. Fails with `Error::InvalidChannels` if the
+            /// device does not have `channel`.
+            pub fn write_to(
+                &mut self,
+                channel: Channel,
+                address: u8,
+                bytes: &[u8],
+            ) -> Result<(), Error<E>> {
+                self.do_on_acquired(AccessContext::device(AccessKind::Write), |mut dev| {
+                    dev.transfer_on(channel, AccessKind::Write, address, |i2c| {
+                        i2c.write(address, bytes)
+                    })
+                })
             }
 
-            fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
-                self.do_on_acquired(|mut dev| dev.i2c.write(address, write).map_err(Error::I2C))
+            /// Read from `address` on `channel` into `buffer` without splitting the device.
+            ///
+            /// See [`write_to()`](#method.write_to).
+            pub fn read_from(
+                &mut self,
+                channel: Channel,
+                address: u8,
+                buffer: &mut [u8],
+            ) -> Result<(), Error<E>> {
+                self.do_on_acquired(AccessContext::device(AccessKind::Read), |mut dev| {
+                    dev.transfer_on(channel, AccessKind::Read, address, |i2c| {
+                        i2c.read(address, buffer)
+                    })
+                })
             }
 
-            fn write_read(
+            /// Write `bytes` to `address` on `channel` and read into `buffer`
+            /// without splitting the device.
+            ///
+            /// See [`write_to()`](#method.write_to).
+            pub fn write_read_on(
                 &mut self,
+                channel: Channel,
                 address: u8,
-                write: &[u8],
-                read: &mut [u8],
-            ) -> Result<(), Self::Error> {
-                self.do_on_acquired(|mut dev| {
-                    dev.i2c.write_read(address, write, read).map_err(Error::I2C)
+                bytes: &[u8],
+                buffer: &mut [u8],
+            ) -> Result<(), Error<E>> {
+                self.do_on_acquired(AccessContext::device(AccessKind::WriteRead), |mut dev| {
+                    dev.transfer_on(channel, AccessKind::WriteRead, address, |i2c| {
+                        i2c.write_read(address, bytes, buffer)
+                    })
                 })
             }
-        }
-    };
-}
 
-macro_rules! impl_device {
-    ( $name:ident, $parts:ident ) => {
-        impl<I2C> $name<I2C> {
-            /// Create new instance of the device
-            pub fn new(i2c: I2C, address: SlaveAddr) -> Self {
-                let data = Xca954xaData {
-                    i2c,
-                    address: address.addr(DEVICE_BASE_ADDRESS),
-                    selected_channel_mask: 0,
-                };
-                $name {
-                    data: cell::RefCell::new(data),
+            /// Select each of the `channels` on its own in ascending order and run `f`
+            /// with the channel and the I²C bus.
+            ///
+            /// Transfers through the bus are reported to the observer, see [`ChannelBus`].
+            /// Stops at the first error. The previous channel selection is restored afterwards.
+            pub fn for_each_channel(
+                &mut self,
+                channels: u8,
+                f: impl FnMut(Channel, &mut ChannelBus<'_, I2C, D, O>) -> Result<(), Error<E>>,
+            ) -> Result<(), Error<E>> {
+                self.do_on_acquired(AccessContext::device(AccessKind::Transaction), |mut dev| {
+                    dev.for_each_channel(channels, f)
+                })
+            }
+
+            /// Write `bytes` to `address` on each of the `channels` one at a time.
+            ///
+            /// Unlike [`broadcast_write()`](#method.broadcast_write), the channels
+            /// are selected on their own and the result on each of them is collected.
+            /// The previous channel selection is restored afterwards. An error doing
+            /// so is collected as well, see [`ChannelResults::restore_error()`].
+            pub fn write_each(
+                &mut self,
+                channels: u8,
+                address: u8,
+                bytes: &[u8],
+            ) -> Result<ChannelResults<E>, Error<E>> {
+                self.do_on_acquired(AccessContext::device(AccessKind::Write), |mut dev| {
+                    dev.on_each_channel(channels, AccessKind::Write, address, |_, i2c| {
+                        i2c.write(address, bytes)
+                    })
+                })
+            }
+
+            /// Read from `address` on each of the `channels` one at a time into
+            /// the buffer with the index of the channel.
+            ///
+            /// There is one buffer for each channel present on the device.
+            /// The result on each channel is collected.
+            /// The previous channel selection is restored afterwards. An error doing
+            /// so is collected as well, see [`ChannelResults::restore_error()`].
+            pub fn read_each<const N: usize>(
+                &mut self,
+                channels: u8,
+                address: u8,
+                buffers: &mut [[u8; N]; ($mask as u8).count_ones() as usize],
+            ) -> Result<ChannelResults<E>, Error<E>> {
+                self.do_on_acquired(AccessContext::device(AccessKind::Read), |mut dev| {
+                    dev.on_each_channel(channels, AccessKind::Read, address, |channel, i2c| {
+                        i2c.read(address, &mut buffers[usize::from(channel.index())])
+                    })
+                })
+            }
+
+            /// Scan all channels for slave devices.
+            ///
+            /// Each channel is selected on its own and every non-reserved
+            /// address (0x08-0x77) except the one of the switch itself is probed
+            /// with a one-byte read. Any error during the probe is interpreted as
+            /// there being no device at that address.
+            /// The previous channel selection is restored afterwards. If it is not
+            /// known, e.g. right after construction, the control register is read
+            /// before scanning.
+            pub fn scan_all(&mut self) -> Result<ScanReport, Error<E>> {
+                self.scan_all_with_watchdog(&mut || ())
+            }
+
+            /// Scan all channels for slave devices, feeding the watchdog
+            /// before probing each address.
+            ///
+            /// See [`scan_all()`](#method.scan_all).
+            pub fn scan_all_with_watchdog<W: Watchdog>(
+                &mut self,
+                watchdog: &mut W,
+            ) -> Result<ScanReport, Error<E>> {
+                self.scan_with_watchdog(&ScanConfig::default(), watchdog)
+            }
+
+            /// Scan for slave devices with the given channels, addresses and
+            /// early-exit condition.
+            ///
+            /// Each channel is selected on its own and the configured addresses
+            /// except the one of the switch itself are probed with a one-byte read,
+            /// until the configured number of devices has been found, if any.
+            /// The previous channel selection is restored afterwards.
+            /// See [`scan_all()`](#method.scan_all).
+            pub fn scan(&mut self, config: &ScanConfig) -> Result<ScanReport, Error<E>> {
+                self.scan_with_watchdog(config, &mut || ())
+            }
+
+            /// Scan for slave devices with the given configuration, feeding the
+            /// watchdog before probing each address.
+            ///
+            /// See [`scan()`](#method.scan).
+            pub fn scan_with_watchdog<W: Watchdog>(
+                &mut self,
+                config: &ScanConfig,
+                watchdog: &mut W,
+            ) -> Result<ScanReport, Error<E>> {
+                self.do_on_acquired(AccessContext::device(AccessKind::Scan), |mut dev| {
+                    dev.scan(config, watchdog)
+                })
+            }
+
+            /// Run a sequence of transfers and delays across channels in order,
+            /// e.g. for scripted production tests.
+            ///
+            /// Data read is placed in the buffers of the read steps. A channel is only
+            /// selected when it differs from the one of the previous step and, with
+            /// automatic deselection, all channels are deselected at the end of the
+            /// sequence instead of after each step. Failed steps are not retried.
+            ///
+            /// On failure, the index of the failed step is returned along with the
+            /// error. The index is the number of steps if deselecting at the end failed
+            /// and 0 if the device could not be acquired. If a step uses a channel
+            /// the device does not have, nothing is done and `Error::InvalidChannels`
+            /// is returned with the index of that step.
+            ///
+            /// ```
+            /// use xca9548a::{Channel, SlaveAddr, Step, Xca9548a};
+            /// # use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+            /// # let dev = I2cMock::new(&[
+            /// #     I2cTrans::write(0x70, vec![0x01]),
+            /// #     I2cTrans::write(0x50, vec![0x00, 0xAB]),
+            /// #     I2cTrans::write_read(0x50, vec![0x00], vec![0xAB]),
+            /// #     I2cTrans::write(0x70, vec![0x02]),
+            /// #     I2cTrans::read(0x48, vec![0x12, 0x34]),
+            /// # ]);
+            ///
+            /// let mut switch = Xca9548a::new(dev, SlaveAddr::default());
+            /// let (mut readback, mut temperature) = ([0], [0; 2]);
+            /// switch
+            ///     .run_sequence(&mut [
+            ///         Step::Write { channel: Channel::Ch0, address: 0x50, data: &[0x00, 0xAB] },
+            ///         Step::Delay { ns: 5_000_000 },
+            ///         Step::WriteRead {
+            ///             channel: Channel::Ch0,
+            ///             address: 0x50,
+            ///             data: &[0x00],
+            ///             buffer: &mut readback,
+            ///         },
+            ///         Step::Read { channel: Channel::Ch1, address: 0x48, buffer: &mut temperature },
+            ///     ])
+            ///     .unwrap();
+            /// assert_eq!([0xAB], readback);
+            /// # switch.destroy().done();
+            /// ```
+            pub fn run_sequence(
+                &mut self,
+                steps: &mut [Step<'_>],
+            ) -> Result<(), (usize, Error<E>)> {
+                let access = AccessContext::device(AccessKind::Transaction);
+                match self.do_on_acquired(access, |mut dev| Ok(dev.run_sequence(steps))) {
+                    Ok(result) => result,
+                    Err(e) => Err((0, e)),
                 }
             }
 
-            /// Destroy driver instance, return I²C bus instance.
-            pub fn destroy(self) -> I2C {
-                self.data.into_inner().i2c
+            /// Test the control register, e.g. as part of a power-on self-test.
+            ///
+            /// Each channel is selected on its own and then all are deselected,
+            /// reading back the control register every time. Channels that can not
+            /// be enabled or disabled are reported, as well as bits set in the
+            /// control register that do not correspond to any channel or interrupt
+            /// of the device. Communication errors are returned as such.
+            /// The previous channel selection is restored afterwards.
+            pub fn self_test(&mut self) -> Result<SelfTestReport, Error<E>> {
+                self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| dev.self_test(),
+                )
             }
+        }
 
-            /// Split device into individual I2C devices
+        impl<'a, PARENT, I2C, T, D, O, E> $name<I2cSlave<'a, PARENT, I2C, T>, D, O>
+        where
+            PARENT: DoOnAcquired<I2C>,
+            PARENT::Delay: DelayNs,
+            PARENT::Observer: Observer,
+            I2C: ehal::I2c<Error = E>,
+            D: DelayNs,
+            O: Observer,
+            E: ehal::Error,
+        {
+            /// Select a single channel of this device cascaded behind a channel
+            /// of an upstream multiplexer.
             ///
-            /// It is not possible to know the compatibilities between channels
-            /// so when talking to a split I2C device, only its channel
-            /// will be selected.
-            pub fn split(&self) -> $parts<$name<I2C>, I2C> {
-                $parts::new(&self)
+            /// The upstream channel is selected on its own, deselecting its siblings,
+            /// and then the downstream channel is selected on this device.
+            /// Returns `Error::InvalidPath` if this device is not connected to
+            /// the upstream channel of the path.
+            pub fn select_path(&mut self, path: ChannelPath) -> Result<(), Error<Error<E>>> {
+                self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| {
+                        if dev.i2c.channel() != path.upstream {
+                            return Err(Error::InvalidPath(path));
+                        }
+                        // The control register of this device is always written so
+                        // that the upstream channel is selected as well.
+                        dev.select_device_channels(path.downstream.mask(), true)
+                    },
+                )
+            }
+        }
+
+        impl<I2C, E> Builder<$name<I2C>>
+        where
+            I2C: ehal::I2c<Error = E>,
+            E: ehal::Error,
+        {
+            /// Build the device, selecting the initial channels if configured.
+            ///
+            /// The address is validated like in `try_new()`.
+            /// In case of error, the I²C bus instance is returned together with it.
+            pub fn build(self, i2c: I2C) -> Result<$name<I2C>, (I2C, Error<E>)> {
+                let address = match self.address.validated_address(self.base_address) {
+                    Ok(address) => address,
+                    Err(e) => return Err((i2c, Error::InvalidAddress(e))),
+                };
+                let dev = $name::from_data(Xca954xaData::new(i2c, address, $mask, self.config));
+                self.select_initial_channels(dev)
+            }
+
+            /// Reset the device through its RESET pin (active low) and build it,
+            /// selecting the initial channels if configured.
+            ///
+            /// The pin is driven low and then high again. The device is then in
+            /// its power-on state with all channels deselected, so the cache
+            /// starts out valid. The reset pulse needed by the device is a few
+            /// nanoseconds, shorter than any pin toggle.
+            /// The pin is only borrowed, like for
+            /// [`shutdown_with_reset()`](struct.Xca9548a.html#method.shutdown_with_reset).
+            ///
+            /// The address is validated like in `try_new()` before the pin is used.
+            /// In case of error, the I²C bus instance is returned together with it.
+            pub fn build_with_reset<P: OutputPin>(
+                self,
+                i2c: I2C,
+                reset: &mut P,
+            ) -> Result<$name<I2C>, (I2C, Error<E>)> {
+                let address = match self.address.validated_address(self.base_address) {
+                    Ok(address) => address,
+                    Err(e) => return Err((i2c, Error::InvalidAddress(e))),
+                };
+                if reset.set_low().and_then(|_| reset.set_high()).is_err() {
+                    return Err((i2c, Error::Pin));
+                }
+                let mut data = Xca954xaData::new(i2c, address, $mask, self.config);
+                data.set_cached_channels(0);
+                self.select_initial_channels($name::from_data(data))
+            }
+
+            fn select_initial_channels(
+                &self,
+                mut dev: $name<I2C>,
+            ) -> Result<$name<I2C>, (I2C, Error<E>)> {
+                if let Some(channels) = self.initial_channels {
+                    // Always written, so that the cache matches the device.
+                    if let Err(e) = dev.force_select(channels) {
+                        return Err((dev.destroy(), e));
+                    }
+                }
+                Ok(dev)
             }
         }
     };
-    ( $name:ident, $parts:ident, no_interrupts ) => {
-        impl_device!($name, $parts);
+    ( $name:ident, $parts:ident, $mask:expr, $part_name:expr, no_interrupts ) => {
+        impl_device!($name, $parts, $mask, $part_name);
 
-        impl<I2C, E> $name<I2C>
+        impl<I2C, D, O, E> $name<I2C, D, O>
         where
             I2C: ehal::I2c<Error = E>,
             E: ehal::Error,
@@ -162,7 +1936,7 @@ macro_rules! impl_device {
             /// A `0` means the channel is disabled and a `1` that the channel is enabled.
             pub fn get_channel_status(&self) -> Result<u8, Error<E>> {
                 let mut data = [0];
-                self.do_on_acquired(|mut dev| {
+                self.do_on_acquired(AccessContext::device(AccessKind::ReadStatus), |mut dev| {
                     let address = dev.address;
                     dev.i2c
                         .read(address, &mut data)
@@ -172,9 +1946,11 @@ macro_rules! impl_device {
             }
         }
 
-        impl<I2C, E> $name<I2C>
+        impl<I2C, D, O, E> $name<I2C, D, O>
         where
             I2C: ehal::I2c<Error = E>,
+            D: DelayNs,
+            O: Observer,
             E: ehal::Error,
         {
             /// Select which channels are enabled.
@@ -184,15 +1960,30 @@ macro_rules! impl_device {
             /// corresponds to channel 7.
             /// A `0` disables the channel and a `1` enables it.
             /// Several channels can be enabled at the same time
+            ///
+            /// The write is skipped if the channels are already selected.
+            /// See [`force_select()`](#method.force_select).
             pub fn select_channels(&mut self, channels: u8) -> Result<(), Error<E>> {
-                self.do_on_acquired(|mut dev| dev.select_channels(channels))
+                self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| dev.select_device_channels(channels, false),
+                )
+            }
+
+            /// Select which channels are enabled, writing the control register
+            /// even if the channels are already selected.
+            pub fn force_select(&mut self, channels: u8) -> Result<(), Error<E>> {
+                self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| dev.select_device_channels(channels, true),
+                )
             }
         }
     };
-    ( $name:ident, $parts:ident, $mask:expr, interrupts ) => {
-        impl_device!($name, $parts);
+    ( $name:ident, $parts:ident, $mask:expr, $part_name:expr, interrupts ) => {
+        impl_device!($name, $parts, $mask, $part_name);
 
-        impl<I2C, E> $name<I2C>
+        impl<I2C, D, O, E> $name<I2C, D, O>
         where
             I2C: ehal::I2c<Error = E>,
             E: ehal::Error,
@@ -204,12 +1995,12 @@ macro_rules! impl_device {
             /// A `0` means the channel is disabled and a `1` that the channel is enabled.
             pub fn get_channel_status(&self) -> Result<u8, Error<E>> {
                 let mut data = [0];
-                self.do_on_acquired(|mut dev| {
+                self.do_on_acquired(AccessContext::device(AccessKind::ReadStatus), |mut dev| {
                     let address = dev.address;
                     dev.i2c
                         .read(address, &mut data)
                         .map_err(Error::I2C)
-                        .and(Ok(data[0] & $mask))
+                        .and(Ok(codec::decode_channels(data[0], $mask)))
                 })
             }
 
@@ -221,19 +2012,21 @@ macro_rules! impl_device {
             /// Note: I2C interrupts are usually active LOW!
             pub fn get_interrupt_status(&self) -> Result<u8, Error<E>> {
                 let mut data = [0];
-                self.do_on_acquired(|mut dev| {
+                self.do_on_acquired(AccessContext::device(AccessKind::ReadStatus), |mut dev| {
                     let address = dev.address;
                     dev.i2c
                         .read(address, &mut data)
                         .map_err(Error::I2C)
-                        .and(Ok((data[0] >> 4) & $mask))
+                        .and(Ok(codec::decode_interrupts(data[0], $mask)))
                 })
             }
         }
 
-        impl<I2C, E> $name<I2C>
+        impl<I2C, D, O, E> $name<I2C, D, O>
         where
             I2C: ehal::I2c<Error = E>,
+            D: DelayNs,
+            O: Observer,
             E: ehal::Error,
         {
             /// Select which channels are enabled.
@@ -243,19 +2036,53 @@ macro_rules! impl_device {
             /// A `0` disables the channel and a `1` enables it.
             /// Several channels can be enabled at the same time.
             ///
-            /// Channels/bits that does not exist for the specific device are ignored.
+            /// Channels/bits that does not exist for the specific device are ignored
+            /// unless strict mode is enabled. See [`Builder::strict()`](struct.Builder.html#method.strict).
+            ///
+            /// The write is skipped if the channels are already selected.
+            /// See [`force_select()`](#method.force_select).
             pub fn select_channels(&mut self, channels: u8) -> Result<(), Error<E>> {
-                self.do_on_acquired(|mut dev| dev.select_channels(channels & $mask))
+                self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| dev.select_device_channels(channels, false),
+                )
+            }
+
+            /// Select which channels are enabled, writing the control register
+            /// even if the channels are already selected.
+            pub fn force_select(&mut self, channels: u8) -> Result<(), Error<E>> {
+                self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| dev.select_device_channels(channels, true),
+                )
             }
         }
     };
 }
 
-impl_device!(Xca9548a, Parts, no_interrupts);
-i2c_traits!(Xca9548a);
+impl_device!(
+    Xca9548a,
+    Parts,
+    codec::XCA9548A_CHANNEL_MASK,
+    "TCA9548A/PCA9548A",
+    no_interrupts
+);
+i2c_traits!(Xca9548a, codec::XCA9548A_CHANNEL_MASK);
 
-impl_device!(Xca9543a, Parts2, 0x03, interrupts);
-i2c_traits!(Xca9543a);
+impl_device!(
+    Xca9543a,
+    Parts2,
+    codec::XCA9543A_CHANNEL_MASK,
+    "TCA9543A/PCA9543A",
+    interrupts
+);
+i2c_traits!(Xca9543a, codec::XCA9543A_CHANNEL_MASK);
 
-impl_device!(Xca9545a, Parts4, 0x0f, interrupts);
-i2c_traits!(Xca9545a);
+impl_device!(
+    Xca9545a,
+    Parts4,
+    codec::XCA9545A_CHANNEL_MASK,
+    "TCA9545A/PCA9545A",
+    interrupts
+);
+i2c_traits!(Xca9545a, codec::XCA9545A_CHANNEL_MASK);
@@ -0,0 +1,111 @@
+use crate::{CachePolicy, Channel, SlaveAddr, DEFAULT_ADDRESS};
+use embedded_hal_mock::eh1::i2c::Transaction as I2cTrans;
+
+/// Builder of the `embedded-hal-mock` expectations for code using the split parts
+///
+/// Each step lists a transaction done through the split part of a channel and
+/// the control register writes needed to select it are inserted following the
+/// same caching behavior as the driver. This keeps the tests in sync with the
+/// driver without interleaving the control register writes by hand.
+///
+/// Available with the `test-util` feature.
+///
+/// ```
+/// use embedded_hal::i2c::I2c;
+/// use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+/// use xca9548a::{Channel, Expectations, SlaveAddr, Xca9548a};
+///
+/// let expectations = Expectations::new(SlaveAddr::default())
+///     .on(Channel::Ch0, I2cTrans::write(0x48, vec![0x01]))
+///     .on(Channel::Ch0, I2cTrans::read(0x48, vec![0x12]))
+///     .on(Channel::Ch1, I2cTrans::read(0x48, vec![0x34]))
+///     .build();
+/// assert_eq!(5, expectations.len());
+///
+/// let switch = Xca9548a::new(I2cMock::new(&expectations), SlaveAddr::default());
+/// let mut parts = switch.split();
+/// let mut data = [0];
+/// parts.i2c0.write(0x48, &[0x01]).unwrap();
+/// parts.i2c0.read(0x48, &mut data).unwrap();
+/// parts.i2c1.read(0x48, &mut data).unwrap();
+/// switch.destroy().done();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Expectations {
+    address: u8,
+    cache_policy: CachePolicy,
+    auto_deselect: bool,
+    selected: u8,
+    transactions: Vec<I2cTrans>,
+}
+
+impl Expectations {
+    /// Start the expectations for a device at `address` with no channel selected.
+    pub fn new(address: SlaveAddr) -> Self {
+        Expectations {
+            address: address.address(DEFAULT_ADDRESS),
+            cache_policy: CachePolicy::default(),
+            auto_deselect: false,
+            selected: 0,
+            transactions: Vec::new(),
+        }
+    }
+
+    /// Follow the given cache policy. See [`Builder::cache_policy()`](struct.Builder.html#method.cache_policy).
+    pub fn cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.cache_policy = cache_policy;
+        self
+    }
+
+    /// Follow the auto-deselection setting. See [`Builder::auto_deselect()`](struct.Builder.html#method.auto_deselect).
+    pub fn auto_deselect(mut self, auto_deselect: bool) -> Self {
+        self.auto_deselect = auto_deselect;
+        self
+    }
+
+    /// Expect `transaction` through the split part of `channel`.
+    pub fn on(mut self, channel: Channel, transaction: I2cTrans) -> Self {
+        if self.cache_policy == CachePolicy::AlwaysSelect || self.selected != channel.mask() {
+            self.write_control(channel.mask());
+        }
+        self.transactions.push(transaction);
+        if self.auto_deselect {
+            self.write_control(0);
+        }
+        self
+    }
+
+    /// Expect `transaction` on the upstream bus. See `upstream()`.
+    pub fn upstream(mut self, transaction: I2cTrans) -> Self {
+        if self.cache_policy == CachePolicy::AlwaysSelect || self.selected != 0 {
+            self.write_control(0);
+        }
+        self.transactions.push(transaction);
+        self
+    }
+
+    /// Expect a `select_channels()` call.
+    pub fn select(mut self, channels: u8) -> Self {
+        if self.cache_policy == CachePolicy::AlwaysSelect || self.selected != channels {
+            self.write_control(channels);
+        }
+        self
+    }
+
+    /// Expect a transaction done on the device itself with the current channel selection.
+    pub fn direct(mut self, transaction: I2cTrans) -> Self {
+        self.transactions.push(transaction);
+        self
+    }
+
+    /// Generated sequence of transactions.
+    pub fn build(self) -> Vec<I2cTrans> {
+        self.transactions
+    }
+
+    fn write_control(&mut self, channels: u8) {
+        self.transactions
+            .push(I2cTrans::write(self.address, vec![channels]));
+        self.selected = channels;
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{AccessContext, AccessKind, Channel, ChannelInfo, DoOnAcquired, Error, Observer};
+use core::{cell, marker::PhantomData};
+use embedded_hal::{delay::DelayNs, i2c as ehal};
+
+/// Switch routing the transfers of a [`Failover`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Active {
+    /// The primary switch (default)
+    Primary,
+    /// The secondary switch, after the primary stopped responding
+    Secondary,
+}
+
+/// Redundant pair of switches wired to the same downstream segments
+///
+/// Transfers are routed through the primary switch. When it stops responding,
+/// i.e. selecting a channel fails or its control register can not be read after
+/// a failed transfer, the secondary switch takes over and the transfer is retried
+/// through it. The failover is reported to the observer of the secondary switch
+/// with [`Observer::failover()`] and can be checked with [`active()`](#method.active).
+///
+/// The secondary switch keeps being used until [`restore_primary()`](#method.restore_primary)
+/// is called, e.g. after the primary switch has been repaired.
+#[derive(Debug)]
+pub struct Failover<DEV> {
+    primary: DEV,
+    secondary: DEV,
+    active: cell::Cell<Active>,
+}
+
+impl<DEV> Failover<DEV> {
+    /// Create a redundant pair of switches, starting with the primary one.
+    ///
+    /// Both switches should have all channels deselected.
+    pub fn new(primary: DEV, secondary: DEV) -> Self {
+        Failover {
+            primary,
+            secondary,
+            active: cell::Cell::new(Active::Primary),
+        }
+    }
+
+    /// Switch routing the transfers.
+    pub fn active(&self) -> Active {
+        self.active.get()
+    }
+
+    /// Route the transfers through the primary switch again.
+    pub fn restore_primary(&mut self) {
+        self.active.set(Active::Primary);
+    }
+
+    /// Primary switch, e.g. for diagnostics.
+    pub fn primary(&self) -> &DEV {
+        &self.primary
+    }
+
+    /// Secondary switch, e.g. for diagnostics.
+    pub fn secondary(&self) -> &DEV {
+        &self.secondary
+    }
+
+    /// Destroy the pair, returning the primary and secondary switches.
+    pub fn destroy(self) -> (DEV, DEV) {
+        (self.primary, self.secondary)
+    }
+
+    /// Virtual I²C bus for `channel` routed through the active switch.
+    pub fn bus<I2C>(&self, channel: Channel) -> FailoverBus<'_, DEV, I2C> {
+        FailoverBus {
+            failover: self,
+            channel,
+            _i2c: PhantomData,
+        }
+    }
+}
+
+/// Virtual I²C bus of a channel of a [`Failover`] pair
+pub struct FailoverBus<'a, DEV, I2C> {
+    failover: &'a Failover<DEV>,
+    channel: Channel,
+    _i2c: PhantomData<I2C>,
+}
+
+impl<DEV, I2C, D, O, E> FailoverBus<'_, DEV, I2C>
+where
+    DEV: DoOnAcquired<I2C, Delay = D, Observer = O>,
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    /// Transfer through the active switch, failing over to the secondary
+    /// switch if the primary one does not respond.
+    fn transfer(
+        &self,
+        kind: AccessKind,
+        address: u16,
+        mut transfer: impl FnMut(&mut I2C) -> Result<(), E>,
+    ) -> Result<(), Error<E>> {
+        let (failover, channel) = (self.failover, self.channel);
+        let access = AccessContext::part(kind, channel);
+        if failover.active.get() == Active::Primary {
+            let (result, failed) = failover.primary.do_on_acquired(access, |mut dev| {
+                let result = dev.part_transfer(channel, kind, address, &mut transfer);
+                let failed = match result {
+                    Err(Error::ChannelSelect(_)) => true,
+                    Err(Error::I2C(_)) => dev.read_control_register().is_err(),
+                    _ => false,
+                };
+                if failed {
+                    dev.cache_dirty = true;
+                }
+                Ok((result, failed))
+            })?;
+            if !failed {
+                return result;
+            }
+            failover.active.set(Active::Secondary);
+            let failed_address = failover.primary.address();
+            return failover.secondary.do_on_acquired(access, |mut dev| {
+                dev.observer.failover(failed_address);
+                dev.part_transfer(channel, kind, address, &mut transfer)
+            });
+        }
+        failover.secondary.do_on_acquired(access, |mut dev| {
+            dev.part_transfer(channel, kind, address, &mut transfer)
+        })
+    }
+}
+
+impl<DEV, I2C> ChannelInfo for FailoverBus<'_, DEV, I2C>
+where
+    DEV: DoOnAcquired<I2C>,
+{
+    /// Address of the active switch.
+    fn mux_address(&self) -> u8 {
+        match self.failover.active() {
+            Active::Primary => self.failover.primary.address(),
+            Active::Secondary => self.failover.secondary.address(),
+        }
+    }
+
+    fn channel(&self) -> Channel {
+        self.channel
+    }
+}
+
+impl<DEV, I2C, E> ehal::ErrorType for FailoverBus<'_, DEV, I2C>
+where
+    DEV: DoOnAcquired<I2C>,
+    I2C: ehal::I2c<Error = E>,
+    E: ehal::Error,
+{
+    type Error = Error<E>;
+}
+
+impl<DEV, I2C, D, O, E> ehal::I2c for FailoverBus<'_, DEV, I2C>
+where
+    DEV: DoOnAcquired<I2C, Delay = D, Observer = O>,
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [ehal::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transfer(AccessKind::Transaction, u16::from(address), |i2c| {
+            i2c.transaction(address, operations)
+        })
+    }
+
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.transfer(AccessKind::Read, u16::from(address), |i2c| {
+            i2c.read(address, read)
+        })
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.transfer(AccessKind::Write, u16::from(address), |i2c| {
+            i2c.write(address, write)
+        })
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.transfer(AccessKind::WriteRead, u16::from(address), |i2c| {
+            i2c.write_read(address, write, read)
+        })
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{AccessKind, Channel, ChannelInfo, Error, Observer, Xca954xaData};
+use embedded_hal::{delay::DelayNs, i2c as ehal};
+
+/// Results of an operation done on several channels one at a time
+///
+/// This includes the result of restoring the previous channel selection
+/// afterwards, see [`restore_error()`](#method.restore_error).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelResults<E: core::fmt::Debug> {
+    results: [Option<Result<(), Error<E>>>; 8],
+    restore_error: Option<Error<E>>,
+}
+
+impl<E: core::fmt::Debug> ChannelResults<E> {
+    fn new() -> Self {
+        ChannelResults {
+            results: core::array::from_fn(|_| None),
+            restore_error: None,
+        }
+    }
+
+    /// Result of the operation on `channel` or `None` if it was not done on it.
+    pub fn get(&self, channel: Channel) -> Option<&Result<(), Error<E>>> {
+        self.results[usize::from(channel.index())].as_ref()
+    }
+
+    /// Whether the operation succeeded on all channels and the previous
+    /// channel selection was restored.
+    pub fn is_ok(&self) -> bool {
+        self.restore_error.is_none() && self.results.iter().flatten().all(Result::is_ok)
+    }
+
+    /// Error restoring the previous channel selection after the operation, if any.
+    pub fn restore_error(&self) -> Option<&Error<E>> {
+        self.restore_error.as_ref()
+    }
+
+    /// Channels where the operation failed together with the error.
+    pub fn errors(&self) -> impl Iterator<Item = (Channel, &Error<E>)> {
+        Channel::iter().filter_map(move |channel| match self.get(channel) {
+            Some(Err(e)) => Some((channel, e)),
+            _ => None,
+        })
+    }
+}
+
+/// I²C bus with a single channel selected
+///
+/// Passed to the closure of `for_each_channel()` on the device driver types.
+/// Transfers through it are reported to the observer and recorded like those
+/// done through the split parts, but do not select the channel again.
+pub struct ChannelBus<'a, I2C, D, O> {
+    dev: &'a mut Xca954xaData<I2C, D, O>,
+    channel: Channel,
+}
+
+impl<'a, I2C, D, O> ChannelBus<'a, I2C, D, O> {
+    pub(crate) fn new(dev: &'a mut Xca954xaData<I2C, D, O>, channel: Channel) -> Self {
+        ChannelBus { dev, channel }
+    }
+}
+
+impl<'a, I2C, D, O> ChannelInfo for ChannelBus<'a, I2C, D, O> {
+    fn mux_address(&self) -> u8 {
+        self.dev.address
+    }
+
+    fn channel(&self) -> Channel {
+        self.channel
+    }
+}
+
+impl<'a, I2C, D, O, E> ehal::ErrorType for ChannelBus<'a, I2C, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    E: ehal::Error,
+{
+    type Error = Error<E>;
+}
+
+impl<'a, I2C, D, O, E> ehal::I2c for ChannelBus<'a, I2C, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    O: Observer,
+    E: ehal::Error,
+{
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [ehal::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.dev
+            .transfer(AccessKind::Transaction, u16::from(address), |i2c| {
+                i2c.transaction(address, operations)
+            })
+    }
+
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.dev
+            .transfer(AccessKind::Read, u16::from(address), |i2c| {
+                i2c.read(address, read)
+            })
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.dev
+            .transfer(AccessKind::Write, u16::from(address), |i2c| {
+                i2c.write(address, write)
+            })
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.dev
+            .transfer(AccessKind::WriteRead, u16::from(address), |i2c| {
+                i2c.write_read(address, write, read)
+            })
+    }
+}
+
+impl<I2C, D, O, E> Xca954xaData<I2C, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    /// Select `channels` at once, write `bytes` to `address` and restore the
+    /// previous channel selection.
+    pub(crate) fn broadcast_write(
+        &mut self,
+        channels: u8,
+        address: u8,
+        bytes: &[u8],
+    ) -> Result<(), Error<E>> {
+        let previous = self.current_channels()?;
+        self.select_device_channels(channels, false)?;
+        let result = self.transfer(AccessKind::Write, u16::from(address), |i2c| {
+            i2c.write(address, bytes)
+        });
+        let restore = self.select_device_channels(previous, false);
+        result.and(restore)
+    }
+
+    /// Select `channel` on its own, do a transfer to `address` and restore the
+    /// previous channel selection.
+    ///
+    /// Channels not present on the device are rejected, as selecting them
+    /// would leave the transfer on the upstream bus.
+    pub(crate) fn transfer_on(
+        &mut self,
+        channel: Channel,
+        kind: AccessKind,
+        address: u8,
+        transfer: impl FnOnce(&mut I2C) -> Result<(), E>,
+    ) -> Result<(), Error<E>> {
+        if channel.mask() & self.channel_mask == 0 {
+            return Err(Error::InvalidChannels(channel.mask()));
+        }
+        let previous = self.current_channels()?;
+        self.select_device_channels(channel.mask(), false)?;
+        let result = self.transfer(kind, u16::from(address), transfer);
+        let restore = self.select_device_channels(previous, false);
+        result.and(restore)
+    }
+
+    /// Select each of the `channels` on its own and run `f` with it,
+    /// restoring the previous channel selection afterwards.
+    pub(crate) fn for_each_channel(
+        &mut self,
+        channels: u8,
+        mut f: impl FnMut(Channel, &mut ChannelBus<'_, I2C, D, O>) -> Result<(), Error<E>>,
+    ) -> Result<(), Error<E>> {
+        if self.config.strict && channels & !self.channel_mask != 0 {
+            return Err(Error::InvalidChannels(channels));
+        }
+        let previous = self.current_channels()?;
+        let mut result = Ok(());
+        for channel in Channel::in_mask(channels & self.channel_mask) {
+            result = self
+                .select_device_channels(channel.mask(), false)
+                .and_then(|_| f(channel, &mut ChannelBus::new(self, channel)));
+            if result.is_err() {
+                break;
+            }
+        }
+        let restore = self.select_device_channels(previous, false);
+        result.and(restore)
+    }
+
+    /// Select each of the `channels` on its own and do a transfer to `address`
+    /// with `f` on it, collecting the result on each channel.
+    ///
+    /// The previous channel selection is restored afterwards. An error doing
+    /// so is collected as well instead of discarding the results.
+    pub(crate) fn on_each_channel(
+        &mut self,
+        channels: u8,
+        kind: AccessKind,
+        address: u8,
+        mut f: impl FnMut(Channel, &mut I2C) -> Result<(), E>,
+    ) -> Result<ChannelResults<E>, Error<E>> {
+        if self.config.strict && channels & !self.channel_mask != 0 {
+            return Err(Error::InvalidChannels(channels));
+        }
+        let previous = self.current_channels()?;
+        let mut results = ChannelResults::new();
+        for channel in Channel::in_mask(channels & self.channel_mask) {
+            let result = self
+                .select_device_channels(channel.mask(), false)
+                .and_then(|_| self.transfer(kind, u16::from(address), |i2c| f(channel, i2c)));
+            results.results[usize::from(channel.index())] = Some(result);
+        }
+        results.restore_error = self.select_device_channels(previous, false).err();
+        Ok(results)
+    }
+}
@@ -0,0 +1,445 @@
+use crate::codec::{self, Mask};
+#[cfg(feature = "history")]
+use crate::History;
+#[cfg(feature = "stats")]
+use crate::Stats;
+use crate::{
+    AccessContext, AccessKind, AddressError, Channel, Error, LastError, NoDelay, NoObserver,
+    Observer, SelectChannels, SlaveAddr, Xca954xaData, DEFAULT_ADDRESS,
+};
+use core::{cell, mem};
+use embedded_hal::{delay::DelayNs, i2c as ehal};
+
+/// Device in a multiplexer group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MuxSpec {
+    address: u8,
+    channel_mask: u8,
+}
+
+impl MuxSpec {
+    /// T/PCA9548A device with the given address.
+    ///
+    /// Addresses that do not fit in 7 bits or are reserved by the I²C
+    /// specification are rejected.
+    pub fn xca9548a(address: SlaveAddr) -> Result<Self, AddressError> {
+        Self::new(address, codec::XCA9548A_CHANNEL_MASK)
+    }
+
+    /// T/PCA9545A device with the given address.
+    ///
+    /// See [`xca9548a()`](#method.xca9548a).
+    pub fn xca9545a(address: SlaveAddr) -> Result<Self, AddressError> {
+        Self::new(address, codec::XCA9545A_CHANNEL_MASK)
+    }
+
+    /// T/PCA9543A device with the given address.
+    ///
+    /// See [`xca9548a()`](#method.xca9548a).
+    pub fn xca9543a(address: SlaveAddr) -> Result<Self, AddressError> {
+        Self::new(address, codec::XCA9543A_CHANNEL_MASK)
+    }
+
+    fn new(address: SlaveAddr, channel_mask: u8) -> Result<Self, AddressError> {
+        Ok(MuxSpec {
+            address: address.validated_address(DEFAULT_ADDRESS)?,
+            channel_mask,
+        })
+    }
+}
+
+/// Channel selection state of a device in a group
+#[derive(Debug, Clone, Copy)]
+struct MuxState {
+    spec: MuxSpec,
+    selected: u8,
+    /// Whether the selection may not match the device.
+    cache_dirty: bool,
+    /// Channels whose warm-up hook has been called.
+    warmed_up: u8,
+    /// Last error seen on each channel of the device.
+    last_errors: [Option<LastError>; 8],
+    #[cfg(feature = "history")]
+    history: History,
+    #[cfg(feature = "stats")]
+    stats: Stats,
+}
+
+#[derive(Debug)]
+struct GroupData<I2C, D, O, const N: usize> {
+    /// Channel selection logic shared by all devices. The state of each
+    /// device is swapped in around each access to it.
+    dev: Xca954xaData<I2C, D, O>,
+    muxes: [MuxState; N],
+    exclusive: bool,
+}
+
+impl<I2C, D, O, const N: usize> GroupData<I2C, D, O, N> {
+    /// Run `f` on the channel selection state of the device at index `mux`.
+    fn on_mux<R>(&mut self, mux: usize, f: impl FnOnce(&mut Xca954xaData<I2C, D, O>) -> R) -> R {
+        let state = &mut self.muxes[mux];
+        let dev = &mut self.dev;
+        dev.address = state.spec.address;
+        dev.channel_mask = state.spec.channel_mask;
+        dev.selected_channel_mask = state.selected;
+        dev.cache_dirty = state.cache_dirty;
+        dev.warmed_up = state.warmed_up;
+        Self::swap_diagnostics(state, dev);
+        let result = f(dev);
+        Self::swap_diagnostics(state, dev);
+        state.selected = dev.selected_channel_mask;
+        state.cache_dirty = dev.cache_dirty;
+        state.warmed_up = dev.warmed_up;
+        result
+    }
+
+    /// Exchange the diagnostics kept for each device with the ones of the
+    /// shared channel selection logic.
+    fn swap_diagnostics(state: &mut MuxState, dev: &mut Xca954xaData<I2C, D, O>) {
+        mem::swap(&mut state.last_errors, &mut dev.last_errors);
+        #[cfg(feature = "history")]
+        mem::swap(&mut state.history, &mut dev.history);
+        #[cfg(feature = "stats")]
+        mem::swap(&mut state.stats, &mut dev.stats);
+    }
+}
+
+impl<I2C, D, O, E, const N: usize> GroupData<I2C, D, O, N>
+where
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    fn select(&mut self, mux: usize, channels: u8) -> Result<(), Error<E>> {
+        if self.exclusive && channels.encode(self.muxes[mux].spec.channel_mask) != 0 {
+            for other in 0..N {
+                let state = self.muxes[other];
+                if other != mux && (state.cache_dirty || state.selected != 0) {
+                    self.on_mux(other, |dev| dev.select_channels(0))?;
+                }
+            }
+        }
+        self.on_mux(mux, |dev| dev.select_device_channels(channels, false))
+    }
+
+    fn transfer(
+        &mut self,
+        mux: usize,
+        channel: Channel,
+        kind: AccessKind,
+        address: u16,
+        transfer: impl FnOnce(&mut I2C) -> Result<(), E>,
+    ) -> Result<(), Error<E>> {
+        let state = self.muxes[mux];
+        if state.cache_dirty || state.selected != channel.mask() {
+            self.select(mux, channel.mask())?;
+        }
+        self.on_mux(mux, |dev| dev.transfer(kind, address, transfer))
+    }
+}
+
+/// Group of multiplexers on the same I²C bus
+///
+/// The group owns the bus, tracks the channel selection of all its devices
+/// and provides a virtual I²C bus for each channel of each device.
+/// See [`bus()`](#method.bus).
+///
+/// Channels are selected like on a single device: the observer hooks are
+/// called and the settle delay, the write verification and the strict mode
+/// apply to every device in the group.
+#[derive(Debug)]
+pub struct MuxGroup<I2C, const N: usize, D = NoDelay, O = NoObserver> {
+    data: cell::RefCell<GroupData<I2C, D, O, N>>,
+}
+
+impl<I2C, const N: usize> MuxGroup<I2C, N> {
+    /// Create a new group of the given devices.
+    ///
+    /// The channel selection of the devices is unknown until it is first
+    /// written, so the first selection on each device is always written.
+    /// Devices sharing an address are rejected with `AddressError::Duplicate`,
+    /// which is returned together with the I²C bus instance.
+    pub fn new(i2c: I2C, muxes: [MuxSpec; N]) -> Result<Self, (I2C, AddressError)> {
+        for (i, spec) in muxes.iter().enumerate() {
+            if muxes[..i].iter().any(|other| other.address == spec.address) {
+                return Err((i2c, AddressError::Duplicate(spec.address)));
+            }
+        }
+        let dev = Xca954xaData::new(i2c, DEFAULT_ADDRESS, 0, Default::default());
+        Ok(MuxGroup {
+            data: cell::RefCell::new(GroupData {
+                dev,
+                muxes: muxes.map(|spec| MuxState {
+                    spec,
+                    selected: 0,
+                    cache_dirty: true,
+                    warmed_up: 0,
+                    last_errors: [None; 8],
+                    #[cfg(feature = "history")]
+                    history: History::default(),
+                    #[cfg(feature = "stats")]
+                    stats: Stats::default(),
+                }),
+                exclusive: false,
+            }),
+        })
+    }
+}
+
+impl<I2C, O, const N: usize> MuxGroup<I2C, N, NoDelay, O> {
+    /// Use `delay` to wait `settle_ns` nanoseconds after each channel selection
+    /// on any device in the group.
+    ///
+    /// Deselecting all channels is not delayed.
+    pub fn with_settle_delay<D: DelayNs>(self, delay: D, settle_ns: u32) -> MuxGroup<I2C, N, D, O> {
+        let data = self.data.into_inner();
+        let (dev, _) = data.dev.with_delay(delay, settle_ns);
+        MuxGroup {
+            data: cell::RefCell::new(GroupData {
+                dev,
+                muxes: data.muxes,
+                exclusive: data.exclusive,
+            }),
+        }
+    }
+}
+
+impl<I2C, D, const N: usize> MuxGroup<I2C, N, D, NoObserver> {
+    /// Register an observer whose hooks are called around every channel
+    /// selection and transfer on any device in the group.
+    ///
+    /// See [`Observer`].
+    pub fn with_observer<O: Observer>(self, observer: O) -> MuxGroup<I2C, N, D, O> {
+        let data = self.data.into_inner();
+        let (dev, _) = data.dev.with_observer(observer);
+        MuxGroup {
+            data: cell::RefCell::new(GroupData {
+                dev,
+                muxes: data.muxes,
+                exclusive: data.exclusive,
+            }),
+        }
+    }
+}
+
+impl<I2C, D, O, const N: usize> MuxGroup<I2C, N, D, O> {
+    /// Enable the exclusive mode. (default: disabled)
+    ///
+    /// In exclusive mode, all other devices in the group are deselected before
+    /// selecting channels on a device. This prevents slaves with the same
+    /// address behind different devices from being connected at the same time.
+    pub fn set_exclusive(&mut self, exclusive: bool) {
+        self.data.get_mut().exclusive = exclusive;
+    }
+
+    /// Enable strict mode. (default: disabled)
+    ///
+    /// In strict mode, selecting channels that do not exist on a device
+    /// returns `Error::InvalidChannels` instead of ignoring them.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.data.get_mut().dev.config.strict = strict;
+    }
+
+    /// Enable the write verification. (default: disabled)
+    ///
+    /// The control register of a device is read back after each channel
+    /// selection on it and `Error::VerificationFailed` is returned if it
+    /// does not match.
+    pub fn set_verify_writes(&mut self, verify_writes: bool) {
+        self.data.get_mut().dev.config.verify_writes = verify_writes;
+    }
+
+    /// Destroy the group, return the I²C bus instance.
+    pub fn destroy(self) -> I2C {
+        self.data.into_inner().dev.i2c
+    }
+
+    /// Channels selected on each device according to the cache or `None`
+    /// if the selection of the device is unknown.
+    pub fn selected_channels(&self) -> [Option<u8>; N] {
+        self.data
+            .borrow()
+            .muxes
+            .map(|state| (!state.cache_dirty).then_some(state.selected))
+    }
+
+    /// Last error seen on `channel` of the device at index `mux`, if any.
+    ///
+    /// Returns `None` as well if there is no such device.
+    pub fn last_error(&self, mux: usize, channel: Channel) -> Option<LastError> {
+        let data = self.data.borrow();
+        data.muxes.get(mux)?.last_errors[usize::from(channel.index())]
+    }
+
+    /// Last operations done on the device at index `mux` or `None` if there
+    /// is no such device.
+    ///
+    /// Available with the `history` feature.
+    #[cfg(feature = "history")]
+    pub fn history(&self, mux: usize) -> Option<History> {
+        Some(self.data.borrow().muxes.get(mux)?.history)
+    }
+
+    /// Bus health counters of the device at index `mux` or `None` if there
+    /// is no such device.
+    ///
+    /// Available with the `stats` feature.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self, mux: usize) -> Option<Stats> {
+        Some(self.data.borrow().muxes.get(mux)?.stats)
+    }
+
+    /// Virtual I²C bus for `channel` of the device at index `mux`.
+    ///
+    /// Transfers through it select the channel on its device if necessary.
+    /// Returns `None` if there is no such device or channel.
+    pub fn bus(&self, mux: usize, channel: Channel) -> Option<GroupBus<'_, I2C, N, D, O>> {
+        let data = self.data.borrow();
+        let state = data.muxes.get(mux)?;
+        if !state.spec.channel_mask.contains(channel.index()) {
+            return None;
+        }
+        Some(GroupBus {
+            group: self,
+            mux,
+            channel,
+        })
+    }
+
+    /// Number of virtual channels in the group.
+    ///
+    /// This is the total number of channels of all devices.
+    pub fn virtual_channel_count(&self) -> usize {
+        let data = self.data.borrow();
+        data.muxes
+            .iter()
+            .map(|state| state.spec.channel_mask.count() as usize)
+            .sum()
+    }
+
+    /// Device index and channel of the virtual channel `index`.
+    ///
+    /// Virtual channels number all channels in the group consecutively,
+    /// device by device in the order given on creation, starting with the
+    /// lowest channel of the first device. For example, in a group of eight
+    /// T/PCA9548A devices virtual channel 10 is channel 2 of device 1.
+    /// Returns `None` if `index` is not lower than
+    /// [`virtual_channel_count()`](#method.virtual_channel_count).
+    pub fn locate_virtual_channel(&self, index: usize) -> Option<(usize, Channel)> {
+        let data = self.data.borrow();
+        let mut remaining = index;
+        for (mux, state) in data.muxes.iter().enumerate() {
+            let count = state.spec.channel_mask.count() as usize;
+            if remaining < count {
+                let channel = Channel::in_mask(state.spec.channel_mask).nth(remaining)?;
+                return Some((mux, channel));
+            }
+            remaining -= count;
+        }
+        None
+    }
+
+    /// Virtual I²C bus for the virtual channel `index`.
+    ///
+    /// See [`locate_virtual_channel()`](#method.locate_virtual_channel) for
+    /// the numbering. Returns `None` if there is no such virtual channel.
+    pub fn virtual_channel(&self, index: usize) -> Option<GroupBus<'_, I2C, N, D, O>> {
+        let (mux, channel) = self.locate_virtual_channel(index)?;
+        self.bus(mux, channel)
+    }
+}
+
+impl<I2C, D, O, E, const N: usize> MuxGroup<I2C, N, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    /// Select the channels of the device at index `mux`.
+    ///
+    /// Channels not present on the device are ignored or rejected in strict mode.
+    /// Returns `Error::NoSuchDevice` if there is no device at index `mux`.
+    pub fn select_channels(&mut self, mux: usize, channels: u8) -> Result<(), Error<E>> {
+        let data = self.data.get_mut();
+        if mux >= N {
+            return Err(Error::NoSuchDevice(mux));
+        }
+        data.select(mux, channels)
+    }
+}
+
+/// Virtual I²C bus of a channel of a device in a [`MuxGroup`]
+#[derive(Debug)]
+pub struct GroupBus<'a, I2C, const N: usize, D = NoDelay, O = NoObserver> {
+    group: &'a MuxGroup<I2C, N, D, O>,
+    mux: usize,
+    channel: Channel,
+}
+
+impl<'a, I2C, D, O, E, const N: usize> GroupBus<'a, I2C, N, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    fn transfer(
+        &mut self,
+        kind: AccessKind,
+        address: u8,
+        transfer: impl FnOnce(&mut I2C) -> Result<(), E>,
+    ) -> Result<(), Error<E>> {
+        let mut data =
+            self.group.data.try_borrow_mut().map_err(|_| {
+                Error::CouldNotAcquireDevice(AccessContext::part(kind, self.channel))
+            })?;
+        data.transfer(self.mux, self.channel, kind, u16::from(address), transfer)
+    }
+}
+
+impl<'a, I2C, D, O, E, const N: usize> ehal::ErrorType for GroupBus<'a, I2C, N, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    E: ehal::Error,
+{
+    type Error = Error<E>;
+}
+
+impl<'a, I2C, D, O, E, const N: usize> ehal::I2c for GroupBus<'a, I2C, N, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [ehal::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transfer(AccessKind::Transaction, address, |i2c| {
+            i2c.transaction(address, operations)
+        })
+    }
+
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.transfer(AccessKind::Read, address, |i2c| i2c.read(address, read))
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.transfer(AccessKind::Write, address, |i2c| i2c.write(address, write))
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.transfer(AccessKind::WriteRead, address, |i2c| {
+            i2c.write_read(address, write, read)
+        })
+    }
+}
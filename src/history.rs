@@ -0,0 +1,70 @@
+use crate::AccessKind;
+use embedded_hal::i2c::ErrorKind;
+
+/// Number of operations kept in the [`History`]
+pub const HISTORY_LEN: usize = 16;
+
+/// Operation recorded in the [`History`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// Channels selected during the operation.
+    ///
+    /// For channel selections, the channels requested.
+    pub channels: u8,
+    /// Slave address. For channel selections, the address of the switch.
+    pub address: u16,
+    /// Kind of operation
+    pub kind: AccessKind,
+    /// Error kind if the operation failed
+    pub error: Option<ErrorKind>,
+}
+
+/// Last operations done by the device, oldest first
+///
+/// Up to [`HISTORY_LEN`] channel selections and transfers are kept,
+/// overwriting the oldest ones. Available with the `history` feature.
+/// See [`history()`](struct.Xca9548a.html#method.history).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct History {
+    entries: [Option<HistoryEntry>; HISTORY_LEN],
+    /// Index where the next entry is written.
+    next: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            entries: [None; HISTORY_LEN],
+            next: 0,
+        }
+    }
+}
+
+impl History {
+    /// Recorded operations from the oldest to the most recent.
+    pub fn iter(&self) -> impl Iterator<Item = &HistoryEntry> + '_ {
+        let (recent, old) = self.entries.split_at(self.next);
+        old.iter().chain(recent.iter()).flatten()
+    }
+
+    /// Most recent operation, if any.
+    pub fn last(&self) -> Option<&HistoryEntry> {
+        let index = (self.next + HISTORY_LEN - 1) % HISTORY_LEN;
+        self.entries[index].as_ref()
+    }
+
+    /// Number of operations recorded.
+    pub fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+
+    /// Whether no operation has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.last().is_none()
+    }
+
+    pub(crate) fn push(&mut self, entry: HistoryEntry) {
+        self.entries[self.next] = Some(entry);
+        self.next = (self.next + 1) % HISTORY_LEN;
+    }
+}
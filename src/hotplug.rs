@@ -0,0 +1,130 @@
+use crate::{
+    AccessContext, AccessKind, Channel, DoOnAcquired, Error, Observer, ScanConfig, ScanDiff,
+    ScanReport,
+};
+use embedded_hal::{delay::DelayNs, i2c as ehal};
+
+/// Device appearing or disappearing on a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotPlugEvent {
+    /// A device started answering at `address` on `channel`.
+    Added {
+        /// Channel of the device
+        channel: Channel,
+        /// Address of the device
+        address: u8,
+    },
+    /// A device stopped answering at `address` on `channel`.
+    Removed {
+        /// Channel of the device
+        channel: Channel,
+        /// Address of the device
+        address: u8,
+    },
+}
+
+/// Hot-plug event callback
+type Callback<'a> = &'a mut dyn FnMut(HotPlugEvent);
+
+/// Hot-plug monitor
+///
+/// Rescans the switch periodically and reports devices appearing or
+/// disappearing on its channels to a callback. The first scan only
+/// records the devices present. Rescans can be paced with a delay, see
+/// [`wait_and_rescan()`](#method.wait_and_rescan), or by the application
+/// itself, e.g. with an async timer, see [`rescan()`](#method.rescan).
+pub struct HotPlugMonitor<'a> {
+    config: ScanConfig,
+    interval_ms: u32,
+    report: Option<ScanReport>,
+    callback: Callback<'a>,
+}
+
+impl<'a> HotPlugMonitor<'a> {
+    /// Create a new monitor rescanning all channels every `interval_ms`
+    /// milliseconds and reporting changes to `callback`.
+    pub fn new(interval_ms: u32, callback: Callback<'a>) -> Self {
+        HotPlugMonitor {
+            config: ScanConfig::default(),
+            interval_ms,
+            report: None,
+            callback,
+        }
+    }
+
+    /// Scan with the given configuration instead of all channels and addresses.
+    ///
+    /// The recorded devices are discarded.
+    pub fn set_scan_config(&mut self, config: ScanConfig) {
+        self.config = config;
+        self.report = None;
+    }
+
+    /// Set the interval between rescans in milliseconds.
+    pub fn set_interval_ms(&mut self, interval_ms: u32) {
+        self.interval_ms = interval_ms;
+    }
+
+    /// Devices found in the last scan, if any.
+    pub fn report(&self) -> Option<&ScanReport> {
+        self.report.as_ref()
+    }
+
+    /// Discard the recorded devices so that the next scan only records the
+    /// devices present again.
+    pub fn reset(&mut self) {
+        self.report = None;
+    }
+
+    /// Wait for the configured interval and rescan `switch`.
+    ///
+    /// See [`rescan()`](#method.rescan).
+    pub fn wait_and_rescan<DEV, I2C, E, D, O>(
+        &mut self,
+        switch: &mut DEV,
+        delay: &mut impl DelayNs,
+    ) -> Result<ScanDiff, Error<E>>
+    where
+        DEV: DoOnAcquired<I2C, Delay = D, Observer = O>,
+        I2C: ehal::I2c<Error = E>,
+        E: ehal::Error,
+        D: DelayNs,
+        O: Observer,
+    {
+        delay.delay_ms(self.interval_ms);
+        self.rescan(switch)
+    }
+
+    /// Rescan `switch` now and report the changes to the previous scan.
+    ///
+    /// The callback is called for each device removed and then for each
+    /// device added, channel by channel. Returns the changes, which are
+    /// empty on the first scan.
+    pub fn rescan<DEV, I2C, E, D, O>(&mut self, switch: &mut DEV) -> Result<ScanDiff, Error<E>>
+    where
+        DEV: DoOnAcquired<I2C, Delay = D, Observer = O>,
+        I2C: ehal::I2c<Error = E>,
+        E: ehal::Error,
+        D: DelayNs,
+        O: Observer,
+    {
+        let config = &self.config;
+        let report = switch
+            .do_on_acquired(AccessContext::device(AccessKind::Scan), |mut dev| {
+                dev.scan(config, &mut || ())
+            })?;
+        let diff = match self.report.replace(report) {
+            Some(previous) => previous.diff(&report),
+            None => ScanDiff::default(),
+        };
+        for channel in Channel::in_mask(diff.changed_channels()) {
+            for address in diff.removed(channel) {
+                (self.callback)(HotPlugEvent::Removed { channel, address });
+            }
+            for address in diff.added(channel) {
+                (self.callback)(HotPlugEvent::Added { channel, address });
+            }
+        }
+        Ok(diff)
+    }
+}
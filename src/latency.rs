@@ -0,0 +1,193 @@
+use crate::{AccessKind, Channel, Observer};
+use embedded_hal::i2c::ErrorKind;
+
+/// Number of buckets in the latency histogram
+pub const LATENCY_BUCKETS: usize = 16;
+
+/// Monotonic timestamp source
+///
+/// The unit of the ticks is up to the implementation (e.g. microseconds).
+/// The counter may wrap around.
+pub trait TimestampSource {
+    /// Current time in ticks.
+    fn now(&mut self) -> u32;
+}
+
+/// Latency statistics of one kind of operation
+///
+/// Bucket `i` of the histogram counts the operations that took less than
+/// `2^(i+1)` ticks and at least `2^i` ticks, except for bucket 0, which also
+/// counts operations that took 0 ticks, and the last bucket, which also
+/// counts all longer operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latency {
+    count: u32,
+    min: u32,
+    max: u32,
+    total: u64,
+    histogram: [u32; LATENCY_BUCKETS],
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Latency {
+            count: 0,
+            min: u32::MAX,
+            max: 0,
+            total: 0,
+            histogram: [0; LATENCY_BUCKETS],
+        }
+    }
+}
+
+impl Latency {
+    /// Number of operations measured.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Shortest latency in ticks, if any operation was measured.
+    pub fn min(&self) -> Option<u32> {
+        (self.count != 0).then_some(self.min)
+    }
+
+    /// Longest latency in ticks, if any operation was measured.
+    pub fn max(&self) -> Option<u32> {
+        (self.count != 0).then_some(self.max)
+    }
+
+    /// Average latency in ticks, if any operation was measured.
+    pub fn mean(&self) -> Option<u32> {
+        (self.count != 0).then(|| (self.total / u64::from(self.count)) as u32)
+    }
+
+    /// Latency histogram with logarithmic buckets.
+    pub fn histogram(&self) -> &[u32; LATENCY_BUCKETS] {
+        &self.histogram
+    }
+
+    fn add(&mut self, ticks: u32) {
+        self.count = self.count.saturating_add(1);
+        self.min = self.min.min(ticks);
+        self.max = self.max.max(ticks);
+        self.total = self.total.saturating_add(u64::from(ticks));
+        let bucket = (ticks.max(1).ilog2() as usize).min(LATENCY_BUCKETS - 1);
+        self.histogram[bucket] = self.histogram[bucket].saturating_add(1);
+    }
+}
+
+/// Observer measuring the latency of the operations per channel
+///
+/// Register it with [`with_observer()`](struct.Xca9548a.html#method.with_observer)
+/// and read the results through [`observer_mut()`](struct.Xca9548a.html#method.observer_mut).
+/// This helps pinpoint a slow downstream segment (e.g. clock stretching or a long cable).
+///
+/// ```
+/// use embedded_hal::i2c::I2c;
+/// use xca9548a::{Channel, LatencyRecorder, SlaveAddr, TimestampSource, Xca9548a};
+/// # use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+/// # let dev = I2cMock::new(&[
+/// #     I2cTrans::write(0x70, vec![0x01]),
+/// #     I2cTrans::read(0x48, vec![0x12, 0x34]),
+/// # ]);
+///
+/// struct Timer(u32);
+///
+/// impl TimestampSource for Timer {
+///     fn now(&mut self) -> u32 {
+///         self.0 += 10;
+///         self.0
+///     }
+/// }
+///
+/// let mut switch = Xca9548a::new(dev, SlaveAddr::default())
+///     .with_observer(LatencyRecorder::new(Timer(0)));
+/// let mut temperature = [0; 2];
+/// switch.split().i2c0.read(0x48, &mut temperature).unwrap();
+/// let latency = switch.observer_mut().channel(Channel::Ch0);
+/// assert_eq!(Some(10), latency.max());
+/// # switch.destroy().done();
+/// ```
+#[derive(Debug)]
+pub struct LatencyRecorder<T> {
+    source: T,
+    start: u32,
+    channels: [Latency; 8],
+    upstream: Latency,
+    selections: Latency,
+}
+
+impl<T: TimestampSource> LatencyRecorder<T> {
+    /// Create a new recorder using the given timestamp source.
+    pub fn new(source: T) -> Self {
+        LatencyRecorder {
+            source,
+            start: 0,
+            channels: [Latency::default(); 8],
+            upstream: Latency::default(),
+            selections: Latency::default(),
+        }
+    }
+
+    /// Latency of the transfers done with `channel` selected.
+    pub fn channel(&self, channel: Channel) -> &Latency {
+        &self.channels[usize::from(channel.index())]
+    }
+
+    /// Latency of the transfers done with no channel selected.
+    pub fn upstream(&self) -> &Latency {
+        &self.upstream
+    }
+
+    /// Latency of the channel selections.
+    pub fn selections(&self) -> &Latency {
+        &self.selections
+    }
+
+    /// Clear all the measurements.
+    pub fn reset(&mut self) {
+        self.channels = [Latency::default(); 8];
+        self.upstream = Latency::default();
+        self.selections = Latency::default();
+    }
+
+    /// Destroy the recorder, returning the timestamp source.
+    pub fn release(self) -> T {
+        self.source
+    }
+
+    fn elapsed(&mut self) -> u32 {
+        self.source.now().wrapping_sub(self.start)
+    }
+}
+
+impl<T: TimestampSource> Observer for LatencyRecorder<T> {
+    fn before_select(&mut self, _channels: u8) {
+        self.start = self.source.now();
+    }
+
+    fn after_select(&mut self, _channels: u8, _result: Result<(), ErrorKind>) {
+        let ticks = self.elapsed();
+        self.selections.add(ticks);
+    }
+
+    fn before_transfer(&mut self, _channels: u8, _address: u16, _kind: AccessKind) {
+        self.start = self.source.now();
+    }
+
+    fn after_transfer(
+        &mut self,
+        channels: u8,
+        _address: u16,
+        _kind: AccessKind,
+        _result: Result<(), ErrorKind>,
+    ) {
+        let ticks = self.elapsed();
+        if channels == 0 {
+            self.upstream.add(ticks);
+        }
+        for channel in Channel::in_mask(channels) {
+            self.channels[usize::from(channel.index())].add(ticks);
+        }
+    }
+}
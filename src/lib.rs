@@ -7,9 +7,16 @@
 //! - Enable one or multiple I2C channels. See [`select_channels()`].
 //! - Communicate with the slaves connected to the enabled channels transparently.
 //! - Split the device into slave (virtual) I2C devices (one per channel). See: [`split()`].
+//! - Scan all channels for connected slave devices. See: [`scan_all()`].
+//! - Test the control register at power-on. See: [`self_test()`].
+//! - Declare trees of cascaded multiplexers. See: [`mux_tree!`].
+//! - Split the device into named slave I2C devices for the channels in use. See: [`split!`].
+//! - Observe channel selections and transfers for logging or metrics. See: [`Observer`].
 //!
 //! [`select_channels()`]: struct.Xca9548a.html#method.select_channels
 //! [`split()`]: struct.Xca9548a.html#method.split
+//! [`scan_all()`]: struct.Xca9548a.html#method.scan_all
+//! [`self_test()`]: struct.Xca9548a.html#method.self_test
 //!
 //! ## The devices
 //!
@@ -34,6 +41,35 @@
 //! - [TCA9543A](http://www.ti.com/lit/ds/symlink/tca9543a.pdf)
 //! - [PCA9543A](http://www.ti.com/lit/ds/symlink/pca9543a.pdf)
 //!
+//! ## Features
+//!
+//! - `std`: Link against the standard library. The [`Error`] type implements
+//!   `std::error::Error` whenever the I²C bus error does, with `source()` returning
+//!   the bus error, so it can be propagated into `Box<dyn std::error::Error>`.
+//!   The `record` module provides recording and replaying of the bus traffic.
+//! - `bench`: Benchmark harness driving the devices against a simulated bus.
+//!   See the `bench` module. Implies `std`.
+//! - `history`: Keep the last operations done by the device in a ring buffer
+//!   for post-mortem debugging. See `history()`.
+//! - `stats`: Count channel switches, transfers and errors per channel and
+//!   accesses rejected because the device was in use. See `stats()`.
+//! - `test-util`: Utilities for testing drivers of downstream devices without
+//!   hardware, like the simulated device `SimXca9548a` and the `embedded-hal-mock`
+//!   expectation builder `Expectations`, as well as accessors to the internal
+//!   state of the driver like `cached_channels()`. Implies `std`.
+//! - `linux`: Detect the device address on a Linux I²C adapter with
+//!   `autodetect()`. Implies `std`.
+//! - `cli`: Build the `xca954xa` command line tool for Linux, which finds the
+//!   switches on an I²C adapter, selects channels and prints the devices
+//!   found on each channel. Implies `linux`.
+//! - `nb`: Variants of channel selection and status reads with `nb`
+//!   signatures like `try_select_channels()`, for superloop firmware built
+//!   around `nb`. The I²C transfers themselves are still blocking.
+//! - `ten-bit-address`: Implement `I2c<TenBitAddress>` for the devices and the
+//!   split parts when the I²C bus supports 10-bit addresses. If the I²C bus
+//!   implements both address modes, untyped integer literal addresses then
+//!   need a type annotation (e.g. `let address: u8 = 0x20;`).
+//!
 //! ## Usage examples (see also examples folder)
 //!
 //! To use this driver, import this crate and an `embedded_hal` implementation,
@@ -69,6 +105,21 @@
 //! let mut i2c_switch = Xca9548a::new(dev, address);
 //! ```
 //!
+//! ### Configuring the device with the builder
+//!
+//! ```no_run
+//! use linux_embedded_hal::I2cdev;
+//! use xca9548a::{CachePolicy, SlaveAddr, Xca9548a};
+//!
+//! let dev = I2cdev::new("/dev/i2c-1").unwrap();
+//! let i2c_switch = Xca9548a::builder()
+//!     .address(SlaveAddr::Alternative(false, false, true))
+//!     .initial_channels(0b0000_0001)
+//!     .cache_policy(CachePolicy::AlwaysSelect)
+//!     .build(dev)
+//!     .unwrap_or_else(|(_dev, error)| panic!("{}", error));
+//! ```
+//!
 //! ### Selecting channel 0 (SD0/SC0 pins)
 //!
 //! ```no_run
@@ -92,7 +143,7 @@
 //! let mut i2c_switch = Xca9548a::new(dev, address);
 //! i2c_switch.select_channels(0b0000_0001).unwrap();
 //!
-//! let slave_address = 0b010_0000; // example slave address
+//! let slave_address: u8 = 0b010_0000; // example slave address
 //! let data_for_slave = [0b0101_0101, 0b1010_1010]; // some data to be sent
 //!
 //! // Read some data from a slave connected to channel 0 using the
@@ -152,7 +203,7 @@
 //! let i2c_switch = Xca9548a::new(dev, address);
 //! let mut parts = i2c_switch.split();
 //!
-//! let slave_address = 0x20;
+//! let slave_address: u8 = 0x20;
 //! let data_for_slave = [0xAB, 0xCD];
 //!
 //! // Write some data to the slave using normal I2C interface
@@ -163,29 +214,143 @@
 //! parts.i2c1.read(slave_address, &mut read_data).unwrap();
 //! ```
 //!
+//! ### Cascading multiplexers
+//!
+//! A multiplexer can be connected behind a channel of another multiplexer by
+//! passing it a split part of the upstream one. The whole path is then selected
+//! automatically and the sibling channels of the upstream multiplexer are deselected.
+//!
+//! ```no_run
+//! use embedded_hal::i2c::I2c;
+//! use linux_embedded_hal::I2cdev;
+//! use xca9548a::{Channel, ChannelPath, SlaveAddr, Xca9543a, Xca9548a};
+//!
+//! let dev = I2cdev::new("/dev/i2c-1").unwrap();
+//! let upstream = Xca9548a::new(dev, SlaveAddr::default());
+//! let parts = upstream.split();
+//! let address = SlaveAddr::Alternative(false, false, true);
+//! let mut downstream = Xca9543a::new(parts.i2c3, address);
+//! downstream
+//!     .select_path(ChannelPath::new(Channel::Ch3, Channel::Ch1))
+//!     .unwrap();
+//!
+//! let mut leaves = downstream.split();
+//! let slave_address: u8 = 0x20;
+//! leaves.i2c0.write(slave_address, &[0xAB, 0xCD]).unwrap();
+//! ```
+//!
+//! ### Propagating errors into `Box<dyn std::error::Error>`
+//!
+//! ```no_run
+//! use linux_embedded_hal::I2cdev;
+//! use xca9548a::{Xca9548a, SlaveAddr};
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let dev = I2cdev::new("/dev/i2c-1")?;
+//!     let mut i2c_switch = Xca9548a::new(dev, SlaveAddr::default());
+//!     i2c_switch.select_channels(0b0000_0001)?;
+//!     Ok(())
+//! }
+//! ```
+//!
 
 #![deny(unsafe_code)]
 #![deny(missing_docs)]
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 
-const DEVICE_BASE_ADDRESS: u8 = 0b111_0000;
+/// Default slave address of the devices (A2, A1 and A0 pins low)
+pub const DEFAULT_ADDRESS: u8 = 0b111_0000;
+pub mod codec;
 mod types;
-pub use types::{Error, SlaveAddr, Xca9543a, Xca9545a, Xca9548a};
+pub use types::{
+    AccessContext, AccessKind, AddressError, Channel, ChannelMask, ChannelPath, DeviceInfo,
+    DeviceState, DeviceType, Error, LastError, NoDelay, Parked, SlaveAddr, Watchdog, Xca9543a,
+    Xca9545a, Xca9548a,
+};
 mod device_impl;
 pub use device_impl::{DoOnAcquired, SelectChannels, Xca954xaData};
 mod parts;
-pub use crate::parts::{I2cSlave, Parts, Parts2, Parts4};
+pub use crate::parts::{ChannelInfo, I2cSlave, Parts, Parts2, Parts4, UpstreamBus};
+mod fanout;
+pub use crate::fanout::{ChannelBus, ChannelResults};
+mod observer;
+pub use crate::observer::{NoObserver, Observer};
+mod latency;
+pub use crate::latency::{Latency, LatencyRecorder, TimestampSource, LATENCY_BUCKETS};
+mod poller;
+pub use crate::poller::Poller;
+mod scan;
+pub use crate::scan::{ScanConfig, ScanDiff, ScanReport};
+mod hotplug;
+pub use crate::hotplug::{HotPlugEvent, HotPlugMonitor};
+mod selftest;
+pub use crate::selftest::SelfTestReport;
+mod persist;
+pub use crate::persist::StateStorage;
+mod lock;
+pub use crate::lock::ChannelGuard;
+mod sequence;
+pub use crate::sequence::Step;
+mod raw;
+pub use crate::raw::select_channels_raw;
+mod failover;
+pub use crate::failover::{Active, Failover, FailoverBus};
+mod adapter;
+pub use crate::adapter::ErrorAdapter;
+mod builder;
+pub use crate::builder::{Builder, CachePolicy, MismatchPolicy, RetryPolicy};
+#[cfg(feature = "bench")]
+pub mod bench;
+mod group;
+#[cfg(feature = "history")]
+mod history;
+#[cfg(feature = "std")]
+pub mod record;
+#[cfg(feature = "history")]
+pub use crate::history::{History, HistoryEntry, HISTORY_LEN};
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(feature = "stats")]
+pub use crate::stats::Stats;
+#[cfg(feature = "trace")]
+mod trace;
+#[cfg(feature = "trace")]
+pub use crate::trace::Tracer;
+#[cfg(feature = "linux")]
+mod linux;
+#[cfg(feature = "nb")]
+mod nonblocking;
+#[cfg(feature = "linux")]
+pub use crate::linux::AutodetectError;
+#[cfg(feature = "test-util")]
+mod sim;
+#[cfg(feature = "test-util")]
+pub use crate::sim::SimXca9548a;
+#[cfg(feature = "test-util")]
+mod expect;
+#[cfg(feature = "test-util")]
+pub use crate::expect::Expectations;
+mod named;
+mod tree;
+pub use crate::group::{GroupBus, MuxGroup, MuxSpec};
+mod topology;
+pub use crate::topology::{Conflict, Topology};
+mod routing;
+pub use crate::routing::{Route, RoutingError, RoutingTable};
+mod router;
+pub use crate::router::Router;
 
 mod private {
     use super::*;
 
     pub trait Sealed {}
-    impl<I2C> Sealed for Xca954xaData<I2C> {}
-    impl<I2C> Sealed for Xca9548a<I2C> {}
-    impl<I2C> Sealed for Xca9543a<I2C> {}
-    impl<I2C> Sealed for Xca9545a<I2C> {}
+    impl<I2C, D, O> Sealed for Xca954xaData<I2C, D, O> {}
+    impl<I2C, D, O> Sealed for Xca9548a<I2C, D, O> {}
+    impl<I2C, D, O> Sealed for Xca9543a<I2C, D, O> {}
+    impl<I2C, D, O> Sealed for Xca9545a<I2C, D, O> {}
     impl<'a, DEV, I2C> Sealed for Parts<'a, DEV, I2C> {}
     impl<'a, DEV, I2C> Sealed for Parts2<'a, DEV, I2C> {}
     impl<'a, DEV, I2C> Sealed for Parts4<'a, DEV, I2C> {}
-    impl<'a, DEV, I2C> Sealed for I2cSlave<'a, DEV, I2C> {}
+    impl<'a, DEV, I2C, T> Sealed for I2cSlave<'a, DEV, I2C, T> {}
+    impl<'a, DEV, I2C> Sealed for UpstreamBus<'a, DEV, I2C> {}
 }
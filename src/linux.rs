@@ -0,0 +1,81 @@
+use crate::{SlaveAddr, Xca9543a, Xca9545a, Xca9548a};
+use embedded_hal::i2c::I2c;
+use linux_embedded_hal::{i2cdev::linux::LinuxI2CError, I2cdev};
+use std::path::Path;
+
+/// Addresses that can be strapped on the devices with the A0-A2 pins.
+const AUTODETECT_ADDRESSES: core::ops::RangeInclusive<u8> = 0x70..=0x77;
+
+/// Errors detecting a device on a Linux I²C adapter
+#[derive(Debug)]
+pub enum AutodetectError {
+    /// The I²C adapter could not be opened.
+    Open(LinuxI2CError),
+    /// No matching device answered in the 0x70-0x77 range.
+    NotFound,
+}
+
+impl core::fmt::Display for AutodetectError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            AutodetectError::Open(e) => write!(f, "Could not open I²C adapter: {}", e),
+            AutodetectError::NotFound => write!(f, "No device found at 0x70-0x77"),
+        }
+    }
+}
+
+impl std::error::Error for AutodetectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AutodetectError::Open(e) => Some(e),
+            AutodetectError::NotFound => None,
+        }
+    }
+}
+
+/// Open the adapter at `path` and return it together with the first address
+/// in the 0x70-0x77 range answering a read and accepted by `select`.
+fn probe_adapter(
+    path: &Path,
+    mut select: impl FnMut(u8) -> bool,
+) -> Result<(I2cdev, u8), AutodetectError> {
+    let mut i2c = I2cdev::new(path).map_err(AutodetectError::Open)?;
+    let address = AUTODETECT_ADDRESSES
+        .filter(|address| i2c.read(*address, &mut [0]).is_ok())
+        .find(|address| select(*address))
+        .ok_or(AutodetectError::NotFound)?;
+    Ok((i2c, address))
+}
+
+macro_rules! impl_autodetect {
+    ( $name:ident ) => {
+        impl $name<I2cdev> {
+            /// Open the Linux I²C adapter at `path` (e.g. `/dev/i2c-1`) and create
+            /// the driver for the first device answering in the 0x70-0x77 range.
+            ///
+            /// This is useful when the address strapping is not known at build time.
+            /// The addresses are probed with one-byte reads, which only read the
+            /// control register of the devices. Available with the `linux` feature.
+            pub fn autodetect<P: AsRef<Path>>(path: P) -> Result<Self, AutodetectError> {
+                Self::autodetect_with(path, |_| true)
+            }
+
+            /// Open the Linux I²C adapter at `path` and create the driver for the
+            /// first device answering in the 0x70-0x77 range whose address is
+            /// accepted by `select`.
+            ///
+            /// See [`autodetect()`](#method.autodetect).
+            pub fn autodetect_with<P: AsRef<Path>>(
+                path: P,
+                select: impl FnMut(u8) -> bool,
+            ) -> Result<Self, AutodetectError> {
+                let (i2c, address) = probe_adapter(path.as_ref(), select)?;
+                Ok(Self::new(i2c, SlaveAddr::Raw(address)))
+            }
+        }
+    };
+}
+
+impl_autodetect!(Xca9548a);
+impl_autodetect!(Xca9543a);
+impl_autodetect!(Xca9545a);
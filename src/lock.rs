@@ -0,0 +1,144 @@
+use crate::{AccessKind, Channel, ChannelInfo, Error, Observer, Xca954xaData};
+use core::cell;
+use embedded_hal::{delay::DelayNs, i2c as ehal};
+
+/// Device whose borrow is held by a [`ChannelGuard`]
+pub(crate) trait Lockable {
+    /// Current generation of the split parts of the device.
+    fn generation(&self) -> u32;
+    /// Finish an access once the borrow of the device data has been released.
+    fn release(&self);
+}
+
+/// Exclusive access to a channel of a device
+///
+/// Returned by `lock()` on the device driver types. The device stays acquired
+/// for the lifetime of the guard, so no split part or other user of the device
+/// can interleave transfers during a multi-step sequence, like an EEPROM page
+/// write followed by acknowledge polling. Other accesses fail with
+/// `Error::CouldNotAcquireDevice` in the meantime.
+///
+/// Transfers behave like those done through the split part of the channel.
+/// Like the split parts, the guard becomes stale when the parts are
+/// invalidated, e.g. by splitting the device again or by a reset through the
+/// RESET pin. Transfers through it then fail with `Error::StaleHandle`.
+pub struct ChannelGuard<'a, I2C, D, O> {
+    /// Only taken when the guard is dropped.
+    dev: Option<cell::RefMut<'a, Xca954xaData<I2C, D, O>>>,
+    device: &'a dyn Lockable,
+    address: u8,
+    channel: Channel,
+    /// Generation of the device when the guard was created.
+    generation: u32,
+}
+
+impl<'a, I2C, D, O> ChannelGuard<'a, I2C, D, O> {
+    pub(crate) fn new(
+        dev: cell::RefMut<'a, Xca954xaData<I2C, D, O>>,
+        device: &'a dyn Lockable,
+        channel: Channel,
+    ) -> Self {
+        ChannelGuard {
+            address: dev.address,
+            dev: Some(dev),
+            device,
+            channel,
+            generation: device.generation(),
+        }
+    }
+
+    /// Channel locked by this guard.
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// Device data if the guard is not stale.
+    pub(crate) fn dev<E: core::fmt::Debug>(
+        &mut self,
+    ) -> Result<&mut Xca954xaData<I2C, D, O>, Error<E>> {
+        match self.dev.as_deref_mut() {
+            Some(dev) if !dev.parts_stale && self.device.generation() == self.generation => Ok(dev),
+            _ => Err(Error::StaleHandle(self.channel)),
+        }
+    }
+}
+
+impl<'a, I2C, D, O> Drop for ChannelGuard<'a, I2C, D, O> {
+    fn drop(&mut self) {
+        self.dev = None;
+        self.device.release();
+    }
+}
+
+impl<'a, I2C, D, O> ChannelInfo for ChannelGuard<'a, I2C, D, O> {
+    fn mux_address(&self) -> u8 {
+        self.address
+    }
+
+    fn channel(&self) -> Channel {
+        self.channel
+    }
+}
+
+impl<'a, I2C, D, O, E> ehal::ErrorType for ChannelGuard<'a, I2C, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    E: ehal::Error,
+{
+    type Error = Error<E>;
+}
+
+impl<'a, I2C, D, O, E> ehal::I2c for ChannelGuard<'a, I2C, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    #[inline]
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [ehal::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let channel = self.channel;
+        self.dev()?.part_transfer(
+            channel,
+            AccessKind::Transaction,
+            u16::from(address),
+            |i2c| i2c.transaction(address, operations),
+        )
+    }
+
+    #[inline]
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        let channel = self.channel;
+        self.dev()?
+            .part_transfer(channel, AccessKind::Read, u16::from(address), |i2c| {
+                i2c.read(address, read)
+            })
+    }
+
+    #[inline]
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        let channel = self.channel;
+        self.dev()?
+            .part_transfer(channel, AccessKind::Write, u16::from(address), |i2c| {
+                i2c.write(address, write)
+            })
+    }
+
+    #[inline]
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let channel = self.channel;
+        self.dev()?
+            .part_transfer(channel, AccessKind::WriteRead, u16::from(address), |i2c| {
+                i2c.write_read(address, write, read)
+            })
+    }
+}
@@ -0,0 +1,43 @@
+/// Split a device into a struct with named fields for just the channels in use.
+///
+/// Each field is the split I2C device of the channel with the given index.
+/// Channels not present on the device are rejected at compile time, as with
+/// [`part()`](struct.Xca9548a.html#method.part), as are duplicate names.
+///
+/// ```
+/// use embedded_hal::i2c::I2c;
+/// use xca9548a::{split, SlaveAddr, Xca9548a};
+/// # use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+/// # let dev = I2cMock::new(&[
+/// #     I2cTrans::write(0x70, vec![0x08]),
+/// #     I2cTrans::read(0x48, vec![0x12, 0x34]),
+/// # ]);
+///
+/// let switch = Xca9548a::new(dev, SlaveAddr::default());
+/// let mut buses = split!(switch => { temp_bus: 3, imu_bus: 5 });
+/// let mut temperature = [0; 2];
+/// buses.temp_bus.read(0x48, &mut temperature).unwrap();
+/// # drop(buses);
+/// # switch.destroy().done();
+/// ```
+///
+/// ```compile_fail
+/// use embedded_hal_mock::eh1::i2c::Mock as I2cMock;
+/// use xca9548a::{split, SlaveAddr, Xca9543a};
+///
+/// let switch = Xca9543a::new(I2cMock::new(&[]), SlaveAddr::default());
+/// let buses = split!(switch => { sensor: 5 });
+/// ```
+#[macro_export]
+macro_rules! split {
+    ( $dev:expr => { $( $name:ident : $channel:literal ),+ $(,)? } ) => {{
+        /// Slave I2C devices of the channels in use
+        struct NamedParts<P> {
+            $( $name: P, )+
+        }
+        let dev = &$dev;
+        NamedParts {
+            $( $name: dev.part::<$channel>(), )+
+        }
+    }};
+}
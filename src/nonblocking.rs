@@ -0,0 +1,64 @@
+use crate::{
+    AccessContext, AccessKind, DoOnAcquired, Error, Observer, Xca9543a, Xca9545a, Xca9548a,
+};
+use embedded_hal::{delay::DelayNs, i2c as ehal};
+
+/// Report all errors as `nb::Error::Other`.
+///
+/// A device busy with another transfer is not released while the caller
+/// polls it from the same context, so `CouldNotAcquireDevice` is not
+/// reported as `WouldBlock`, which would make `nb::block!` spin forever.
+fn into_nb<T, E: core::fmt::Debug>(result: Result<T, Error<E>>) -> nb::Result<T, Error<E>> {
+    result.map_err(nb::Error::Other)
+}
+
+macro_rules! impl_nonblocking {
+    ( $name:ident ) => {
+        impl<I2C, D, O, E> $name<I2C, D, O>
+        where
+            I2C: ehal::I2c<Error = E>,
+            D: DelayNs,
+            O: Observer,
+            E: ehal::Error,
+        {
+            /// Variant of `select_channels()` with an `nb` signature.
+            ///
+            /// The I²C transfer itself is blocking. If the device is busy with
+            /// another transfer, e.g. through one of the parts, this fails with
+            /// `nb::Error::Other(Error::CouldNotAcquireDevice)`.
+            pub fn try_select_channels(&self, channels: u8) -> nb::Result<(), Error<E>> {
+                into_nb(self.do_on_acquired(
+                    AccessContext::device(AccessKind::SelectChannels),
+                    |mut dev| dev.select_device_channels(channels, false),
+                ))
+            }
+
+            /// Variant of `get_channel_status()` with an `nb` signature.
+            ///
+            /// See [`try_select_channels()`](#method.try_select_channels).
+            pub fn try_get_channel_status(&self) -> nb::Result<u8, Error<E>> {
+                into_nb(self.get_channel_status())
+            }
+        }
+    };
+    ( $name:ident, interrupts ) => {
+        impl_nonblocking!($name);
+
+        impl<I2C, D, O, E> $name<I2C, D, O>
+        where
+            I2C: ehal::I2c<Error = E>,
+            E: ehal::Error,
+        {
+            /// Variant of `get_interrupt_status()` with an `nb` signature.
+            ///
+            /// See [`try_select_channels()`](#method.try_select_channels).
+            pub fn try_get_interrupt_status(&self) -> nb::Result<u8, Error<E>> {
+                into_nb(self.get_interrupt_status())
+            }
+        }
+    };
+}
+
+impl_nonblocking!(Xca9548a);
+impl_nonblocking!(Xca9543a, interrupts);
+impl_nonblocking!(Xca9545a, interrupts);
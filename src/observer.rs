@@ -0,0 +1,113 @@
+use crate::{AccessKind, Channel};
+use embedded_hal::i2c::{ErrorKind, I2c};
+
+/// Hooks called around channel selections and transfers
+///
+/// Implement this to add custom logging, LED indication or metrics.
+/// All methods do nothing by default.
+/// See [`with_observer()`](struct.Xca9548a.html#method.with_observer).
+///
+/// ```
+/// use xca9548a::{AccessKind, Observer};
+/// use embedded_hal::i2c::ErrorKind;
+///
+/// #[derive(Default)]
+/// struct ErrorCounter(u32);
+///
+/// impl Observer for ErrorCounter {
+///     fn after_transfer(
+///         &mut self,
+///         _channels: u8,
+///         _address: u16,
+///         _kind: AccessKind,
+///         result: Result<(), ErrorKind>,
+///     ) {
+///         if result.is_err() {
+///             self.0 += 1;
+///         }
+///     }
+/// }
+/// ```
+pub trait Observer {
+    /// Called before writing `channels` to the control register.
+    fn before_select(&mut self, _channels: u8) {}
+
+    /// Called after writing `channels` to the control register.
+    fn after_select(&mut self, _channels: u8, _result: Result<(), ErrorKind>) {}
+
+    /// Called before a transfer to `address` with `channels` selected.
+    fn before_transfer(&mut self, _channels: u8, _address: u16, _kind: AccessKind) {}
+
+    /// Called after a transfer to `address` with `channels` selected.
+    fn after_transfer(
+        &mut self,
+        _channels: u8,
+        _address: u16,
+        _kind: AccessKind,
+        _result: Result<(), ErrorKind>,
+    ) {
+    }
+
+    /// Called the first time `channel` is selected since the construction of
+    /// the driver or the last reset, with the channel selected.
+    ///
+    /// This allows preparing the downstream segment, e.g. enabling an LDO or
+    /// configuring a bus repeater through `i2c`. If several channels are selected
+    /// for the first time at once, this is called for each of them with all of
+    /// them selected. An error is returned from the channel selection and the
+    /// hook is called again on the next selection of the channel.
+    fn warm_up<I2C: I2c>(&mut self, _channel: Channel, _i2c: &mut I2C) -> Result<(), I2C::Error> {
+        Ok(())
+    }
+
+    /// Called when this switch takes over from the primary switch at
+    /// `failed_address` in a [`Failover`](crate::Failover) pair, before
+    /// retrying the failed transfer.
+    fn failover(&mut self, _failed_address: u8) {}
+
+    /// Called when the control register reads `actual` instead of the
+    /// `expected` channels with `MismatchPolicy::Notify`.
+    fn mismatch(&mut self, _expected: u8, _actual: u8) {}
+}
+
+/// Observer doing nothing. Used when no observer is registered.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NoObserver;
+
+impl Observer for NoObserver {}
+
+impl<T: Observer + ?Sized> Observer for &mut T {
+    fn before_select(&mut self, channels: u8) {
+        T::before_select(self, channels)
+    }
+
+    fn after_select(&mut self, channels: u8, result: Result<(), ErrorKind>) {
+        T::after_select(self, channels, result)
+    }
+
+    fn before_transfer(&mut self, channels: u8, address: u16, kind: AccessKind) {
+        T::before_transfer(self, channels, address, kind)
+    }
+
+    fn after_transfer(
+        &mut self,
+        channels: u8,
+        address: u16,
+        kind: AccessKind,
+        result: Result<(), ErrorKind>,
+    ) {
+        T::after_transfer(self, channels, address, kind, result)
+    }
+
+    fn warm_up<I2C: I2c>(&mut self, channel: Channel, i2c: &mut I2C) -> Result<(), I2C::Error> {
+        T::warm_up(self, channel, i2c)
+    }
+
+    fn failover(&mut self, failed_address: u8) {
+        T::failover(self, failed_address)
+    }
+
+    fn mismatch(&mut self, expected: u8, actual: u8) {
+        T::mismatch(self, expected, actual)
+    }
+}
@@ -1,9 +1,164 @@
-use crate::{DoOnAcquired, Error, SelectChannels};
+use crate::{
+    AccessContext, AccessKind, Channel, DoOnAcquired, Error, Observer, ScanConfig, ScanReport,
+    Xca954xaData,
+};
 use core::marker::PhantomData;
-use embedded_hal::i2c as ehal;
+use embedded_hal::{delay::DelayNs, i2c as ehal};
+
+/// Location of a virtual I²C bus behind a switch
+///
+/// This allows generic downstream drivers and logging layers to report
+/// where their bus is, e.g. "mux 0x70 ch 4", without knowing its concrete type.
+pub trait ChannelInfo {
+    /// I²C address of the switch.
+    fn mux_address(&self) -> u8;
+
+    /// Channel of the switch.
+    fn channel(&self) -> Channel;
+}
+
+impl<T: ChannelInfo + ?Sized> ChannelInfo for &mut T {
+    fn mux_address(&self) -> u8 {
+        T::mux_address(self)
+    }
+
+    fn channel(&self) -> Channel {
+        T::channel(self)
+    }
+}
 
 /// Slave I2C device
-pub struct I2cSlave<'a, DEV: 'a, I2C>(&'a DEV, u8, PhantomData<I2C>);
+///
+/// A user tag can be attached to it with [`with_tag()`](#method.with_tag),
+/// e.g. a board-specific ID or a connector label to report in error handling.
+///
+/// Splitting the device again makes the slave I2C devices created before
+/// stale. Transfers through them then fail with `Error::StaleHandle`.
+pub struct I2cSlave<'a, DEV: 'a, I2C, T = ()> {
+    dev: &'a DEV,
+    channel: Channel,
+    tag: T,
+    /// Generation of the device when this part was created.
+    generation: u32,
+    _i2c: PhantomData<I2C>,
+}
+
+impl<'a, DEV: 'a, I2C> I2cSlave<'a, DEV, I2C> {
+    pub(crate) fn new(dev: &'a DEV, channel: Channel, generation: u32) -> Self {
+        I2cSlave {
+            dev,
+            channel,
+            tag: (),
+            generation,
+            _i2c: PhantomData,
+        }
+    }
+}
+
+impl<'a, DEV: 'a, I2C, T> I2cSlave<'a, DEV, I2C, T> {
+    /// Channel of the switch this slave I2C device is connected to.
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// Attach a user tag to this slave I2C device, replacing any previous one.
+    pub fn with_tag<U>(self, tag: U) -> I2cSlave<'a, DEV, I2C, U> {
+        I2cSlave {
+            dev: self.dev,
+            channel: self.channel,
+            tag,
+            generation: self.generation,
+            _i2c: PhantomData,
+        }
+    }
+
+    /// User tag attached to this slave I2C device.
+    pub fn tag(&self) -> &T {
+        &self.tag
+    }
+
+    /// Mutable access to the user tag attached to this slave I2C device.
+    pub fn tag_mut(&mut self) -> &mut T {
+        &mut self.tag
+    }
+
+    /// Replace the user tag, returning the previous one.
+    pub fn set_tag(&mut self, tag: T) -> T {
+        core::mem::replace(&mut self.tag, tag)
+    }
+}
+
+impl<'a, DEV, I2C, T> ChannelInfo for I2cSlave<'a, DEV, I2C, T>
+where
+    DEV: DoOnAcquired<I2C>,
+{
+    fn mux_address(&self) -> u8 {
+        self.dev.address()
+    }
+
+    fn channel(&self) -> Channel {
+        self.channel
+    }
+}
+
+impl<'a, DEV, I2C, T, E> I2cSlave<'a, DEV, I2C, T>
+where
+    DEV: DoOnAcquired<I2C>,
+    I2C: ehal::I2c<Error = E>,
+    E: ehal::Error,
+{
+    /// Acquire the device for `access`, unless this slave I2C device is stale.
+    #[inline]
+    fn acquire<R>(
+        &self,
+        access: AccessContext,
+        f: impl FnOnce(
+            core::cell::RefMut<Xca954xaData<I2C, DEV::Delay, DEV::Observer>>,
+        ) -> Result<R, Error<E>>,
+    ) -> Result<R, Error<E>> {
+        if self.dev.generation() != self.generation {
+            return Err(Error::StaleHandle(self.channel));
+        }
+        self.dev.do_on_acquired(access, f)
+    }
+}
+
+impl<'a, DEV, I2C, T, D, O, E> I2cSlave<'a, DEV, I2C, T>
+where
+    DEV: DoOnAcquired<I2C, Delay = D, Observer = O>,
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    /// Check whether a device answers at `address` on the channel of this
+    /// slave I2C device with a one-byte read.
+    pub fn probe(&mut self, address: u8) -> Result<bool, Error<E>> {
+        let access = AccessContext::part(AccessKind::Scan, self.channel);
+        self.acquire(access, |mut dev| dev.probe(self.channel, address))
+    }
+
+    /// Scan the channel of this slave I2C device for devices at every
+    /// non-reserved address except the one of the switch itself.
+    ///
+    /// The previous channel selection is restored afterwards.
+    /// See [`ScanReport::devices()`].
+    pub fn scan(&mut self) -> Result<ScanReport, Error<E>> {
+        let access = AccessContext::part(AccessKind::Scan, self.channel);
+        let config = ScanConfig::new().channels(self.channel.mask());
+        self.acquire(access, |mut dev| dev.scan(&config, &mut || ()))
+    }
+
+    /// Deselect the channel of this slave I2C device if it is selected.
+    ///
+    /// This disconnects the segment explicitly, e.g. at the end of a
+    /// session, instead of leaving it connected until another channel is
+    /// selected. Other selected channels are left as they are.
+    pub fn release(&mut self) -> Result<(), Error<E>> {
+        let access = AccessContext::part(AccessKind::SelectChannels, self.channel);
+        self.acquire(access, |mut dev| dev.release_part(self.channel))
+    }
+}
 
 macro_rules! parts {
     ( $name:ident; $( $i2cx:ident, $channel:expr ),+ ) => {
@@ -17,10 +172,10 @@ macro_rules! parts {
         }
 
         impl<'a, DEV:'a, I2C> $name<'a, DEV, I2C> {
-            pub(crate) fn new(dev: &'a DEV) -> Self {
+            pub(crate) fn new(dev: &'a DEV, generation: u32) -> Self {
                 $name {
                     $(
-                        $i2cx: I2cSlave(&dev, $channel, PhantomData),
+                        $i2cx: I2cSlave::new(dev, $channel, generation),
                     )*
                 }
             }
@@ -28,16 +183,17 @@ macro_rules! parts {
     }
 }
 parts!(
-    Parts; i2c0, 0x01, i2c1, 0x02, i2c2, 0x04, i2c3, 0x08, i2c4, 0x10, i2c5, 0x20, i2c6, 0x40, i2c7, 0x80
+    Parts; i2c0, Channel::Ch0, i2c1, Channel::Ch1, i2c2, Channel::Ch2, i2c3, Channel::Ch3,
+    i2c4, Channel::Ch4, i2c5, Channel::Ch5, i2c6, Channel::Ch6, i2c7, Channel::Ch7
 );
 parts!(
-    Parts2; i2c0, 0x01, i2c1, 0x02
+    Parts2; i2c0, Channel::Ch0, i2c1, Channel::Ch1
 );
 parts!(
-    Parts4; i2c0, 0x01, i2c1, 0x02, i2c2, 0x04, i2c3, 0x08
+    Parts4; i2c0, Channel::Ch0, i2c1, Channel::Ch1, i2c2, Channel::Ch2, i2c3, Channel::Ch3
 );
 
-impl<'a, DEV, I2C, E> ehal::ErrorType for I2cSlave<'a, DEV, I2C>
+impl<'a, DEV, I2C, T, E> ehal::ErrorType for I2cSlave<'a, DEV, I2C, T>
 where
     DEV: DoOnAcquired<I2C>,
     I2C: ehal::I2c<Error = E>,
@@ -46,40 +202,145 @@ where
     type Error = Error<E>;
 }
 
-impl<'a, DEV, I2C, E> ehal::I2c for I2cSlave<'a, DEV, I2C>
+impl<'a, DEV, I2C, T, D, O, E> ehal::I2c for I2cSlave<'a, DEV, I2C, T>
+where
+    DEV: DoOnAcquired<I2C, Delay = D, Observer = O>,
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    #[inline]
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [ehal::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let access = AccessContext::part(AccessKind::Transaction, self.channel);
+        self.acquire(access, |mut dev| {
+            dev.part_transfer(self.channel, access.kind, u16::from(address), |i2c| {
+                i2c.transaction(address, operations)
+            })
+        })
+    }
+
+    #[inline]
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        let access = AccessContext::part(AccessKind::Read, self.channel);
+        self.acquire(access, |mut dev| {
+            dev.part_transfer(self.channel, access.kind, u16::from(address), |i2c| {
+                i2c.read(address, read)
+            })
+        })
+    }
+
+    #[inline]
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        let access = AccessContext::part(AccessKind::Write, self.channel);
+        self.acquire(access, |mut dev| {
+            dev.part_transfer(self.channel, access.kind, u16::from(address), |i2c| {
+                i2c.write(address, write)
+            })
+        })
+    }
+
+    #[inline]
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let access = AccessContext::part(AccessKind::WriteRead, self.channel);
+        self.acquire(access, |mut dev| {
+            dev.part_transfer(self.channel, access.kind, u16::from(address), |i2c| {
+                i2c.write_read(address, write, read)
+            })
+        })
+    }
+}
+
+#[cfg(feature = "ten-bit-address")]
+impl<'a, DEV, I2C, T, D, O, E> ehal::I2c<ehal::TenBitAddress> for I2cSlave<'a, DEV, I2C, T>
+where
+    DEV: DoOnAcquired<I2C, Delay = D, Observer = O>,
+    I2C: ehal::I2c<Error = E> + ehal::I2c<ehal::TenBitAddress>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    #[inline]
+    fn transaction(
+        &mut self,
+        address: ehal::TenBitAddress,
+        operations: &mut [ehal::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let access = AccessContext::part(AccessKind::Transaction, self.channel);
+        self.acquire(access, |mut dev| {
+            dev.part_transfer(self.channel, access.kind, address, |i2c| {
+                ehal::I2c::<ehal::TenBitAddress>::transaction(i2c, address, operations)
+            })
+        })
+    }
+}
+
+/// I2C device on the upstream bus
+///
+/// All channels of the switch are deselected before talking to the device.
+/// See [`upstream()`](struct.Xca9548a.html#method.upstream).
+pub struct UpstreamBus<'a, DEV: 'a, I2C>(&'a DEV, PhantomData<I2C>);
+
+impl<'a, DEV: 'a, I2C> UpstreamBus<'a, DEV, I2C> {
+    pub(crate) fn new(dev: &'a DEV) -> Self {
+        UpstreamBus(dev, PhantomData)
+    }
+}
+
+impl<'a, DEV, I2C, E> ehal::ErrorType for UpstreamBus<'a, DEV, I2C>
 where
     DEV: DoOnAcquired<I2C>,
     I2C: ehal::I2c<Error = E>,
     E: ehal::Error,
+{
+    type Error = Error<E>;
+}
+
+impl<'a, DEV, I2C, D, O, E> ehal::I2c for UpstreamBus<'a, DEV, I2C>
+where
+    DEV: DoOnAcquired<I2C, Delay = D, Observer = O>,
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
 {
     fn transaction(
         &mut self,
         address: u8,
         operations: &mut [ehal::Operation<'_>],
     ) -> Result<(), Self::Error> {
-        self.0.do_on_acquired(|mut dev| {
-            if dev.selected_channel_mask != self.1 {
-                dev.select_channels(self.1)?;
-            }
-            dev.i2c.transaction(address, operations).map_err(Error::I2C)
+        let access = AccessContext::device(AccessKind::Transaction);
+        self.0.do_on_acquired(access, |mut dev| {
+            dev.upstream_transfer(access.kind, u16::from(address), |i2c| {
+                i2c.transaction(address, operations)
+            })
         })
     }
 
     fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
-        self.0.do_on_acquired(|mut dev| {
-            if dev.selected_channel_mask != self.1 {
-                dev.select_channels(self.1)?;
-            }
-            dev.i2c.read(address, read).map_err(Error::I2C)
+        let access = AccessContext::device(AccessKind::Read);
+        self.0.do_on_acquired(access, |mut dev| {
+            dev.upstream_transfer(access.kind, u16::from(address), |i2c| {
+                i2c.read(address, read)
+            })
         })
     }
 
     fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
-        self.0.do_on_acquired(|mut dev| {
-            if dev.selected_channel_mask != self.1 {
-                dev.select_channels(self.1)?;
-            }
-            dev.i2c.write(address, write).map_err(Error::I2C)
+        let access = AccessContext::device(AccessKind::Write);
+        self.0.do_on_acquired(access, |mut dev| {
+            dev.upstream_transfer(access.kind, u16::from(address), |i2c| {
+                i2c.write(address, write)
+            })
         })
     }
 
@@ -89,11 +350,11 @@ where
         write: &[u8],
         read: &mut [u8],
     ) -> Result<(), Self::Error> {
-        self.0.do_on_acquired(|mut dev| {
-            if dev.selected_channel_mask != self.1 {
-                dev.select_channels(self.1)?;
-            }
-            dev.i2c.write_read(address, write, read).map_err(Error::I2C)
+        let access = AccessContext::device(AccessKind::WriteRead);
+        self.0.do_on_acquired(access, |mut dev| {
+            dev.upstream_transfer(access.kind, u16::from(address), |i2c| {
+                i2c.write_read(address, write, read)
+            })
         })
     }
 }
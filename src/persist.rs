@@ -0,0 +1,185 @@
+use crate::{
+    builder::{CachePolicy, Config, MismatchPolicy, RetryPolicy},
+    DeviceState,
+};
+
+/// Format version of the encoded state
+const VERSION: u8 = 1;
+
+/// Non-volatile storage for the driver state
+///
+/// Implement this on top of the flash, EEPROM or backup registers of the
+/// platform to keep the channel selection and configuration across deep
+/// sleep or watchdog resets.
+/// See [`DeviceState::save()`] and [`DeviceState::load()`].
+pub trait StateStorage {
+    /// Storage error
+    type Error;
+
+    /// Store `data`, replacing any previously stored data.
+    fn save(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Read the stored data into `buffer`, returning the number of bytes read.
+    ///
+    /// Return `0` if nothing has been stored.
+    fn load(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+impl<T: StateStorage + ?Sized> StateStorage for &mut T {
+    type Error = T::Error;
+
+    fn save(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        T::save(self, data)
+    }
+
+    fn load(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        T::load(self, buffer)
+    }
+}
+
+const STRICT: u8 = 1 << 0;
+const ALWAYS_SELECT: u8 = 1 << 1;
+const AUTO_DESELECT: u8 = 1 << 2;
+const VERIFY_WRITES: u8 = 1 << 3;
+const AUTO_RECOVER: u8 = 1 << 4;
+const CACHE_DIRTY: u8 = 1 << 5;
+const SINGLE_CHANNEL: u8 = 1 << 6;
+
+impl DeviceState {
+    /// Length of the encoded state in bytes
+    pub const ENCODED_LEN: usize = 51;
+
+    /// Encode the state into a versioned, checksummed byte array.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let config = &self.config;
+        let mut flags = 0;
+        for (set, flag) in [
+            (config.strict, STRICT),
+            (
+                config.cache_policy == CachePolicy::AlwaysSelect,
+                ALWAYS_SELECT,
+            ),
+            (config.auto_deselect, AUTO_DESELECT),
+            (config.verify_writes, VERIFY_WRITES),
+            (config.auto_recover, AUTO_RECOVER),
+            (self.cache_dirty, CACHE_DIRTY),
+            (config.single_channel, SINGLE_CHANNEL),
+        ] {
+            if set {
+                flags |= flag;
+            }
+        }
+        let (kind, retries, a, b, jitter) = match config.retry_policy {
+            RetryPolicy::Fixed { retries, delay_ns } => (0, retries, delay_ns, 0, false),
+            RetryPolicy::Linear { retries, step_ns } => (1, retries, step_ns, 0, false),
+            RetryPolicy::Exponential {
+                retries,
+                initial_ns,
+                max_ns,
+                jitter,
+            } => (2, retries, initial_ns, max_ns, jitter),
+        };
+        let mut bytes = [0; Self::ENCODED_LEN];
+        bytes[..4].copy_from_slice(&[VERSION, self.address, self.selected_channel_mask, flags]);
+        bytes[4..6].copy_from_slice(&config.integrity_check_interval.to_le_bytes());
+        bytes[6..9].copy_from_slice(&[kind, retries, u8::from(jitter)]);
+        bytes[9..13].copy_from_slice(&a.to_le_bytes());
+        bytes[13..17].copy_from_slice(&b.to_le_bytes());
+        for (chunk, ns) in bytes[17..49].chunks_exact_mut(4).zip(config.settle_ns) {
+            chunk.copy_from_slice(&ns.to_le_bytes());
+        }
+        bytes[49] = match config.mismatch_policy {
+            MismatchPolicy::Error => 0,
+            MismatchPolicy::Rewrite => 1,
+            MismatchPolicy::Notify => 2,
+        };
+        bytes[50] = checksum(&bytes[..50]);
+        bytes
+    }
+
+    /// Decode a state encoded with [`to_bytes()`](#method.to_bytes).
+    ///
+    /// Returns `None` if the data is truncated, corrupted or was encoded in
+    /// an unknown format version.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..Self::ENCODED_LEN)?;
+        if bytes[0] != VERSION || bytes[50] != checksum(&bytes[..50]) {
+            return None;
+        }
+        let u32_at =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let flags = bytes[3];
+        let retries = bytes[7];
+        let retry_policy = match bytes[6] {
+            0 => RetryPolicy::Fixed {
+                retries,
+                delay_ns: u32_at(9),
+            },
+            1 => RetryPolicy::Linear {
+                retries,
+                step_ns: u32_at(9),
+            },
+            2 => RetryPolicy::Exponential {
+                retries,
+                initial_ns: u32_at(9),
+                max_ns: u32_at(13),
+                jitter: bytes[8] != 0,
+            },
+            _ => return None,
+        };
+        let mismatch_policy = match bytes[49] {
+            0 => MismatchPolicy::Error,
+            1 => MismatchPolicy::Rewrite,
+            2 => MismatchPolicy::Notify,
+            _ => return None,
+        };
+        let mut settle_ns = [0; 8];
+        for (i, ns) in settle_ns.iter_mut().enumerate() {
+            *ns = u32_at(17 + 4 * i);
+        }
+        let config = Config {
+            strict: flags & STRICT != 0,
+            single_channel: flags & SINGLE_CHANNEL != 0,
+            cache_policy: if flags & ALWAYS_SELECT != 0 {
+                CachePolicy::AlwaysSelect
+            } else {
+                CachePolicy::Trust
+            },
+            auto_deselect: flags & AUTO_DESELECT != 0,
+            auto_recover: flags & AUTO_RECOVER != 0,
+            retry_policy,
+            verify_writes: flags & VERIFY_WRITES != 0,
+            integrity_check_interval: u16::from_le_bytes([bytes[4], bytes[5]]),
+            mismatch_policy,
+            settle_ns,
+        };
+        Some(DeviceState {
+            address: bytes[1],
+            selected_channel_mask: bytes[2],
+            cache_dirty: flags & CACHE_DIRTY != 0,
+            config,
+        })
+    }
+
+    /// Save the encoded state to `storage`.
+    pub fn save<S: StateStorage>(&self, storage: &mut S) -> Result<(), S::Error> {
+        storage.save(&self.to_bytes())
+    }
+
+    /// Load a state saved with [`save()`](#method.save) from `storage`.
+    ///
+    /// Returns `None` if nothing valid has been stored.
+    pub fn load<S: StateStorage>(storage: &mut S) -> Result<Option<Self>, S::Error> {
+        let mut bytes = [0; Self::ENCODED_LEN];
+        let len = storage.load(&mut bytes)?;
+        Ok(Self::from_bytes(&bytes[..len.min(Self::ENCODED_LEN)]))
+    }
+}
+
+/// Two's complement of the byte sum, so that all bytes add up to zero.
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg()
+}
@@ -0,0 +1,178 @@
+use crate::{AccessContext, AccessKind, Channel, ChannelBus, DoOnAcquired, Error, Observer};
+use core::{future::Future, ops::ControlFlow};
+use embedded_hal::{delay::DelayNs, i2c as ehal};
+
+/// Poll job callback
+///
+/// It gets the I²C bus with the channel of the job selected. Transfers through
+/// it are reported to the observer, see [`ChannelBus`].
+type Callback<'a, E> =
+    &'a mut dyn FnMut(&mut dyn ehal::I2c<Error = Error<E>>) -> Result<(), Error<E>>;
+
+struct PollJob<'a, E: core::fmt::Debug> {
+    channel: Channel,
+    interval: u32,
+    elapsed: u32,
+    callback: Callback<'a, E>,
+}
+
+/// Round-robin poll scheduler
+///
+/// Poll jobs are registered for a channel with an interval in ticks.
+/// Each call to [`tick()`](#method.tick) runs the jobs that are due,
+/// running all the jobs of a channel together so that each channel is
+/// selected only once per tick. The channel serviced first rotates
+/// so that all channels are treated fairly.
+///
+/// Up to `N` jobs can be registered.
+pub struct Poller<'a, E: core::fmt::Debug, const N: usize> {
+    jobs: [Option<PollJob<'a, E>>; N],
+    next_channel: u8,
+}
+
+impl<'a, E: core::fmt::Debug, const N: usize> Default for Poller<'a, E, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, E: core::fmt::Debug, const N: usize> Poller<'a, E, N> {
+    /// Create a new poller without any jobs.
+    pub fn new() -> Self {
+        Poller {
+            jobs: core::array::from_fn(|_| None),
+            next_channel: 0,
+        }
+    }
+
+    /// Register a job polling `channel` of `switch` every `interval` ticks.
+    ///
+    /// An interval of 0 is treated as 1.
+    /// Returns the index of the job or `None` if there is no space left.
+    ///
+    /// Returns `Error::InvalidChannels` if the switch does not have `channel`.
+    pub fn register<DEV, I2C>(
+        &mut self,
+        switch: &DEV,
+        channel: Channel,
+        interval: u32,
+        callback: Callback<'a, E>,
+    ) -> Result<Option<usize>, Error<E>>
+    where
+        DEV: DoOnAcquired<I2C>,
+    {
+        if channel.mask() & switch.channel_mask() == 0 {
+            return Err(Error::InvalidChannels(channel.mask()));
+        }
+        let index = match self.jobs.iter().position(Option::is_none) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        self.jobs[index] = Some(PollJob {
+            channel,
+            interval: interval.max(1),
+            elapsed: 0,
+            callback,
+        });
+        Ok(Some(index))
+    }
+
+    /// Remove the job with the given index.
+    pub fn unregister(&mut self, index: usize) {
+        if let Some(job) = self.jobs.get_mut(index) {
+            *job = None;
+        }
+    }
+}
+
+impl<'a, E, const N: usize> Poller<'a, E, N>
+where
+    E: ehal::Error,
+{
+    /// Advance one tick and run the jobs that are due on `switch`.
+    ///
+    /// All due jobs are run even if some of them fail. Returns the number
+    /// of jobs run or the first error.
+    pub fn tick<DEV, I2C, D, O>(&mut self, switch: &mut DEV) -> Result<usize, Error<E>>
+    where
+        DEV: DoOnAcquired<I2C, Delay = D, Observer = O>,
+        I2C: ehal::I2c<Error = E>,
+        D: DelayNs,
+        O: Observer,
+    {
+        let mut due = 0;
+        for job in self.jobs.iter_mut().flatten() {
+            job.elapsed += 1;
+            if job.elapsed >= job.interval {
+                due |= job.channel.mask();
+            }
+        }
+        if due == 0 {
+            return Ok(0);
+        }
+        let start = self.next_channel;
+        let jobs = &mut self.jobs;
+        let mut last = start;
+        let result =
+            switch.do_on_acquired(AccessContext::device(AccessKind::Transaction), |mut dev| {
+                let mut count = 0;
+                let mut first_error = None;
+                for offset in 0..8 {
+                    let index = (start + offset) % 8;
+                    let channel = Channel::from_checked_index(index);
+                    if due & channel.mask() == 0 {
+                        continue;
+                    }
+                    last = index;
+                    if let Err(e) = dev.select_device_channels(channel.mask(), false) {
+                        first_error.get_or_insert(e);
+                        continue;
+                    }
+                    for job in jobs.iter_mut().flatten() {
+                        if job.channel == channel && job.elapsed >= job.interval {
+                            job.elapsed = 0;
+                            count += 1;
+                            if let Err(e) = (job.callback)(&mut ChannelBus::new(&mut dev, channel))
+                            {
+                                first_error.get_or_insert(e);
+                            }
+                        }
+                    }
+                }
+                match first_error {
+                    Some(e) => Err(e),
+                    None => Ok(count),
+                }
+            });
+        self.next_channel = (last + 1) % 8;
+        result
+    }
+
+    /// Run a tick on `switch` after each `sleep()` until `on_tick` breaks.
+    ///
+    /// This is the polling loop of an async task. The timer is supplied by
+    /// the caller, e.g. `|| Timer::after_millis(100)` with `embassy-time`.
+    /// `on_tick` gets the result of each tick and returns
+    /// `ControlFlow::Break` with the value to return to stop polling.
+    /// The transfers of the jobs are blocking.
+    pub async fn run<DEV, I2C, D, O, F, R>(
+        &mut self,
+        switch: &mut DEV,
+        mut sleep: impl FnMut() -> F,
+        mut on_tick: impl FnMut(Result<usize, Error<E>>) -> ControlFlow<R>,
+    ) -> R
+    where
+        DEV: DoOnAcquired<I2C, Delay = D, Observer = O>,
+        I2C: ehal::I2c<Error = E>,
+        D: DelayNs,
+        O: Observer,
+        F: Future<Output = ()>,
+    {
+        loop {
+            sleep().await;
+            if let ControlFlow::Break(value) = on_tick(self.tick(switch)) {
+                return value;
+            }
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use embedded_hal::i2c::I2c;
+
+/// Select `channels` on the switch at `address` by writing the control register
+/// directly on `i2c`.
+///
+/// This does not use the driver at all, so it can be used where the driver can
+/// not be acquired, like interrupt handlers or panic paths, given exclusive
+/// access to the bus. The value is written as-is, so each bit corresponds to a
+/// channel of the device.
+///
+/// Any driver instance for the same switch does not know about this change, so
+/// call `invalidate_cache()` on it afterwards if it is still in use.
+///
+/// ```
+/// use xca9548a::{select_channels_raw, DEFAULT_ADDRESS};
+/// # use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+/// # let mut i2c = I2cMock::new(&[I2cTrans::write(0x70, vec![0])]);
+///
+/// // isolate all downstream segments before resetting
+/// select_channels_raw(&mut i2c, DEFAULT_ADDRESS, 0).ok();
+/// # i2c.done();
+/// ```
+pub fn select_channels_raw<I2C: I2c>(
+    i2c: &mut I2C,
+    address: u8,
+    channels: u8,
+) -> Result<(), I2C::Error> {
+    i2c.write(address, &[channels])
+}
@@ -0,0 +1,496 @@
+//! Recording and replaying the traffic of a device.
+//!
+//! A [`Recorder`] wraps the I²C bus of a device and captures every control
+//! register write and every transaction together with the channels selected
+//! at the time. The resulting [`Recording`] can be stored as text and fed back
+//! through a [`Replayer`], which acts as a simulated bus reproducing the
+//! original responses and errors. This allows reproducing field failures in tests.
+//!
+//! ```
+//! use embedded_hal::i2c::I2c;
+//! use xca9548a::record::{Recorder, Recording, Replayer};
+//! use xca9548a::{SlaveAddr, Xca9548a, DEFAULT_ADDRESS};
+//! # use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+//! # let bus = I2cMock::new(&[
+//! #     I2cTrans::write(DEFAULT_ADDRESS, vec![0x01]),
+//! #     I2cTrans::read(0x48, vec![0x12, 0x34]),
+//! # ]);
+//!
+//! // In the field:
+//! let switch = Xca9548a::new(Recorder::new(bus, DEFAULT_ADDRESS), SlaveAddr::default());
+//! let mut temperature = [0; 2];
+//! switch.split().i2c0.read(0x48, &mut temperature).unwrap();
+//! let (mut bus, recording) = switch.destroy().into_parts();
+//! let log = recording.to_string();
+//! # bus.done();
+//!
+//! // In a test:
+//! let recording: Recording = log.parse().unwrap();
+//! let switch = Xca9548a::new(Replayer::new(recording), SlaveAddr::default());
+//! let mut temperature = [0; 2];
+//! switch.split().i2c0.read(0x48, &mut temperature).unwrap();
+//! assert_eq!([0x12, 0x34], temperature);
+//! assert_eq!(0, switch.destroy().remaining());
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+use embedded_hal::i2c::{self as ehal, ErrorKind, NoAcknowledgeSource};
+
+/// Operation of a recorded transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordedOperation {
+    /// Data written
+    Write(Vec<u8>),
+    /// Data read
+    Read(Vec<u8>),
+}
+
+/// Entry of a [`Recording`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    /// Write to the control register of the switch
+    Select {
+        /// Switch address
+        address: u8,
+        /// Channels selected
+        channels: u8,
+        /// Error returned by the bus, if any
+        error: Option<ErrorKind>,
+    },
+    /// Any other transaction
+    Transaction {
+        /// Channels selected at the time
+        channels: u8,
+        /// Slave address
+        address: u8,
+        /// Operations of the transaction
+        operations: Vec<RecordedOperation>,
+        /// Error returned by the bus, if any
+        error: Option<ErrorKind>,
+    },
+}
+
+impl Record {
+    /// Error returned by the bus, if any.
+    pub fn error(&self) -> Option<ErrorKind> {
+        match self {
+            Record::Select { error, .. } | Record::Transaction { error, .. } => *error,
+        }
+    }
+
+    fn address(&self) -> u8 {
+        match self {
+            Record::Select { address, .. } | Record::Transaction { address, .. } => *address,
+        }
+    }
+
+    fn operations(&self) -> Vec<RecordedOperation> {
+        match self {
+            Record::Select { channels, .. } => vec![RecordedOperation::Write(vec![*channels])],
+            Record::Transaction { operations, .. } => operations.clone(),
+        }
+    }
+}
+
+const ERROR_NAMES: [(ErrorKind, &str); 7] = [
+    (ErrorKind::Bus, "bus"),
+    (ErrorKind::ArbitrationLoss, "arbitration-loss"),
+    (
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+        "nack-address",
+    ),
+    (
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+        "nack-data",
+    ),
+    (
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+        "nack",
+    ),
+    (ErrorKind::Overrun, "overrun"),
+    (ErrorKind::Other, "other"),
+];
+
+fn write_result(f: &mut fmt::Formatter<'_>, error: Option<ErrorKind>) -> fmt::Result {
+    match error {
+        None => write!(f, " ok"),
+        Some(error) => {
+            let name = ERROR_NAMES
+                .iter()
+                .find(|(kind, _)| *kind == error)
+                .map_or("other", |(_, name)| name);
+            write!(f, " err:{}", name)
+        }
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, data: &[u8]) -> fmt::Result {
+    data.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+}
+
+/// Formats the record as a single line.
+///
+/// For example `select 70 01 ok` or `transfer 01 48 w:00 r:1234 err:nack-data`.
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Record::Select {
+                address, channels, ..
+            } => write!(f, "select {:02x} {:02x}", address, channels)?,
+            Record::Transaction {
+                channels,
+                address,
+                operations,
+                ..
+            } => {
+                write!(f, "transfer {:02x} {:02x}", channels, address)?;
+                for operation in operations {
+                    match operation {
+                        RecordedOperation::Write(data) => {
+                            write!(f, " w:")?;
+                            write_hex(f, data)?;
+                        }
+                        RecordedOperation::Read(data) => {
+                            write!(f, " r:")?;
+                            write_hex(f, data)?;
+                        }
+                    }
+                }
+            }
+        }
+        write_result(f, self.error())
+    }
+}
+
+/// Error parsing a [`Recording`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseRecordingError {
+    /// Line number where the error was found, starting at 1
+    pub line: usize,
+}
+
+impl fmt::Display for ParseRecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid recording at line {}", self.line)
+    }
+}
+
+impl std::error::Error for ParseRecordingError {}
+
+fn parse_byte(text: &str) -> Option<u8> {
+    if text.len() != 2 {
+        return None;
+    }
+    u8::from_str_radix(text, 16).ok()
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| parse_byte(text.get(i..i + 2)?))
+        .collect()
+}
+
+fn parse_result(text: &str) -> Option<Option<ErrorKind>> {
+    if text == "ok" {
+        return Some(None);
+    }
+    let name = text.strip_prefix("err:")?;
+    ERROR_NAMES
+        .iter()
+        .find(|(_, n)| *n == name)
+        .map(|(kind, _)| Some(*kind))
+}
+
+fn parse_record(line: &str) -> Option<Record> {
+    let mut fields = line.split_whitespace();
+    let kind = fields.next()?;
+    let first = parse_byte(fields.next()?)?;
+    let second = parse_byte(fields.next()?)?;
+    let mut fields: Vec<_> = fields.collect();
+    let error = parse_result(fields.pop()?)?;
+    match kind {
+        "select" if fields.is_empty() => Some(Record::Select {
+            address: first,
+            channels: second,
+            error,
+        }),
+        "transfer" => {
+            let operations = fields
+                .iter()
+                .map(|field| {
+                    if let Some(data) = field.strip_prefix("w:") {
+                        parse_hex(data).map(RecordedOperation::Write)
+                    } else {
+                        parse_hex(field.strip_prefix("r:")?).map(RecordedOperation::Read)
+                    }
+                })
+                .collect::<Option<_>>()?;
+            Some(Record::Transaction {
+                channels: first,
+                address: second,
+                operations,
+                error,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Traffic captured by a [`Recorder`]
+///
+/// It can be converted to text with `to_string()` (one record per line)
+/// and back with `parse()`. Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Recording {
+    records: Vec<Record>,
+}
+
+impl Recording {
+    /// Create an empty recording.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records in chronological order.
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+}
+
+impl From<Vec<Record>> for Recording {
+    fn from(records: Vec<Record>) -> Self {
+        Recording { records }
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.records
+            .iter()
+            .try_for_each(|record| writeln!(f, "{}", record))
+    }
+}
+
+impl FromStr for Recording {
+    type Err = ParseRecordingError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let records = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                let line = line.trim();
+                !line.is_empty() && !line.starts_with('#')
+            })
+            .map(|(index, line)| parse_record(line).ok_or(ParseRecordingError { line: index + 1 }))
+            .collect::<Result<_, _>>()?;
+        Ok(Recording { records })
+    }
+}
+
+/// I²C bus wrapper recording all the traffic through it
+///
+/// Single-byte writes to the switch address are recorded as channel selections.
+/// Any other transaction is recorded together with the channels selected at the time.
+#[derive(Debug)]
+pub struct Recorder<I2C> {
+    i2c: I2C,
+    switch_address: u8,
+    channels: u8,
+    recording: Recording,
+}
+
+impl<I2C> Recorder<I2C> {
+    /// Wrap an I²C bus where a switch is present at `switch_address`.
+    pub fn new(i2c: I2C, switch_address: u8) -> Self {
+        Recorder {
+            i2c,
+            switch_address,
+            channels: 0,
+            recording: Recording::new(),
+        }
+    }
+
+    /// Traffic recorded so far.
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// Take the traffic recorded so far and start a new recording.
+    pub fn take_recording(&mut self) -> Recording {
+        core::mem::take(&mut self.recording)
+    }
+
+    /// Destroy the recorder, return the I²C bus instance and the recorded traffic.
+    pub fn into_parts(self) -> (I2C, Recording) {
+        (self.i2c, self.recording)
+    }
+}
+
+impl<I2C: ehal::ErrorType> ehal::ErrorType for Recorder<I2C> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: ehal::I2c> Recorder<I2C> {
+    fn record<E: ehal::Error>(
+        &mut self,
+        address: u8,
+        operations: &[ehal::Operation<'_>],
+        result: &Result<(), E>,
+    ) {
+        let error = result.as_ref().err().map(ehal::Error::kind);
+        let record = match operations {
+            [ehal::Operation::Write([channels])] if address == self.switch_address => {
+                if error.is_none() {
+                    self.channels = *channels;
+                }
+                Record::Select {
+                    address,
+                    channels: *channels,
+                    error,
+                }
+            }
+            _ => Record::Transaction {
+                channels: self.channels,
+                address,
+                operations: operations
+                    .iter()
+                    .map(|operation| match operation {
+                        ehal::Operation::Write(data) => RecordedOperation::Write(data.to_vec()),
+                        ehal::Operation::Read(data) => RecordedOperation::Read(data.to_vec()),
+                    })
+                    .collect(),
+                error,
+            },
+        };
+        self.recording.records.push(record);
+    }
+}
+
+impl<I2C: ehal::I2c> ehal::I2c for Recorder<I2C> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [ehal::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let result = self.i2c.transaction(address, operations);
+        self.record(address, operations, &result);
+        result
+    }
+
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        let result = self.i2c.read(address, read);
+        self.record(address, &[ehal::Operation::Read(read)], &result);
+        result
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        let result = self.i2c.write(address, write);
+        self.record(address, &[ehal::Operation::Write(write)], &result);
+        result
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let result = self.i2c.write_read(address, write, read);
+        self.record(
+            address,
+            &[ehal::Operation::Write(write), ehal::Operation::Read(read)],
+            &result,
+        );
+        result
+    }
+}
+
+/// Simulated I²C bus replaying a [`Recording`]
+///
+/// Each transaction must match the next record: same address, same operations
+/// and same data written. The data read and the errors recorded are then
+/// reproduced. A transaction that does not match returns `ErrorKind::Other`
+/// and is reported by [`mismatch()`](#method.mismatch).
+#[derive(Debug)]
+pub struct Replayer {
+    recording: Recording,
+    position: usize,
+    mismatch: Option<usize>,
+}
+
+impl Replayer {
+    /// Create a new replayer for the recording.
+    pub fn new(recording: Recording) -> Self {
+        Replayer {
+            recording,
+            position: 0,
+            mismatch: None,
+        }
+    }
+
+    /// Number of records not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.recording.records.len() - self.position
+    }
+
+    /// Index of the first record that did not match the traffic, if any.
+    ///
+    /// If the traffic went on after the end of the recording, this is the number of records.
+    pub fn mismatch(&self) -> Option<usize> {
+        self.mismatch
+    }
+
+    fn matches(record: &Record, address: u8, operations: &[ehal::Operation<'_>]) -> bool {
+        let recorded = record.operations();
+        record.address() == address
+            && recorded.len() == operations.len()
+            && recorded
+                .iter()
+                .zip(operations.iter())
+                .all(|(recorded, operation)| match (recorded, operation) {
+                    (RecordedOperation::Write(expected), ehal::Operation::Write(data)) => {
+                        expected.as_slice() == *data
+                    }
+                    (RecordedOperation::Read(expected), ehal::Operation::Read(buffer)) => {
+                        expected.len() == buffer.len()
+                    }
+                    _ => false,
+                })
+    }
+}
+
+impl ehal::ErrorType for Replayer {
+    type Error = ErrorKind;
+}
+
+impl ehal::I2c for Replayer {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [ehal::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let record = match self.recording.records.get(self.position) {
+            Some(record)
+                if self.mismatch.is_none() && Self::matches(record, address, operations) =>
+            {
+                record
+            }
+            _ => {
+                self.mismatch.get_or_insert(self.position);
+                return Err(ErrorKind::Other);
+            }
+        };
+        self.position += 1;
+        for (recorded, operation) in record.operations().iter().zip(operations.iter_mut()) {
+            if let (RecordedOperation::Read(data), ehal::Operation::Read(buffer)) =
+                (recorded, operation)
+            {
+                buffer.copy_from_slice(data);
+            }
+        }
+        record.error().map_or(Ok(()), Err)
+    }
+}
@@ -0,0 +1,238 @@
+use crate::{
+    AccessContext, AccessKind, Channel, DoOnAcquired, Error, Observer, RoutingError, RoutingTable,
+    ScanConfig, ScanReport,
+};
+use core::marker::PhantomData;
+use embedded_hal::{delay::DelayNs, i2c as ehal};
+
+/// I²C bus routing each transfer to the channel of its target address
+///
+/// The router owns the switch and a [`RoutingTable`] giving the channel of
+/// each device, so that code written for a single bus can talk to the devices
+/// on all channels without knowing about the switch.
+/// Addresses without a route are accessed on the upstream bus with all
+/// channels deselected, like through [`upstream()`](struct.Xca9548a.html#method.upstream).
+/// Addresses routed to more than one channel are ambiguous and transfers to
+/// them fail with `Error::AmbiguousAddress`.
+/// Created with [`into_router()`](struct.Xca9548a.html#method.into_router) or
+/// [`into_router_with_table()`](struct.Xca9548a.html#method.into_router_with_table).
+///
+/// Instead of being configured, the routes can be learned by scanning the
+/// bus once, e.g. for test fixtures whose topology varies between units.
+/// See [`learn()`](#method.learn).
+///
+/// ```
+/// use embedded_hal::i2c::I2c;
+/// use xca9548a::{Channel, Route, RoutingTable, SlaveAddr, Xca9548a};
+/// # use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+/// # let dev = I2cMock::new(&[
+/// #     I2cTrans::write(0x70, vec![0x08]),
+/// #     I2cTrans::write(0x48, vec![0x01]),
+/// # ]);
+///
+/// const ROUTES: RoutingTable<&str> = RoutingTable::new(&[("sensor", Route::new(Channel::Ch3, 0x48))]);
+///
+/// let switch = Xca9548a::new(dev, SlaveAddr::default());
+/// let mut router = switch.into_router_with_table(ROUTES);
+/// router.validate().unwrap();
+/// // selects channel 3 before writing
+/// router.write(0x48, &[0x01]).unwrap();
+/// # router.destroy().destroy().done();
+/// ```
+#[derive(Debug)]
+pub struct Router<'a, DEV, I2C, ID = u8> {
+    switch: DEV,
+    table: RoutingTable<'a, ID>,
+    /// Devices found on the channels but not on the upstream bus by `learn()`
+    learned: ScanReport,
+    _i2c: PhantomData<I2C>,
+}
+
+impl<'a, DEV, I2C, ID: PartialEq> Router<'a, DEV, I2C, ID> {
+    pub(crate) fn new(switch: DEV, table: RoutingTable<'a, ID>) -> Self {
+        Router {
+            switch,
+            table,
+            learned: ScanReport::default(),
+            _i2c: PhantomData,
+        }
+    }
+
+    /// Mask of the channels transfers to `address` could be routed to.
+    ///
+    /// The routing table takes precedence over the learned routes.
+    fn channels_of(&self, address: u8) -> u8 {
+        match self.table.channels_at(address) {
+            0 => self
+                .learned
+                .where_is(address)
+                .fold(0, |mask, channel| mask | channel.mask()),
+            channels => channels,
+        }
+    }
+
+    /// Whether `address` is routed to more than one channel.
+    ///
+    /// Transfers to ambiguous addresses fail with `Error::AmbiguousAddress`.
+    /// Routing the address to a single channel in the routing table resolves it.
+    pub fn is_ambiguous(&self, address: u8) -> bool {
+        self.channels_of(address).count_ones() > 1
+    }
+
+    /// Addresses routed to more than one channel in ascending order.
+    pub fn ambiguous_addresses(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..128).filter(move |address| self.is_ambiguous(*address))
+    }
+
+    /// Channel transfers to `address` are routed to, if any.
+    pub fn channel_of(&self, address: u8) -> Option<Channel> {
+        let channels = self.channels_of(address);
+        if channels.count_ones() == 1 {
+            Channel::in_mask(channels).next()
+        } else {
+            None
+        }
+    }
+
+    /// Routing table used by the router.
+    pub fn table(&self) -> RoutingTable<'a, ID>
+    where
+        ID: Copy,
+    {
+        self.table
+    }
+
+    /// Switch used by the router.
+    pub fn switch(&self) -> &DEV {
+        &self.switch
+    }
+
+    /// Destroy the router, returning the switch.
+    pub fn destroy(self) -> DEV {
+        self.switch
+    }
+}
+
+impl<'a, DEV, I2C, ID: PartialEq> Router<'a, DEV, I2C, ID>
+where
+    DEV: DoOnAcquired<I2C>,
+{
+    /// Check the routing table against the actual address of the switch,
+    /// including any custom base address.
+    ///
+    /// See [`RoutingTable::validate()`].
+    pub fn validate(&self) -> Result<(), RoutingError<'a, ID>> {
+        self.table.validate_for(self.switch.address())
+    }
+}
+
+impl<'a, DEV, I2C, ID, D, O, E> Router<'a, DEV, I2C, ID>
+where
+    DEV: DoOnAcquired<I2C, Delay = D, Observer = O>,
+    I2C: ehal::I2c<Error = E>,
+    ID: PartialEq,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    /// Scan the upstream bus and all channels and replace the learned routes
+    /// with the devices found.
+    ///
+    /// Devices found on exactly one channel are routed to it. Devices
+    /// answering on the upstream bus with all channels deselected are left
+    /// unrouted. Addresses found on more than one channel are ambiguous, see
+    /// [`is_ambiguous()`](#method.is_ambiguous). Addresses in the routing
+    /// table keep their routes.
+    /// Returns the devices found on each channel.
+    pub fn learn(&mut self) -> Result<ScanReport, Error<E>> {
+        let config = ScanConfig::default();
+        let access = AccessContext::device(AccessKind::Scan);
+        let (upstream, report) = self.switch.do_on_acquired(access, |mut dev| {
+            let upstream = dev.scan_upstream(&config)?;
+            let report = dev.scan(&config, &mut || ())?;
+            Ok((upstream, report))
+        })?;
+        self.learned = ScanReport::default();
+        for channel in Channel::iter() {
+            for address in report.devices(channel) {
+                if upstream & (1 << address) == 0 {
+                    self.learned.insert(channel, address);
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    fn transfer(
+        &self,
+        kind: AccessKind,
+        address: u8,
+        transfer: impl FnMut(&mut I2C) -> Result<(), E>,
+    ) -> Result<(), Error<E>> {
+        if self.is_ambiguous(address) {
+            return Err(Error::AmbiguousAddress(address));
+        }
+        match self.channel_of(address) {
+            Some(channel) => {
+                let access = AccessContext::part(kind, channel);
+                self.switch.do_on_acquired(access, |mut dev| {
+                    dev.part_transfer(channel, kind, u16::from(address), transfer)
+                })
+            }
+            None => {
+                let access = AccessContext::device(kind);
+                self.switch.do_on_acquired(access, |mut dev| {
+                    dev.upstream_transfer(kind, u16::from(address), transfer)
+                })
+            }
+        }
+    }
+}
+
+impl<'a, DEV, I2C, ID, E> ehal::ErrorType for Router<'a, DEV, I2C, ID>
+where
+    DEV: DoOnAcquired<I2C>,
+    I2C: ehal::I2c<Error = E>,
+    E: ehal::Error,
+{
+    type Error = Error<E>;
+}
+
+impl<'a, DEV, I2C, ID, D, O, E> ehal::I2c for Router<'a, DEV, I2C, ID>
+where
+    DEV: DoOnAcquired<I2C, Delay = D, Observer = O>,
+    I2C: ehal::I2c<Error = E>,
+    ID: PartialEq,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [ehal::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transfer(AccessKind::Transaction, address, |i2c| {
+            i2c.transaction(address, operations)
+        })
+    }
+
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.transfer(AccessKind::Read, address, |i2c| i2c.read(address, read))
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.transfer(AccessKind::Write, address, |i2c| i2c.write(address, write))
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.transfer(AccessKind::WriteRead, address, |i2c| {
+            i2c.write_read(address, write, read)
+        })
+    }
+}
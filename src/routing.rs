@@ -0,0 +1,170 @@
+use crate::{Channel, SlaveAddr, DEFAULT_ADDRESS};
+
+/// Location of a device behind a switch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Route {
+    /// Channel where the device is connected
+    pub channel: Channel,
+    /// Address of the device
+    pub address: u8,
+}
+
+impl Route {
+    /// Create a new route.
+    pub const fn new(channel: Channel, address: u8) -> Self {
+        Route { channel, address }
+    }
+}
+
+/// Inconsistency found in a routing table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoutingError<'a, ID> {
+    /// The same ID appears more than once.
+    DuplicateId(&'a ID),
+    /// Two IDs are routed to the same device.
+    SharedRoute {
+        /// Shared route
+        route: Route,
+        /// IDs routed to it
+        ids: (&'a ID, &'a ID),
+    },
+    /// A device uses the address of the switch itself.
+    SwitchAddress(&'a ID),
+}
+
+impl<ID: core::fmt::Display> core::fmt::Display for RoutingError<'_, ID> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RoutingError::DuplicateId(id) => write!(f, "Device {} is routed more than once", id),
+            RoutingError::SharedRoute { route, ids } => write!(
+                f,
+                "Devices {} and {} are both routed to 0x{:02X} on channel {}",
+                ids.0,
+                ids.1,
+                route.address,
+                route.channel.index()
+            ),
+            RoutingError::SwitchAddress(id) => {
+                write!(f, "Device {} uses the switch address", id)
+            }
+        }
+    }
+}
+
+/// Table mapping logical device IDs to the channel and address of each device
+///
+/// This formalizes the bookkeeping of identical devices sharing an address on
+/// different channels. IDs can be any comparable type, e.g. an enum or an index.
+///
+/// ```
+/// use xca9548a::{Channel, Route, RoutingTable, SlaveAddr};
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Sensor {
+///     Inlet,
+///     Outlet,
+/// }
+///
+/// const ROUTES: RoutingTable<Sensor> = RoutingTable::new(&[
+///     (Sensor::Inlet, Route::new(Channel::Ch0, 0x48)),
+///     (Sensor::Outlet, Route::new(Channel::Ch1, 0x48)),
+/// ]);
+///
+/// ROUTES.validate(SlaveAddr::default()).unwrap();
+/// assert_eq!(Some(Route::new(Channel::Ch1, 0x48)), ROUTES.route(&Sensor::Outlet));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct RoutingTable<'a, ID> {
+    routes: &'a [(ID, Route)],
+}
+
+impl<'a, ID: PartialEq> RoutingTable<'a, ID> {
+    /// Create a routing table from a list of IDs and their routes.
+    pub const fn new(routes: &'a [(ID, Route)]) -> Self {
+        RoutingTable { routes }
+    }
+
+    /// Route of the device with the given `id`.
+    pub fn route(&self, id: &ID) -> Option<Route> {
+        self.routes
+            .iter()
+            .find(|(other, _)| other == id)
+            .map(|(_, route)| *route)
+    }
+
+    /// ID of the device at `address` on `channel`.
+    pub fn id_at(&self, channel: Channel, address: u8) -> Option<&'a ID> {
+        let route = Route::new(channel, address);
+        self.routes
+            .iter()
+            .find(|(_, other)| *other == route)
+            .map(|(id, _)| id)
+    }
+
+    /// IDs and addresses of the devices on `channel`.
+    pub fn on_channel(&self, channel: Channel) -> impl Iterator<Item = (&'a ID, u8)> {
+        self.routes
+            .iter()
+            .filter(move |(_, route)| route.channel == channel)
+            .map(|(id, route)| (id, route.address))
+    }
+
+    /// Mask of the channels with a device routed at `address`.
+    pub fn channels_at(&self, address: u8) -> u8 {
+        self.routes
+            .iter()
+            .filter(|(_, route)| route.address == address)
+            .fold(0, |mask, (_, route)| mask | route.channel.mask())
+    }
+
+    /// Mask of the channels with at least one routed device.
+    pub fn channels(&self) -> u8 {
+        self.routes
+            .iter()
+            .fold(0, |mask, (_, route)| mask | route.channel.mask())
+    }
+
+    /// All IDs and their routes in the order they were declared.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a ID, Route)> {
+        self.routes.iter().map(|(id, route)| (id, *route))
+    }
+
+    /// Check that IDs and routes are unique and that no device uses the
+    /// address of the switch, returning the first inconsistency.
+    pub fn validate(&self, switch_address: SlaveAddr) -> Result<(), RoutingError<'a, ID>> {
+        self.validate_with_base_address(switch_address, DEFAULT_ADDRESS)
+    }
+
+    /// Check the routing table for a switch using a custom base address.
+    ///
+    /// See [`validate()`](#method.validate).
+    pub fn validate_with_base_address(
+        &self,
+        switch_address: SlaveAddr,
+        base_address: u8,
+    ) -> Result<(), RoutingError<'a, ID>> {
+        self.validate_for(switch_address.address(base_address))
+    }
+
+    /// Check the routing table for a switch at the effective `switch_address`.
+    pub(crate) fn validate_for(&self, switch_address: u8) -> Result<(), RoutingError<'a, ID>> {
+        let routes = self.routes;
+        for (i, (id, route)) in routes.iter().enumerate() {
+            if route.address == switch_address {
+                return Err(RoutingError::SwitchAddress(id));
+            }
+            for (other_id, other_route) in &routes[i + 1..] {
+                if id == other_id {
+                    return Err(RoutingError::DuplicateId(id));
+                }
+                if route == other_route {
+                    return Err(RoutingError::SharedRoute {
+                        route: *route,
+                        ids: (id, other_id),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
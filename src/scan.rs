@@ -0,0 +1,258 @@
+use crate::{Channel, Error, Observer, SelectChannels, Watchdog, Xca954xaData};
+use core::ops::RangeInclusive;
+use embedded_hal::{delay::DelayNs, i2c as ehal};
+
+/// First address probed when scanning. Lower addresses are reserved.
+const FIRST_SCAN_ADDRESS: u8 = 0x08;
+/// Last address probed when scanning. Higher addresses are reserved.
+const LAST_SCAN_ADDRESS: u8 = 0x77;
+
+/// Slave devices found on each channel during a scan.
+///
+/// This is a fixed-size inventory of the devices on each channel that can be
+/// kept around for locating devices on modular hardware. See [`where_is()`](#method.where_is).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScanReport {
+    found: [u128; 8],
+}
+
+impl ScanReport {
+    /// Whether a device answered at `address` on `channel`.
+    pub fn contains(&self, channel: Channel, address: u8) -> bool {
+        address < 128 && self.found[usize::from(channel.index())] & (1 << address) != 0
+    }
+
+    /// Addresses of the devices found on `channel` in ascending order.
+    pub fn devices(&self, channel: Channel) -> impl Iterator<Item = u8> {
+        addresses(self.found[usize::from(channel.index())])
+    }
+
+    /// Channels where a device answered at `address` in ascending order.
+    pub fn where_is(&self, address: u8) -> impl Iterator<Item = Channel> + '_ {
+        Channel::iter().filter(move |channel| self.contains(*channel, address))
+    }
+
+    /// Total number of devices found on all channels.
+    pub fn device_count(&self) -> usize {
+        self.found.iter().map(|f| f.count_ones() as usize).sum()
+    }
+
+    /// Differences to a newer report of the same switch.
+    ///
+    /// This can be used to detect devices being plugged in or removed
+    /// between two scans.
+    pub fn diff(&self, newer: &ScanReport) -> ScanDiff {
+        let mut diff = ScanDiff::default();
+        for i in 0..8 {
+            diff.added[i] = newer.found[i] & !self.found[i];
+            diff.removed[i] = self.found[i] & !newer.found[i];
+        }
+        diff
+    }
+
+    pub(crate) fn insert(&mut self, channel: Channel, address: u8) {
+        self.found[usize::from(channel.index())] |= 1 << address;
+    }
+}
+
+/// Differences between two scan reports.
+///
+/// See [`ScanReport::diff()`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScanDiff {
+    added: [u128; 8],
+    removed: [u128; 8],
+}
+
+impl ScanDiff {
+    /// Whether both reports contained the same devices.
+    pub fn is_empty(&self) -> bool {
+        self.changed_channels() == 0
+    }
+
+    /// Addresses of the devices that appeared on `channel` in ascending order.
+    pub fn added(&self, channel: Channel) -> impl Iterator<Item = u8> {
+        addresses(self.added[usize::from(channel.index())])
+    }
+
+    /// Addresses of the devices that disappeared from `channel` in ascending order.
+    pub fn removed(&self, channel: Channel) -> impl Iterator<Item = u8> {
+        addresses(self.removed[usize::from(channel.index())])
+    }
+
+    /// Mask of the channels where devices appeared or disappeared.
+    pub fn changed_channels(&self) -> u8 {
+        Channel::iter()
+            .filter(|channel| {
+                let i = usize::from(channel.index());
+                self.added[i] | self.removed[i] != 0
+            })
+            .fold(0, |mask, channel| mask | channel.mask())
+    }
+}
+
+fn addresses(found: u128) -> impl Iterator<Item = u8> {
+    (0..128).filter(move |address| found & (1 << address) != 0)
+}
+
+/// Scan parameters
+///
+/// By default all channels are scanned and every non-reserved address
+/// (0x08-0x77) is probed. See [`scan()`](struct.Xca9548a.html#method.scan).
+///
+/// ```
+/// use xca9548a::ScanConfig;
+///
+/// // Skip other multiplexers and a read-sensitive device and stop at the first device found.
+/// let config = ScanConfig::new()
+///     .channels(0b0000_0011)
+///     .skip_range(0x70..=0x77)
+///     .skip(0x50)
+///     .stop_after(1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanConfig {
+    channels: u8,
+    first: u8,
+    last: u8,
+    skipped: u128,
+    stop_after: Option<usize>,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        ScanConfig {
+            channels: 0xff,
+            first: FIRST_SCAN_ADDRESS,
+            last: LAST_SCAN_ADDRESS,
+            skipped: 0,
+            stop_after: None,
+        }
+    }
+}
+
+impl ScanConfig {
+    /// Create a configuration scanning all channels and non-reserved addresses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scan only the `channels` present on the device.
+    pub fn channels(mut self, channels: u8) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Probe only the addresses in the given range.
+    ///
+    /// The reserved addresses 0x00-0x07 are never probed, e.g. to avoid
+    /// issuing a general call, so the range is clamped to start at 0x08.
+    pub fn addresses(mut self, addresses: RangeInclusive<u8>) -> Self {
+        self.first = (*addresses.start()).max(FIRST_SCAN_ADDRESS);
+        self.last = (*addresses.end()).min(0x7f);
+        self
+    }
+
+    /// Do not probe `address`.
+    ///
+    /// Addresses outside of the 7-bit range are ignored.
+    pub fn skip(mut self, address: u8) -> Self {
+        if address > 0x7f {
+            return self;
+        }
+        self.skipped |= 1 << address;
+        self
+    }
+
+    /// Do not probe the addresses in the given range.
+    pub fn skip_range(mut self, addresses: RangeInclusive<u8>) -> Self {
+        for address in addresses.filter(|address| *address < 128) {
+            self.skipped |= 1 << address;
+        }
+        self
+    }
+
+    /// Stop scanning once `devices` devices have been found.
+    pub fn stop_after(mut self, devices: usize) -> Self {
+        self.stop_after = Some(devices);
+        self
+    }
+
+    fn probes(&self, address: u8) -> bool {
+        (self.first..=self.last).contains(&address) && self.skipped & (1 << address) == 0
+    }
+}
+
+impl<I2C, D, O, E> Xca954xaData<I2C, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    /// Probe the configured addresses on each of the configured channels one by one.
+    ///
+    /// The switch address itself is skipped and the previous channel
+    /// selection is restored afterwards.
+    pub(crate) fn scan(
+        &mut self,
+        config: &ScanConfig,
+        watchdog: &mut impl Watchdog,
+    ) -> Result<ScanReport, Error<E>> {
+        let previous = self.current_channels()?;
+        let mut report = ScanReport::default();
+        let mut remaining = config.stop_after;
+        'channels: for channel in Channel::in_mask(config.channels & self.channel_mask) {
+            if remaining == Some(0) {
+                break;
+            }
+            self.select_channels(channel.mask())?;
+            for address in (config.first..=config.last).filter(|a| config.probes(*a)) {
+                if address == self.address {
+                    continue;
+                }
+                watchdog.feed();
+                if self.i2c.read(address, &mut [0]).is_ok() {
+                    report.insert(channel, address);
+                    remaining = remaining.map(|count| count - 1);
+                    if remaining == Some(0) {
+                        break 'channels;
+                    }
+                }
+            }
+        }
+        self.select_channels(previous)?;
+        Ok(report)
+    }
+
+    /// Probe the configured addresses on the upstream bus with all channels
+    /// deselected, returning a bit for each address where a device answered.
+    ///
+    /// The switch address itself is skipped and the previous channel
+    /// selection is restored afterwards.
+    pub(crate) fn scan_upstream(&mut self, config: &ScanConfig) -> Result<u128, Error<E>> {
+        let previous = self.current_channels()?;
+        self.select_channels(0)?;
+        let mut found = 0;
+        for address in (config.first..=config.last).filter(|a| config.probes(*a)) {
+            if address != self.address && self.i2c.read(address, &mut [0]).is_ok() {
+                found |= 1 << address;
+            }
+        }
+        self.select_channels(previous)?;
+        Ok(found)
+    }
+
+    /// Probe `address` on `channel` with a one-byte read, selecting the channel
+    /// like a transfer through its split part.
+    ///
+    /// The probe is not retried nor recorded as an error if nothing answers.
+    pub(crate) fn probe(&mut self, channel: Channel, address: u8) -> Result<bool, Error<E>> {
+        self.select_part(channel)?;
+        let found = self.i2c.read(address, &mut [0]).is_ok();
+        if self.config.auto_deselect {
+            self.select_channels(0)?;
+        }
+        Ok(found)
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{codec, Channel, Error, Observer, SelectChannels, Xca954xaData};
+use embedded_hal::{delay::DelayNs, i2c as ehal};
+
+/// Result of a control register self-test
+///
+/// See [`self_test()`](struct.Xca9548a.html#method.self_test).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SelfTestReport {
+    stuck_low: u8,
+    stuck_high: u8,
+    unexpected_bits: u8,
+}
+
+impl SelfTestReport {
+    /// Whether all checks passed.
+    pub fn passed(&self) -> bool {
+        *self == SelfTestReport::default()
+    }
+
+    /// Channels that could not be enabled.
+    pub fn stuck_low(&self) -> u8 {
+        self.stuck_low
+    }
+
+    /// Channels that remained enabled when another or no channel was selected.
+    pub fn stuck_high(&self) -> u8 {
+        self.stuck_high
+    }
+
+    /// Control register bits that were read as `1` although they do not
+    /// correspond to any channel or interrupt present on the device.
+    pub fn unexpected_bits(&self) -> u8 {
+        self.unexpected_bits
+    }
+
+    /// Channels that failed the test.
+    pub fn failed_channels(&self) -> impl Iterator<Item = Channel> {
+        Channel::in_mask(self.stuck_low | self.stuck_high)
+    }
+}
+
+impl<I2C, D, O, E> Xca954xaData<I2C, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    /// Select each channel on its own, then none, reading back the control register each time.
+    ///
+    /// The previous channel selection is restored afterwards.
+    pub(crate) fn self_test(&mut self) -> Result<SelfTestReport, Error<E>> {
+        let previous = self.selected_channel_mask;
+        let mask = self.channel_mask;
+        let mut report = SelfTestReport::default();
+        let patterns = Channel::in_mask(mask).map(|channel| channel.mask());
+        for pattern in patterns.chain(core::iter::once(0)) {
+            self.i2c
+                .write(self.address, &[pattern])
+                .map_err(Error::ChannelSelect)?;
+            let value = self.read_control_register()?;
+            let channels = codec::decode_channels(value, mask);
+            report.stuck_low |= pattern & !channels;
+            report.stuck_high |= channels & !pattern;
+            report.unexpected_bits |= codec::reserved_bits(value, mask);
+        }
+        self.cache_dirty = true;
+        self.select_channels(previous)?;
+        Ok(report)
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{AccessKind, Channel, Error, Observer, SelectChannels, Xca954xaData};
+use embedded_hal::{delay::DelayNs, i2c as ehal};
+
+/// Step of a transaction sequence
+///
+/// See [`run_sequence()`](struct.Xca9548a.html#method.run_sequence).
+#[derive(Debug, PartialEq, Eq)]
+pub enum Step<'a> {
+    /// Write `data` to the device at `address` on `channel`.
+    Write {
+        /// Channel where the device is connected
+        channel: Channel,
+        /// Device address
+        address: u8,
+        /// Data to write
+        data: &'a [u8],
+    },
+    /// Read from the device at `address` on `channel` into `buffer`.
+    Read {
+        /// Channel where the device is connected
+        channel: Channel,
+        /// Device address
+        address: u8,
+        /// Buffer to fill, whose length determines the number of bytes read
+        buffer: &'a mut [u8],
+    },
+    /// Write `data` to the device at `address` on `channel` and read into `buffer`.
+    WriteRead {
+        /// Channel where the device is connected
+        channel: Channel,
+        /// Device address
+        address: u8,
+        /// Data to write
+        data: &'a [u8],
+        /// Buffer to fill, whose length determines the number of bytes read
+        buffer: &'a mut [u8],
+    },
+    /// Wait `ns` nanoseconds with the settle delay provider.
+    Delay {
+        /// Delay in nanoseconds
+        ns: u32,
+    },
+}
+
+impl Step<'_> {
+    /// Channel used by the step, if any.
+    fn channel(&self) -> Option<Channel> {
+        match self {
+            Step::Write { channel, .. }
+            | Step::Read { channel, .. }
+            | Step::WriteRead { channel, .. } => Some(*channel),
+            Step::Delay { .. } => None,
+        }
+    }
+}
+
+impl<I2C, D, O, E> Xca954xaData<I2C, D, O>
+where
+    I2C: ehal::I2c<Error = E>,
+    D: DelayNs,
+    O: Observer,
+    E: ehal::Error,
+{
+    /// Run the `steps` in order, stopping at the first failure.
+    ///
+    /// Each channel is only selected when it differs from the one of the previous step.
+    /// Nothing is done if any step uses a channel not present on the device.
+    pub(crate) fn run_sequence(&mut self, steps: &mut [Step<'_>]) -> Result<(), (usize, Error<E>)> {
+        for (index, step) in steps.iter().enumerate() {
+            if let Some(channel) = step.channel() {
+                if channel.mask() & self.channel_mask == 0 {
+                    return Err((index, Error::InvalidChannels(channel.mask())));
+                }
+            }
+        }
+        for (index, step) in steps.iter_mut().enumerate() {
+            self.run_step(step).map_err(|e| (index, e))?;
+        }
+        if self.config.auto_deselect {
+            self.select_channels(0).map_err(|e| (steps.len(), e))?;
+        }
+        Ok(())
+    }
+
+    fn run_step(&mut self, step: &mut Step<'_>) -> Result<(), Error<E>> {
+        match step {
+            Step::Write {
+                channel,
+                address,
+                data,
+            } => {
+                self.select_part(*channel)?;
+                self.transfer(AccessKind::Write, u16::from(*address), |i2c| {
+                    i2c.write(*address, data)
+                })
+            }
+            Step::Read {
+                channel,
+                address,
+                buffer,
+            } => {
+                self.select_part(*channel)?;
+                self.transfer(AccessKind::Read, u16::from(*address), |i2c| {
+                    i2c.read(*address, buffer)
+                })
+            }
+            Step::WriteRead {
+                channel,
+                address,
+                data,
+                buffer,
+            } => {
+                self.select_part(*channel)?;
+                self.transfer(AccessKind::WriteRead, u16::from(*address), |i2c| {
+                    i2c.write_read(*address, data, buffer)
+                })
+            }
+            Step::Delay { ns } => {
+                self.delay.delay_ns(*ns);
+                Ok(())
+            }
+        }
+    }
+}
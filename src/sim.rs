@@ -0,0 +1,339 @@
+use crate::record::{Record, RecordedOperation, Recording};
+use crate::{Channel, DEFAULT_ADDRESS};
+use embedded_hal::i2c::{self as ehal, ErrorKind, NoAcknowledgeSource};
+use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+
+/// Simulated T/PCA9548A for testing drivers of the downstream devices
+///
+/// It implements the I²C traits so that it can be used as the bus of the
+/// real driver. Single-byte writes to its own address update the control
+/// register and reads from its address return it. Any other transaction is
+/// routed to the bus attached to the selected channel, for example an
+/// `embedded-hal-mock` instance. This allows testing the channel routing
+/// without hardware and without interleaving the control register writes
+/// in the mock expectations.
+///
+/// With several channels selected, the transaction is forwarded to the bus
+/// of each of them in ascending order and reads return the data of the last one.
+/// Transactions are not acknowledged if no bus is attached to the selected channels.
+///
+/// Faults can be injected to test the error handling and recovery end to end.
+/// See [`set_control_nack()`](#method.set_control_nack).
+///
+/// Available with the `test-util` feature.
+///
+/// ```
+/// use embedded_hal::i2c::I2c;
+/// use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+/// use xca9548a::{Channel, SimXca9548a, SlaveAddr, Xca9548a};
+///
+/// let sensor = I2cMock::new(&[I2cTrans::read(0x48, vec![0x12, 0x34])]);
+/// let sim = SimXca9548a::default().attach(Channel::Ch3, sensor);
+/// let switch = Xca9548a::new(sim, SlaveAddr::default());
+/// let mut temperature = [0; 2];
+/// switch.split().i2c3.read(0x48, &mut temperature).unwrap();
+/// assert_eq!([0x12, 0x34], temperature);
+///
+/// let mut sim = switch.destroy();
+/// assert_eq!(0b0000_1000, sim.control());
+/// sim.detach(Channel::Ch3).unwrap().done();
+/// ```
+#[derive(Debug)]
+pub struct SimXca9548a<B> {
+    address: u8,
+    control: u8,
+    buses: [Option<B>; 8],
+    faults: Faults,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Faults {
+    control_nack: bool,
+    nack_addresses: u128,
+    bytes_until_arbitration_loss: Option<usize>,
+    stuck_mask: u8,
+    stuck_value: u8,
+}
+
+impl<B> Default for SimXca9548a<B> {
+    fn default() -> Self {
+        Self::new(DEFAULT_ADDRESS)
+    }
+}
+
+impl<B> SimXca9548a<B> {
+    /// Create a new simulated device at `address` with all channels deselected
+    /// and no buses attached.
+    pub fn new(address: u8) -> Self {
+        SimXca9548a {
+            address,
+            control: 0,
+            buses: core::array::from_fn(|_| None),
+            faults: Faults::default(),
+        }
+    }
+
+    /// Attach `bus` to `channel`, replacing any previous one.
+    pub fn attach(mut self, channel: Channel, bus: B) -> Self {
+        self.buses[usize::from(channel.index())] = Some(bus);
+        self
+    }
+
+    /// Detach and return the bus of `channel`.
+    pub fn detach(&mut self, channel: Channel) -> Option<B> {
+        self.buses[usize::from(channel.index())].take()
+    }
+
+    /// Bus attached to `channel`.
+    pub fn bus_mut(&mut self, channel: Channel) -> Option<&mut B> {
+        self.buses[usize::from(channel.index())].as_mut()
+    }
+
+    /// Current value of the control register.
+    pub fn control(&self) -> u8 {
+        self.control
+    }
+
+    /// Address of the simulated device.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Do not acknowledge any access to the control register.
+    pub fn set_control_nack(&mut self, nack: bool) {
+        self.faults.control_nack = nack;
+    }
+
+    /// Do not acknowledge transactions to the downstream `address`
+    /// on any channel.
+    pub fn set_address_nack(&mut self, address: u8, nack: bool) {
+        let bit = 1 << (address & 0x7f);
+        if nack {
+            self.faults.nack_addresses |= bit;
+        } else {
+            self.faults.nack_addresses &= !bit;
+        }
+    }
+
+    /// Lose the bus arbitration in the transaction during which the total
+    /// number of bytes transferred exceeds `bytes`.
+    ///
+    /// The fault happens once. `None` disables it.
+    pub fn set_arbitration_loss_after(&mut self, bytes: Option<usize>) {
+        self.faults.bytes_until_arbitration_loss = bytes;
+    }
+
+    /// Make the control register bits in `mask` stuck at the value they have in `value`.
+    ///
+    /// This simulates a channel that can not be enabled or disabled.
+    pub fn set_stuck_bits(&mut self, mask: u8, value: u8) {
+        self.faults.stuck_mask = mask;
+        self.faults.stuck_value = value;
+        self.control = self.apply_stuck_bits(self.control);
+    }
+
+    /// Remove all injected faults.
+    pub fn clear_faults(&mut self) {
+        self.faults = Faults::default();
+    }
+
+    fn apply_stuck_bits(&self, control: u8) -> u8 {
+        (control & !self.faults.stuck_mask) | (self.faults.stuck_value & self.faults.stuck_mask)
+    }
+
+    /// Check the injected faults before a transaction of `bytes` bytes to `address`.
+    fn check_faults(&mut self, address: u8, bytes: usize) -> Result<(), ErrorKind> {
+        if let Some(remaining) = self.faults.bytes_until_arbitration_loss {
+            if bytes > remaining {
+                self.faults.bytes_until_arbitration_loss = None;
+                return Err(ErrorKind::ArbitrationLoss);
+            }
+            self.faults.bytes_until_arbitration_loss = Some(remaining - bytes);
+        }
+        let nack = if address == self.address {
+            self.faults.control_nack
+        } else {
+            self.faults.nack_addresses & (1 << (address & 0x7f)) != 0
+        };
+        if nack {
+            Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl SimXca9548a<I2cMock> {
+    /// Create a simulated device at `address` replaying the downstream
+    /// traffic of a recording.
+    ///
+    /// This allows recording the traffic of the real hardware once with a
+    /// [`Recorder`](crate::record::Recorder), storing it as a fixture and
+    /// replaying it in tests. Each channel with recorded traffic gets an
+    /// `embedded-hal-mock` bus expecting the transactions done on it in order,
+    /// so the interleaving with other channels and the channel selections may differ.
+    ///
+    /// Transactions with no channel selected are ignored. Single writes, single
+    /// reads and writes followed by a read are expected as `write()`, `read()`
+    /// and `write_read()` calls, any other sequence as a `transaction()` call.
+    /// Errors of `transaction()` calls can not be reproduced and are ignored.
+    pub fn from_recording(address: u8, recording: &Recording) -> Self {
+        let mut expectations: [Vec<I2cTrans>; 8] = Default::default();
+        for record in recording.records() {
+            if let Record::Transaction {
+                channels,
+                address: slave,
+                operations,
+                error,
+            } = record
+            {
+                if *slave == address {
+                    continue;
+                }
+                for channel in Channel::in_mask(*channels) {
+                    let expected = &mut expectations[usize::from(channel.index())];
+                    expected.extend(expected_transactions(*slave, operations, *error));
+                }
+            }
+        }
+        let mut sim = SimXca9548a::new(address);
+        for channel in Channel::iter() {
+            let expected = &expectations[usize::from(channel.index())];
+            if !expected.is_empty() {
+                sim = sim.attach(channel, I2cMock::new(expected));
+            }
+        }
+        sim
+    }
+
+    /// Check that all the recorded transactions were replayed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are transactions left on any channel.
+    pub fn done(&mut self) {
+        for bus in self.buses.iter_mut().flatten() {
+            bus.done();
+        }
+    }
+}
+
+fn expected_transactions(
+    address: u8,
+    operations: &[RecordedOperation],
+    error: Option<ErrorKind>,
+) -> Vec<I2cTrans> {
+    let single = match operations {
+        [RecordedOperation::Write(data)] => Some(I2cTrans::write(address, data.clone())),
+        [RecordedOperation::Read(data)] => Some(I2cTrans::read(address, data.clone())),
+        [RecordedOperation::Write(write), RecordedOperation::Read(read)] => {
+            Some(I2cTrans::write_read(address, write.clone(), read.clone()))
+        }
+        _ => None,
+    };
+    if let Some(transaction) = single {
+        return vec![match error {
+            Some(error) => transaction.with_error(error),
+            None => transaction,
+        }];
+    }
+    let mut transactions = vec![I2cTrans::transaction_start(address)];
+    transactions.extend(operations.iter().map(|operation| match operation {
+        RecordedOperation::Write(data) => I2cTrans::write(address, data.clone()),
+        RecordedOperation::Read(data) => I2cTrans::read(address, data.clone()),
+    }));
+    transactions.push(I2cTrans::transaction_end(address));
+    transactions
+}
+
+impl<B: ehal::I2c> SimXca9548a<B> {
+    /// Access the control register. The last byte written is stored
+    /// and reads return the register value.
+    fn access_control(&mut self, operations: &mut [ehal::Operation<'_>]) -> Result<(), ErrorKind> {
+        for operation in operations {
+            match operation {
+                ehal::Operation::Write(data) => {
+                    if let Some(value) = data.last() {
+                        self.control = self.apply_stuck_bits(*value);
+                    }
+                }
+                ehal::Operation::Read(buffer) => buffer.fill(self.control),
+            }
+        }
+        Ok(())
+    }
+
+    /// Forward a transaction to the buses of the selected channels.
+    fn route(
+        &mut self,
+        mut f: impl FnMut(&mut B) -> Result<(), B::Error>,
+    ) -> Result<(), ErrorKind> {
+        let mut acknowledged = false;
+        for channel in Channel::in_mask(self.control) {
+            if let Some(bus) = self.buses[usize::from(channel.index())].as_mut() {
+                acknowledged = true;
+                f(bus).map_err(|e| ehal::Error::kind(&e))?;
+            }
+        }
+        if acknowledged {
+            Ok(())
+        } else {
+            Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+        }
+    }
+}
+
+impl<B> ehal::ErrorType for SimXca9548a<B> {
+    type Error = ErrorKind;
+}
+
+impl<B: ehal::I2c> ehal::I2c for SimXca9548a<B> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [ehal::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let bytes = operations
+            .iter()
+            .map(|operation| match operation {
+                ehal::Operation::Write(data) => data.len(),
+                ehal::Operation::Read(buffer) => buffer.len(),
+            })
+            .sum();
+        self.check_faults(address, bytes)?;
+        if address == self.address {
+            return self.access_control(operations);
+        }
+        self.route(|bus| bus.transaction(address, operations))
+    }
+
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.check_faults(address, read.len())?;
+        if address == self.address {
+            return self.access_control(&mut [ehal::Operation::Read(read)]);
+        }
+        self.route(|bus| bus.read(address, read))
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.check_faults(address, write.len())?;
+        if address == self.address {
+            return self.access_control(&mut [ehal::Operation::Write(write)]);
+        }
+        self.route(|bus| bus.write(address, write))
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.check_faults(address, write.len() + read.len())?;
+        if address == self.address {
+            return self
+                .access_control(&mut [ehal::Operation::Write(write), ehal::Operation::Read(read)]);
+        }
+        self.route(|bus| bus.write_read(address, write, read))
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{AccessKind, Channel};
+
+/// Bus health counters of a device
+///
+/// All counters wrap around on overflow, so compare snapshots with
+/// `wrapping_sub()` to follow trends. Available with the `stats` feature.
+/// See [`stats()`](struct.Xca9548a.html#method.stats).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    channel_switches: u32,
+    select_errors: u32,
+    transactions: [u32; 8],
+    errors: [u32; 8],
+    upstream_transactions: u32,
+    upstream_errors: u32,
+    pub(crate) borrow_failures: u32,
+}
+
+impl Stats {
+    /// Number of channel selections written to the device.
+    pub fn channel_switches(&self) -> u32 {
+        self.channel_switches
+    }
+
+    /// Number of channel selections that failed.
+    pub fn select_errors(&self) -> u32 {
+        self.select_errors
+    }
+
+    /// Number of transfers done while `channel` was selected.
+    pub fn transactions(&self, channel: Channel) -> u32 {
+        self.transactions[usize::from(channel.index())]
+    }
+
+    /// Number of transfers that failed while `channel` was selected.
+    pub fn errors(&self, channel: Channel) -> u32 {
+        self.errors[usize::from(channel.index())]
+    }
+
+    /// Number of transfers done while no channel was selected.
+    pub fn upstream_transactions(&self) -> u32 {
+        self.upstream_transactions
+    }
+
+    /// Number of transfers that failed while no channel was selected.
+    pub fn upstream_errors(&self) -> u32 {
+        self.upstream_errors
+    }
+
+    /// Number of accesses rejected because the device was already in use.
+    pub fn borrow_failures(&self) -> u32 {
+        self.borrow_failures
+    }
+
+    pub(crate) fn record(&mut self, channels: u8, kind: AccessKind, failed: bool) {
+        fn increment(counter: &mut u32, condition: bool) {
+            if condition {
+                *counter = counter.wrapping_add(1);
+            }
+        }
+
+        if kind == AccessKind::SelectChannels {
+            increment(&mut self.channel_switches, !failed);
+            increment(&mut self.select_errors, failed);
+        } else if channels == 0 {
+            increment(&mut self.upstream_transactions, true);
+            increment(&mut self.upstream_errors, failed);
+        } else {
+            for channel in Channel::in_mask(channels) {
+                let index = usize::from(channel.index());
+                increment(&mut self.transactions[index], true);
+                increment(&mut self.errors[index], failed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{SlaveAddr, Xca9548a};
+    use embedded_hal::i2c::I2c;
+    use embedded_hal_mock::eh1::i2c::Mock as I2cMock;
+
+    #[test]
+    fn counts_borrow_failures() {
+        let mut switch = Xca9548a::new(I2cMock::new(&[]), SlaveAddr::default());
+        {
+            let _data = switch.data.borrow_mut();
+            let mut parts = switch.split();
+            parts.i2c0.write(0x20, &[0]).unwrap_err();
+            parts.i2c1.read(0x20, &mut [0]).unwrap_err();
+        }
+        assert_eq!(2, switch.stats().unwrap().borrow_failures());
+        switch.reset_stats();
+        assert_eq!(0, switch.stats().unwrap().borrow_failures());
+        switch.destroy().done();
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{Channel, SlaveAddr, DEFAULT_ADDRESS};
+
+/// Address conflict found in a topology
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// A device uses the address of the switch itself.
+    SwitchAddress {
+        /// Channel of the device
+        channel: Channel,
+        /// Address of the device
+        address: u8,
+    },
+    /// Two devices sharing an address are connected at the same time.
+    DuplicateAddress {
+        /// Shared address
+        address: u8,
+        /// Channels of the devices. Both are the same for devices on the same channel.
+        channels: (Channel, Channel),
+    },
+}
+
+impl core::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Conflict::SwitchAddress { channel, address } => write!(
+                f,
+                "Device 0x{:02X} on channel {} uses the switch address",
+                address,
+                channel.index()
+            ),
+            Conflict::DuplicateAddress { address, channels } => write!(
+                f,
+                "Devices 0x{:02X} on channels {} and {} can be connected at the same time",
+                address,
+                channels.0.index(),
+                channels.1.index()
+            ),
+        }
+    }
+}
+
+/// Declared devices connected to the channels of a switch
+///
+/// This validates the topology without any I²C communication, so it can be
+/// done at initialization or in host-side tests.
+#[derive(Debug, Clone, Copy)]
+pub struct Topology<'a> {
+    switch_address: u8,
+    devices: &'a [(Channel, u8)],
+}
+
+impl<'a> Topology<'a> {
+    /// Create a topology of a switch and the devices (channel and address)
+    /// connected to it.
+    pub fn new(switch_address: SlaveAddr, devices: &'a [(Channel, u8)]) -> Self {
+        Topology {
+            switch_address: switch_address.address(DEFAULT_ADDRESS),
+            devices,
+        }
+    }
+
+    /// Find the conflicts when enabling each of the `enabled` channel masks.
+    ///
+    /// Devices with the same address on the same channel always conflict.
+    /// Devices on different channels only conflict if both channels are
+    /// enabled at the same time in any of the masks.
+    pub fn conflicts<'b>(&'b self, enabled: &'b [u8]) -> impl Iterator<Item = Conflict> + 'b {
+        let switch_address = self.switch_address;
+        let devices = self.devices;
+        let switch = devices
+            .iter()
+            .filter(move |(_, address)| *address == switch_address)
+            .map(|&(channel, address)| Conflict::SwitchAddress { channel, address });
+        let duplicates = (0..devices.len())
+            .flat_map(move |i| (i + 1..devices.len()).map(move |j| (devices[i], devices[j])))
+            .filter(move |((ch1, address1), (ch2, address2))| {
+                let both = ch1.mask() | ch2.mask();
+                address1 == address2
+                    && (ch1 == ch2 || enabled.iter().any(|mask| mask & both == both))
+            })
+            .map(|((ch1, address), (ch2, _))| Conflict::DuplicateAddress {
+                address,
+                channels: (ch1, ch2),
+            });
+        switch.chain(duplicates)
+    }
+
+    /// Validate the topology for the `enabled` channel masks, returning the first conflict.
+    ///
+    /// See [`conflicts()`](#method.conflicts).
+    pub fn validate(&self, enabled: &[u8]) -> Result<(), Conflict> {
+        match self.conflicts(enabled).next() {
+            Some(conflict) => Err(conflict),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{AccessKind, Observer};
+use core::fmt::Write;
+use embedded_hal::i2c::ErrorKind;
+
+/// Observer writing a line for every channel selection and transfer
+///
+/// The lines contain the selected channels, the slave address, the kind of
+/// transfer and its result, so that the bus traffic can be correlated with
+/// logic analyzer captures. They are written to any [`core::fmt::Write`]
+/// implementation, e.g. a UART or RTT channel, or a `String`:
+///
+/// ```text
+/// select 0x04 ok
+/// Write 0x48 on 0x04 ok
+/// WriteRead 0x48 on 0x04 err NoAcknowledge(Address)
+/// ```
+///
+/// Errors of the writer are ignored.
+/// See [`with_observer()`](struct.Xca9548a.html#method.with_observer).
+///
+/// ```
+/// use embedded_hal::i2c::I2c;
+/// use xca9548a::{SlaveAddr, Tracer, Xca9548a};
+/// # use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+/// # let dev = I2cMock::new(&[
+/// #     I2cTrans::write(0x70, vec![0x04]),
+/// #     I2cTrans::write(0x48, vec![0x01]),
+/// # ]);
+///
+/// let switch = Xca9548a::new(dev, SlaveAddr::default()).with_observer(Tracer::new(String::new()));
+/// switch.split().i2c2.write(0x48, &[0x01]).unwrap();
+/// let (switch, tracer) = switch.release_observer();
+/// assert_eq!("select 0x04 ok\nWrite 0x48 on 0x04 ok\n", tracer.into_inner());
+/// # switch.destroy().done();
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Tracer<W> {
+    writer: W,
+}
+
+impl<W: Write> Tracer<W> {
+    /// Create a new tracer writing to `writer`.
+    pub fn new(writer: W) -> Self {
+        Tracer { writer }
+    }
+
+    /// Access the writer.
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Destroy the tracer, returning the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn result(&mut self, result: Result<(), ErrorKind>) {
+        let _ = match result {
+            Ok(()) => writeln!(self.writer, "ok"),
+            Err(e) => writeln!(self.writer, "err {:?}", e),
+        };
+    }
+}
+
+impl<W: Write> Observer for Tracer<W> {
+    fn after_select(&mut self, channels: u8, result: Result<(), ErrorKind>) {
+        let _ = write!(self.writer, "select {:#04x} ", channels);
+        self.result(result);
+    }
+
+    fn after_transfer(
+        &mut self,
+        channels: u8,
+        address: u16,
+        kind: AccessKind,
+        result: Result<(), ErrorKind>,
+    ) {
+        let _ = write!(
+            self.writer,
+            "{:?} {:#04x} on {:#04x} ",
+            kind, address, channels
+        );
+        self.result(result);
+    }
+}
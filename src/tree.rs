@@ -0,0 +1,69 @@
+/// Declare a tree of cascaded multiplexers and bind its leaf I²C buses.
+///
+/// Each node creates a device on a bus with an address and lists its
+/// `leaves` (named split parts) and `children` (devices connected to one of its channels).
+/// The devices and leaves are bound as local variables with the given names.
+/// Talking to a leaf selects the whole path to it.
+///
+/// ```no_run
+/// use embedded_hal::i2c::I2c;
+/// use linux_embedded_hal::I2cdev;
+/// use xca9548a::{mux_tree, SlaveAddr};
+///
+/// let dev = I2cdev::new("/dev/i2c-1").unwrap();
+/// mux_tree! {
+///     let backplane = Xca9548a(dev, SlaveAddr::default()) {
+///         leaves { psu: i2c0, fan: i2c1 }
+///         children {
+///             slot0: Xca9543a(i2c4, SlaveAddr::Alternative(false, false, true)) {
+///                 leaves { slot0_sensor: i2c0, slot0_eeprom: i2c1 }
+///                 children {}
+///             },
+///             slot1: Xca9543a(i2c5, SlaveAddr::Alternative(false, false, true)) {
+///                 leaves { slot1_sensor: i2c0, slot1_eeprom: i2c1 }
+///                 children {}
+///             }
+///         }
+///     }
+/// }
+///
+/// let slave_address: u8 = 0x48;
+/// let mut temperature = [0; 2];
+/// slot0_sensor.read(slave_address, &mut temperature).unwrap();
+/// slot1_sensor.read(slave_address, &mut temperature).unwrap();
+/// ```
+#[macro_export]
+macro_rules! mux_tree {
+    (
+        let $root:ident = $ty:ident ( $bus:expr, $address:expr ) { $($body:tt)* }
+    ) => {
+        #[allow(unused_mut)]
+        let mut $root = $crate::$ty::new($bus, $address);
+        $crate::mux_tree!(@node $root { $($body)* });
+    };
+    (
+        @node $dev:ident {
+            leaves { $( $leaf:ident : $leaf_channel:ident ),* $(,)? }
+            children {
+                $(
+                    $child:ident : $child_ty:ident ( $child_channel:ident, $child_address:expr )
+                    { $($child_body:tt)* }
+                ),* $(,)?
+            }
+        }
+    ) => {
+        #[allow(unused_variables)]
+        let parts = $dev.split();
+        $(
+            #[allow(unused_mut)]
+            let mut $leaf = parts.$leaf_channel;
+        )*
+        $(
+            #[allow(unused_mut)]
+            let mut $child = $crate::$child_ty::new(parts.$child_channel, $child_address);
+        )*
+        $(
+            $crate::mux_tree!(@node $child { $($child_body)* });
+        )*
+    };
+}
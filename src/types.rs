@@ -1,13 +1,225 @@
-use crate::Xca954xaData;
-use core::cell;
+use crate::{builder::Config, codec::Mask, private, NoObserver, Xca954xaData, DEFAULT_ADDRESS};
+use core::{cell, marker::PhantomData};
+use embedded_hal::{delay::DelayNs, i2c::ErrorKind};
 
 /// All possible errors in this crate
-#[derive(Debug)]
+///
+/// New variants may be added in minor releases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Error<E: core::fmt::Debug> {
     /// I²C bus error
     I2C(E),
+    /// I²C bus error while writing the control register to select channels
+    ChannelSelect(E),
+    /// Invalid slave address
+    InvalidAddress(AddressError),
+    /// Some of the channels do not exist on the device (only in strict mode).
+    ///
+    /// Contains the requested channel mask.
+    InvalidChannels(u8),
+    /// More than one channel would be selected at the same time (only in
+    /// single-channel mode).
+    ///
+    /// Contains the requested channel mask.
+    MultipleChannels(u8),
+    /// The device is not in its power-on default state.
+    ///
+    /// Contains the channels found selected.
+    NotPowerOnDefault(u8),
+    /// Error setting the RESET pin
+    Pin,
+    /// The control register read back after writing it does not match the written channels.
+    ///
+    /// Only when the write verification is enabled.
+    VerificationFailed {
+        /// Channels written
+        expected: u8,
+        /// Channels read back
+        actual: u8,
+    },
+    /// The control register does not match the cached channel selection.
+    ///
+    /// The channels may have been changed by another master, a reset or an upset.
+    /// Only when the integrity check is enabled. The cache is updated to the
+    /// channels read and the transfer is not done.
+    ExternalChange {
+        /// Channels selected according to the cache
+        cached: u8,
+        /// Channels read from the device
+        actual: u8,
+    },
+    /// The channel path does not go through the upstream channel of this device.
+    InvalidPath(ChannelPath),
     /// Could not acquire device. Maybe it is already acquired.
-    CouldNotAcquireDevice,
+    ///
+    /// Contains the access that was attempted.
+    CouldNotAcquireDevice(AccessContext),
+    /// The split part was created before the device was split again or its
+    /// parts were invalidated.
+    ///
+    /// Contains the channel of the part.
+    StaleHandle(Channel),
+    /// A router has devices at this address on more than one channel and
+    /// can not tell where to send the transfer.
+    AmbiguousAddress(u8),
+    /// There is no device at this index in a multiplexer group.
+    NoSuchDevice(usize),
+}
+
+/// Kind of operation attempted on the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    /// I²C read
+    Read,
+    /// I²C write
+    Write,
+    /// I²C write followed by a read
+    WriteRead,
+    /// I²C transaction
+    Transaction,
+    /// Channel selection
+    SelectChannels,
+    /// Channel or interrupt status read
+    ReadStatus,
+    /// Channel scan
+    Scan,
+    /// Query of the driver state without bus access
+    Inspect,
+}
+
+/// Access attempted when the device could not be acquired
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessContext {
+    /// Attempted operation
+    pub kind: AccessKind,
+    /// Channel of the split part through which the access was attempted.
+    ///
+    /// `None` if the access was attempted on the device itself.
+    pub channel: Option<Channel>,
+}
+
+impl AccessContext {
+    pub(crate) fn device(kind: AccessKind) -> Self {
+        AccessContext {
+            kind,
+            channel: None,
+        }
+    }
+
+    pub(crate) fn part(kind: AccessKind, channel: Channel) -> Self {
+        AccessContext {
+            kind,
+            channel: Some(channel),
+        }
+    }
+}
+
+impl<E: core::fmt::Debug> Error<E> {
+    /// Recover the I²C bus error, if any.
+    pub fn into_inner(self) -> Option<E> {
+        match self {
+            Error::I2C(e) | Error::ChannelSelect(e) => Some(e),
+            Error::CouldNotAcquireDevice(_)
+            | Error::InvalidAddress(_)
+            | Error::InvalidChannels(_)
+            | Error::MultipleChannels(_)
+            | Error::NotPowerOnDefault(_)
+            | Error::Pin
+            | Error::VerificationFailed { .. }
+            | Error::ExternalChange { .. }
+            | Error::InvalidPath(_)
+            | Error::StaleHandle(_)
+            | Error::AmbiguousAddress(_)
+            | Error::NoSuchDevice(_) => None,
+        }
+    }
+}
+
+impl<E: core::fmt::Debug> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::I2C(e)
+    }
+}
+
+impl<E: core::fmt::Debug> core::fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::I2C(e) => write!(f, "I²C bus error: {:?}", e),
+            Error::ChannelSelect(e) => write!(f, "I²C bus error while selecting channels: {:?}", e),
+            Error::InvalidAddress(e) => write!(f, "Invalid address: {}", e),
+            Error::InvalidChannels(mask) => {
+                write!(f, "Invalid channels 0b{:08b} for this device", mask)
+            }
+            Error::MultipleChannels(mask) => write!(
+                f,
+                "Channels 0b{:08b} can not be selected at the same time in single-channel mode",
+                mask
+            ),
+            Error::NotPowerOnDefault(mask) => write!(
+                f,
+                "Device not in power-on default state, channels 0b{:08b} are selected",
+                mask
+            ),
+            Error::Pin => write!(f, "Error setting the RESET pin"),
+            Error::VerificationFailed { expected, actual } => write!(
+                f,
+                "Control register verification failed, expected channels 0b{:08b} but read 0b{:08b}",
+                expected, actual
+            ),
+            Error::ExternalChange { cached, actual } => write!(
+                f,
+                "Channel selection changed externally, cached 0b{:08b} but read 0b{:08b}",
+                cached, actual
+            ),
+            Error::InvalidPath(path) => write!(
+                f,
+                "Channel path {} does not go through the upstream channel of this device",
+                path
+            ),
+            Error::CouldNotAcquireDevice(context) => {
+                write!(f, "Could not acquire device for {:?}", context.kind)?;
+                if let Some(channel) = context.channel {
+                    write!(f, " through the part of channel {}", channel.index())?;
+                }
+                write!(f, ", it may already be acquired")
+            }
+            Error::StaleHandle(channel) => write!(
+                f,
+                "Part of channel {} is stale, the device was split again",
+                channel.index()
+            ),
+            Error::AmbiguousAddress(address) => write!(
+                f,
+                "Devices at address 0x{:02X} are routed to more than one channel",
+                address
+            ),
+            Error::NoSuchDevice(index) => write!(f, "No device at index {} in the group", index),
+        }
+    }
+}
+
+impl<E> core::error::Error for Error<E>
+where
+    E: core::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::I2C(e) | Error::ChannelSelect(e) => Some(e),
+            Error::InvalidAddress(e) => Some(e),
+            Error::CouldNotAcquireDevice(_)
+            | Error::InvalidChannels(_)
+            | Error::MultipleChannels(_)
+            | Error::NotPowerOnDefault(_)
+            | Error::Pin
+            | Error::VerificationFailed { .. }
+            | Error::ExternalChange { .. }
+            | Error::InvalidPath(_)
+            | Error::StaleHandle(_)
+            | Error::AmbiguousAddress(_)
+            | Error::NoSuchDevice(_) => None,
+        }
+    }
 }
 
 /// Possible slave addresses
@@ -19,69 +231,514 @@ pub enum SlaveAddr {
     /// Alternative slave address providing bit values for A2, A1 and A0
     /// Note: Some devices does not have all Ax pins, these should be set to false.
     Alternative(bool, bool, bool),
+    /// Raw 7-bit slave address
+    ///
+    /// Useful for boards using non-standard strapping or clone chips.
+    /// It is validated when constructing the device with `try_new()`.
+    Raw(u8),
 }
 
 impl SlaveAddr {
-    pub(crate) fn addr(self, default: u8) -> u8 {
+    /// Create an alternative slave address from the levels of the A2, A1 and A0 pins.
+    pub fn from_pins(a2: bool, a1: bool, a0: bool) -> Self {
+        SlaveAddr::Alternative(a2, a1, a0)
+    }
+
+    /// Effective 7-bit slave address given the device base address.
+    pub fn address(self, base_address: u8) -> u8 {
         match self {
-            SlaveAddr::Default => default,
+            SlaveAddr::Default => base_address,
             SlaveAddr::Alternative(a2, a1, a0) => {
-                default | ((a2 as u8) << 2) | ((a1 as u8) << 1) | a0 as u8
+                base_address | ((a2 as u8) << 2) | ((a1 as u8) << 1) | a0 as u8
+            }
+            SlaveAddr::Raw(address) => address,
+        }
+    }
+
+    /// Value of the A2, A1 and A0 address bits.
+    pub fn bits(self) -> u8 {
+        self.address(0) & 0b111
+    }
+
+    pub(crate) fn validated_address(self, base_address: u8) -> Result<u8, AddressError> {
+        let address = self.address(base_address);
+        match address {
+            0x80..=0xFF => Err(AddressError::OutOfRange(address)),
+            0x00..=0x07 | 0x78..=0x7F => Err(AddressError::Reserved(address)),
+            _ => Ok(address),
+        }
+    }
+}
+
+impl core::fmt::Display for SlaveAddr {
+    /// Formats the effective address with the default base address.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "0x{:02X}", self.address(DEFAULT_ADDRESS))
+    }
+}
+
+/// Invalid slave address errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressError {
+    /// The address does not fit in 7 bits.
+    OutOfRange(u8),
+    /// The address is reserved by the I²C specification (0x00-0x07 and 0x78-0x7F),
+    /// including the general call address (0x00).
+    Reserved(u8),
+    /// The address is used by more than one device in a [`MuxGroup`](crate::MuxGroup).
+    Duplicate(u8),
+}
+
+impl core::fmt::Display for AddressError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            AddressError::OutOfRange(address) => {
+                write!(f, "Address 0x{:02X} is not a 7-bit address", address)
+            }
+            AddressError::Reserved(address) => {
+                write!(f, "Address 0x{:02X} is reserved", address)
+            }
+            AddressError::Duplicate(address) => {
+                write!(
+                    f,
+                    "Address 0x{:02X} is used by more than one device",
+                    address
+                )
             }
         }
     }
 }
 
+impl core::error::Error for AddressError {}
+
+/// I²C channel of the switch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Channel {
+    /// Channel 0 (SD0/SC0 pins)
+    Ch0,
+    /// Channel 1 (SD1/SC1 pins)
+    Ch1,
+    /// Channel 2 (SD2/SC2 pins)
+    Ch2,
+    /// Channel 3 (SD3/SC3 pins)
+    Ch3,
+    /// Channel 4 (SD4/SC4 pins)
+    Ch4,
+    /// Channel 5 (SD5/SC5 pins)
+    Ch5,
+    /// Channel 6 (SD6/SC6 pins)
+    Ch6,
+    /// Channel 7 (SD7/SC7 pins)
+    Ch7,
+}
+
+impl Channel {
+    const ALL: [Channel; 8] = [
+        Channel::Ch0,
+        Channel::Ch1,
+        Channel::Ch2,
+        Channel::Ch3,
+        Channel::Ch4,
+        Channel::Ch5,
+        Channel::Ch6,
+        Channel::Ch7,
+    ];
+
+    /// All channels in ascending order.
+    pub fn iter() -> impl Iterator<Item = Channel> {
+        Self::ALL.iter().copied()
+    }
+
+    /// Channels present on the device type `DEV` in ascending order.
+    ///
+    /// ```
+    /// use xca9548a::{Channel, Xca9545a};
+    ///
+    /// assert_eq!(4, Channel::all::<Xca9545a<()>>().count());
+    /// ```
+    pub fn all<DEV: DeviceType>() -> impl Iterator<Item = Channel> {
+        Self::in_mask(DEV::CHANNEL_MASK)
+    }
+
+    /// Create a channel from its index (0-7).
+    pub fn from_index(index: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(index)).copied()
+    }
+
+    /// Channels whose bits are set in `mask` in ascending order.
+    pub(crate) fn in_mask(mask: u8) -> impl Iterator<Item = Channel> {
+        mask.indices().map(Self::from_checked_index)
+    }
+
+    /// Create a channel from an index known to be valid at compile time.
+    pub(crate) const fn from_checked_index(index: u8) -> Self {
+        Self::ALL[index as usize]
+    }
+
+    /// Index of the channel (0-7).
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// Control register bit mask of the channel.
+    pub fn mask(self) -> u8 {
+        1 << self.index()
+    }
+
+    /// Format the channels whose bits are set in `mask` as a set of channel
+    /// indices, e.g. `{0,2,5}`.
+    ///
+    /// ```
+    /// use xca9548a::Channel;
+    ///
+    /// assert_eq!("{0,2,5}", Channel::display_mask(0b10_0101).to_string());
+    /// assert_eq!("{}", Channel::display_mask(0).to_string());
+    /// ```
+    pub fn display_mask(mask: u8) -> impl core::fmt::Display {
+        MaskDisplay(mask)
+    }
+}
+
+impl core::fmt::Display for Channel {
+    /// Formats the channel index, e.g. `ch3`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "ch{}", self.index())
+    }
+}
+
+/// Channels of a mask formatted as a set of channel indices
+struct MaskDisplay(u8);
+
+impl core::fmt::Display for MaskDisplay {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{{")?;
+        for (i, channel) in Channel::in_mask(self.0).enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", channel.index())?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Common channel masks of the device type `DEV`
+///
+/// The masks are plain `u8` values. Format them with [`Channel::display_mask()`].
+///
+/// ```
+/// use xca9548a::{Channel, ChannelMask, Xca9543a};
+///
+/// assert_eq!(0b11, ChannelMask::<Xca9543a<()>>::ALL);
+/// assert_eq!(0, ChannelMask::<Xca9543a<()>>::NONE);
+/// assert_eq!("{0,1}", Channel::display_mask(ChannelMask::<Xca9543a<()>>::ALL).to_string());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelMask<DEV>(PhantomData<DEV>);
+
+impl<DEV: DeviceType> ChannelMask<DEV> {
+    /// All the channels present on the device
+    pub const ALL: u8 = DEV::CHANNEL_MASK;
+    /// No channel
+    pub const NONE: u8 = 0;
+}
+
+/// Path to a channel of a multiplexer cascaded behind a channel of another multiplexer
+///
+/// See [`select_path()`](struct.Xca9548a.html#method.select_path).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChannelPath {
+    /// Channel of the upstream multiplexer where the downstream multiplexer is connected
+    pub upstream: Channel,
+    /// Channel of the downstream multiplexer
+    pub downstream: Channel,
+}
+
+impl ChannelPath {
+    /// Create a new channel path.
+    pub fn new(upstream: Channel, downstream: Channel) -> Self {
+        ChannelPath {
+            upstream,
+            downstream,
+        }
+    }
+}
+
+impl core::fmt::Display for ChannelPath {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}/{}", self.upstream.index(), self.downstream.index())
+    }
+}
+
+/// Hook invoked periodically during long multi-transaction operations
+/// like [`scan_all_with_watchdog()`](struct.Xca9548a.html#method.scan_all_with_watchdog)
+/// so that the application can feed its hardware watchdog.
+///
+/// It is implemented for closures so that `&mut || wdt.feed()` can be passed directly.
+pub trait Watchdog {
+    /// Feed the watchdog.
+    fn feed(&mut self);
+}
+
+impl<F: FnMut()> Watchdog for F {
+    fn feed(&mut self) {
+        self()
+    }
+}
+
+/// Driver state retained when temporarily releasing the I²C bus
+///
+/// Returned by `destroy_with_state()` and `state()` and used to rebuild the driver
+/// with `from_parts()` without losing the channel selection cache.
+/// It can also be persisted across reboots with a [`StateStorage`](crate::StateStorage).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceState {
+    pub(crate) address: u8,
+    pub(crate) selected_channel_mask: u8,
+    /// Whether the channel selection cache was already known not to match the device.
+    pub(crate) cache_dirty: bool,
+    pub(crate) config: Config,
+}
+
+impl DeviceState {
+    /// 7-bit slave address of the device.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Channels selected at the time the state was taken.
+    pub fn selected_channels(&self) -> u8 {
+        self.selected_channel_mask
+    }
+}
+
+/// Channel selection saved while the device is parked in a low-power state
+///
+/// Returned by `park_low_power()` and consumed by `unpark()` on the device driver types.
+#[must_use]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parked {
+    pub(crate) channels: u8,
+}
+
+impl Parked {
+    /// Channels selected before parking.
+    pub fn channels(&self) -> u8 {
+        self.channels
+    }
+}
+
+/// Last error seen on a channel
+///
+/// See `last_error()` on the device driver types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LastError {
+    pub(crate) kind: ErrorKind,
+    pub(crate) sequence: u32,
+}
+
+impl LastError {
+    /// Kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Sequence number of the error.
+    ///
+    /// The errors of a device are numbered in the order they happened, so the
+    /// channel whose last error has the highest number failed most recently.
+    /// The number wraps around after `u32::MAX` errors.
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+}
+
+/// Properties of each device type
+///
+/// This allows generic code to reason about the device types.
+pub trait DeviceType: private::Sealed {
+    /// Number of channels
+    const CHANNELS: u8;
+    /// Control register mask of the channels present on the device
+    const CHANNEL_MASK: u8;
+    /// Part name, e.g. `TCA9548A/PCA9548A`
+    const NAME: &'static str;
+    /// Whether the device reports the state of the interrupt lines of the channels
+    const HAS_INTERRUPTS: bool;
+}
+
+/// Address and properties of a device
+///
+/// See [`device_info()`](struct.Xca9548a.html#method.device_info).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// 7-bit slave address
+    pub address: u8,
+    /// Part name, e.g. `TCA9548A/PCA9548A`
+    pub name: &'static str,
+    /// Number of channels
+    pub channels: u8,
+    /// Whether the device reports the state of the interrupt lines of the channels
+    pub has_interrupts: bool,
+}
+
+impl core::fmt::Display for DeviceInfo {
+    /// Formats the part name and address, e.g. `TCA9548A/PCA9548A at 0x70`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} at 0x{:02X}", self.name, self.address)
+    }
+}
+
+/// No settle delay after channel changes (default)
+///
+/// See `with_settle_delay()` on the device driver types.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// Control register read done once the device is no longer in use
+pub(crate) type DeferredRead<I2C, D, O> = fn(&mut Xca954xaData<I2C, D, O>) -> Result<u8, ErrorKind>;
+
 /// Device driver for T/PCA9548A
 #[derive(Debug)]
-pub struct Xca9548a<I2C> {
-    pub(crate) data: cell::RefCell<Xca954xaData<I2C>>,
+pub struct Xca9548a<I2C, D = NoDelay, O = NoObserver> {
+    pub(crate) data: cell::RefCell<Xca954xaData<I2C, D, O>>,
+    /// Copy of the device address, available while the data is borrowed.
+    pub(crate) address: u8,
+    /// Control register read queued while the device was in use.
+    pub(crate) deferred_read: cell::Cell<Option<DeferredRead<I2C, D, O>>>,
+    /// Result of the last deferred control register read.
+    pub(crate) deferred_status: cell::Cell<Option<Result<u8, ErrorKind>>>,
+    /// Generation of the split parts. Parts of older generations are stale.
+    pub(crate) generation: cell::Cell<u32>,
+    /// Accesses rejected because the device was in use.
+    #[cfg(feature = "stats")]
+    pub(crate) borrow_failures: cell::Cell<u32>,
 }
 
 /// Device driver for T/PCA9543A
 #[derive(Debug)]
-pub struct Xca9543a<I2C> {
-    pub(crate) data: cell::RefCell<Xca954xaData<I2C>>,
+pub struct Xca9543a<I2C, D = NoDelay, O = NoObserver> {
+    pub(crate) data: cell::RefCell<Xca954xaData<I2C, D, O>>,
+    /// Copy of the device address, available while the data is borrowed.
+    pub(crate) address: u8,
+    /// Control register read queued while the device was in use.
+    pub(crate) deferred_read: cell::Cell<Option<DeferredRead<I2C, D, O>>>,
+    /// Result of the last deferred control register read.
+    pub(crate) deferred_status: cell::Cell<Option<Result<u8, ErrorKind>>>,
+    /// Generation of the split parts. Parts of older generations are stale.
+    pub(crate) generation: cell::Cell<u32>,
+    /// Accesses rejected because the device was in use.
+    #[cfg(feature = "stats")]
+    pub(crate) borrow_failures: cell::Cell<u32>,
 }
 
 /// Device driver for T/PCA9545A
 #[derive(Debug)]
-pub struct Xca9545a<I2C> {
-    pub(crate) data: cell::RefCell<Xca954xaData<I2C>>,
+pub struct Xca9545a<I2C, D = NoDelay, O = NoObserver> {
+    pub(crate) data: cell::RefCell<Xca954xaData<I2C, D, O>>,
+    /// Copy of the device address, available while the data is borrowed.
+    pub(crate) address: u8,
+    /// Control register read queued while the device was in use.
+    pub(crate) deferred_read: cell::Cell<Option<DeferredRead<I2C, D, O>>>,
+    /// Result of the last deferred control register read.
+    pub(crate) deferred_status: cell::Cell<Option<Result<u8, ErrorKind>>>,
+    /// Generation of the split parts. Parts of older generations are stale.
+    pub(crate) generation: cell::Cell<u32>,
+    /// Accesses rejected because the device was in use.
+    #[cfg(feature = "stats")]
+    pub(crate) borrow_failures: cell::Cell<u32>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::DEVICE_BASE_ADDRESS as BASE_ADDR;
+    use crate::DEFAULT_ADDRESS as BASE_ADDR;
+
+    #[test]
+    fn can_convert_channel_index() {
+        assert_eq!(Some(Channel::Ch0), Channel::from_index(0));
+        assert_eq!(Some(Channel::Ch7), Channel::from_index(7));
+        assert_eq!(None, Channel::from_index(8));
+        assert_eq!(5, Channel::Ch5.index());
+        assert_eq!(0b0010_0000, Channel::Ch5.mask());
+    }
 
     #[test]
     fn can_get_default_address() {
         let addr = SlaveAddr::default();
-        assert_eq!(BASE_ADDR, addr.addr(BASE_ADDR));
+        assert_eq!(BASE_ADDR, addr.address(BASE_ADDR));
     }
 
     #[test]
     fn can_generate_alternative_addresses() {
         assert_eq!(
             0b111_0000,
-            SlaveAddr::Alternative(false, false, false).addr(BASE_ADDR)
+            SlaveAddr::Alternative(false, false, false).address(BASE_ADDR)
         );
         assert_eq!(
             0b111_0001,
-            SlaveAddr::Alternative(false, false, true).addr(BASE_ADDR)
+            SlaveAddr::Alternative(false, false, true).address(BASE_ADDR)
         );
         assert_eq!(
             0b111_0010,
-            SlaveAddr::Alternative(false, true, false).addr(BASE_ADDR)
+            SlaveAddr::Alternative(false, true, false).address(BASE_ADDR)
         );
         assert_eq!(
             0b111_0100,
-            SlaveAddr::Alternative(true, false, false).addr(BASE_ADDR)
+            SlaveAddr::Alternative(true, false, false).address(BASE_ADDR)
         );
         assert_eq!(
             0b111_0111,
-            SlaveAddr::Alternative(true, true, true).addr(BASE_ADDR)
+            SlaveAddr::Alternative(true, true, true).address(BASE_ADDR)
+        );
+    }
+
+    #[test]
+    fn can_create_address_from_pins() {
+        assert_eq!(
+            SlaveAddr::Alternative(true, false, true),
+            SlaveAddr::from_pins(true, false, true)
+        );
+    }
+
+    #[test]
+    fn can_get_address_bits() {
+        assert_eq!(0, SlaveAddr::default().bits());
+        assert_eq!(0b101, SlaveAddr::from_pins(true, false, true).bits());
+        assert_eq!(0b011, SlaveAddr::Raw(0x73).bits());
+    }
+
+    #[test]
+    fn can_use_raw_address() {
+        assert_eq!(0x74, SlaveAddr::Raw(0x74).address(BASE_ADDR));
+        assert_eq!(Ok(0x74), SlaveAddr::Raw(0x74).validated_address(BASE_ADDR));
+    }
+
+    #[test]
+    fn rejects_raw_address_out_of_range() {
+        assert_eq!(
+            Err(AddressError::OutOfRange(0xE0)),
+            SlaveAddr::Raw(0xE0).validated_address(BASE_ADDR)
+        );
+    }
+
+    #[test]
+    fn rejects_reserved_addresses() {
+        for address in [0x00, 0x07, 0x78, 0x7F] {
+            assert_eq!(
+                Err(AddressError::Reserved(address)),
+                SlaveAddr::Raw(address).validated_address(BASE_ADDR)
+            );
+        }
+        assert_eq!(
+            Err(AddressError::Reserved(0x7C)),
+            SlaveAddr::from_pins(true, false, false).validated_address(0x78)
         );
+        assert_eq!(Ok(0x08), SlaveAddr::Raw(0x08).validated_address(BASE_ADDR));
+        assert_eq!(Ok(0x77), SlaveAddr::Raw(0x77).validated_address(BASE_ADDR));
     }
 }
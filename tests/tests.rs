@@ -71,6 +71,36 @@ macro_rules! test_device {
             switch.destroy().done();
         }
 
+        #[test]
+        fn skips_selecting_already_selected_channels() {
+            let transactions = [I2cTrans::write(DEV_ADDR, vec![0x01])];
+            let mut switch = new(&transactions);
+            switch.select_channels(0x01).unwrap();
+            switch.select_channels(0x01).unwrap();
+            switch.destroy().done();
+        }
+
+        #[test]
+        fn writes_first_selection_of_no_channels() {
+            let transactions = [I2cTrans::write(DEV_ADDR, vec![0])];
+            let mut switch = new(&transactions);
+            switch.select_channels(0).unwrap();
+            switch.select_channels(0).unwrap();
+            switch.destroy().done();
+        }
+
+        #[test]
+        fn can_force_select_channels() {
+            let transactions = [
+                I2cTrans::write(DEV_ADDR, vec![0x01]),
+                I2cTrans::write(DEV_ADDR, vec![0x01]),
+            ];
+            let mut switch = new(&transactions);
+            switch.select_channels(0x01).unwrap();
+            switch.force_select(0x01).unwrap();
+            switch.destroy().done();
+        }
+
         #[test]
         fn can_get_channel_status() {
             let transactions = [I2cTrans::read(DEV_ADDR, vec![0b0101_0101 & $channels])];
@@ -240,3 +270,4496 @@ mod test_xca9543a {
     test_interrupt!(Xca9543a, 0x03);
     test_ch_out_of_range!(Xca9543a, 0x03);
 }
+
+mod errors {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c, NoAcknowledgeSource};
+    use xca9548a::{AccessContext, AccessKind, Channel};
+
+    fn select_with_error(kind: ErrorKind) -> xca9548a::Error<ErrorKind> {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x01]).with_error(kind)];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let error = switch.select_channels(0x01).unwrap_err();
+        switch.destroy().done();
+        error
+    }
+
+    #[test]
+    fn can_classify_nack() {
+        let error = select_with_error(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        assert!(error.is_nack());
+        assert!(!error.is_arbitration_loss());
+        assert!(!error.is_bus_error());
+    }
+
+    #[test]
+    fn can_classify_arbitration_loss() {
+        let error = select_with_error(ErrorKind::ArbitrationLoss);
+        assert!(!error.is_nack());
+        assert!(error.is_arbitration_loss());
+        assert!(!error.is_bus_error());
+    }
+
+    #[test]
+    fn can_display_errors() {
+        let error = select_with_error(ErrorKind::Bus);
+        assert_eq!(
+            "I²C bus error while selecting channels: Bus",
+            format!("{}", error)
+        );
+        let error: xca9548a::Error<ErrorKind> = xca9548a::Error::I2C(ErrorKind::Overrun);
+        assert_eq!("I²C bus error: Overrun", format!("{}", error));
+        let error: xca9548a::Error<ErrorKind> =
+            xca9548a::Error::CouldNotAcquireDevice(AccessContext {
+                kind: AccessKind::ReadStatus,
+                channel: None,
+            });
+        assert_eq!(
+            "Could not acquire device for ReadStatus, it may already be acquired",
+            format!("{}", error)
+        );
+        let error: xca9548a::Error<ErrorKind> =
+            xca9548a::Error::CouldNotAcquireDevice(AccessContext {
+                kind: AccessKind::Write,
+                channel: Some(Channel::Ch3),
+            });
+        assert_eq!(
+            "Could not acquire device for Write through the part of channel 3, it may already be acquired",
+            format!("{}", error)
+        );
+    }
+
+    #[test]
+    fn can_compare_and_recover_inner_error() {
+        let error = select_with_error(ErrorKind::Bus);
+        assert_eq!(xca9548a::Error::ChannelSelect(ErrorKind::Bus), error);
+        assert_eq!(Some(ErrorKind::Bus), error.into_inner());
+        let error: xca9548a::Error<ErrorKind> =
+            xca9548a::Error::CouldNotAcquireDevice(AccessContext {
+                kind: AccessKind::Scan,
+                channel: None,
+            });
+        assert_eq!(None, error.into_inner());
+    }
+
+    #[test]
+    fn can_convert_bus_error() {
+        fn write(i2c: &mut I2cMock) -> Result<(), xca9548a::Error<ErrorKind>> {
+            i2c.write(SLAVE_ADDR, &SLAVE_WRITE_DATA)?;
+            Ok(())
+        }
+        let transactions =
+            [I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(ErrorKind::Bus)];
+        let mut i2c = I2cMock::new(&transactions);
+        assert_eq!(Err(xca9548a::Error::I2C(ErrorKind::Bus)), write(&mut i2c));
+        i2c.done();
+    }
+
+    #[test]
+    fn can_classify_bus_error() {
+        let error = select_with_error(ErrorKind::Bus);
+        assert!(!error.is_nack());
+        assert!(!error.is_arbitration_loss());
+        assert!(error.is_bus_error());
+    }
+}
+
+mod scan {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+    use xca9548a::{Channel, ScanConfig};
+
+    const NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+
+    pub(super) fn scan_transactions(channel_count: u8, found: &[(u8, u8)]) -> Vec<I2cTrans> {
+        let mut transactions = Vec::new();
+        for channel in 0..channel_count {
+            transactions.push(I2cTrans::write(DEV_ADDR, vec![1 << channel]));
+            for address in (0x08..=0x77).filter(|a| *a != DEV_ADDR) {
+                let trans = I2cTrans::read(address, vec![0]);
+                if found.contains(&(channel, address)) {
+                    transactions.push(trans);
+                } else {
+                    transactions.push(trans.with_error(NACK));
+                }
+            }
+        }
+        transactions.push(I2cTrans::write(DEV_ADDR, vec![0]));
+        transactions
+    }
+
+    #[test]
+    fn can_diff_reports() {
+        let mut transactions = vec![I2cTrans::read(DEV_ADDR, vec![0])];
+        transactions.extend(scan_transactions(2, &[(0, 0x20), (1, 0x48)]));
+        transactions.extend(scan_transactions(2, &[(0, 0x20), (0, 0x48), (1, 0x50)]));
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let before = switch.scan_all().unwrap();
+        let after = switch.scan_all().unwrap();
+        assert!(before.diff(&before).is_empty());
+        let diff = before.diff(&after);
+        assert!(!diff.is_empty());
+        assert_eq!(0b11, diff.changed_channels());
+        assert_eq!(vec![0x48], diff.added(Channel::Ch0).collect::<Vec<_>>());
+        assert_eq!(0, diff.removed(Channel::Ch0).count());
+        assert_eq!(vec![0x50], diff.added(Channel::Ch1).collect::<Vec<_>>());
+        assert_eq!(vec![0x48], diff.removed(Channel::Ch1).collect::<Vec<_>>());
+        assert_eq!(0, diff.added(Channel::Ch2).count());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn can_scan_all_channels() {
+        let mut transactions = vec![I2cTrans::read(DEV_ADDR, vec![0])];
+        transactions.extend(scan_transactions(2, &[(0, 0x20), (1, 0x20), (1, 0x48)]));
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let report = switch.scan_all().unwrap();
+        assert_eq!(3, report.device_count());
+        assert!(report.contains(Channel::Ch0, 0x20));
+        assert!(!report.contains(Channel::Ch0, 0x48));
+        assert_eq!(
+            vec![0x20, 0x48],
+            report.devices(Channel::Ch1).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![Channel::Ch0, Channel::Ch1],
+            report.where_is(0x20).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![Channel::Ch1],
+            report.where_is(0x48).collect::<Vec<_>>()
+        );
+        assert_eq!(0, report.where_is(0x50).count());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn part_can_scan_its_channel() {
+        let mut transactions = vec![I2cTrans::read(DEV_ADDR, vec![0])];
+        transactions.extend(scan_transactions(1, &[(0, 0x20), (0, 0x48)]));
+        let switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            let report = parts.i2c0.scan().unwrap();
+            assert_eq!(
+                vec![0x20, 0x48],
+                report.devices(Channel::Ch0).collect::<Vec<_>>()
+            );
+            assert_eq!(2, report.device_count());
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn part_can_probe_address() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::read(0x20, vec![0]),
+            I2cTrans::read(0x21, vec![0]).with_error(NACK),
+        ];
+        let switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            assert!(parts.i2c1.probe(0x20).unwrap());
+            assert!(!parts.i2c1.probe(0x21).unwrap());
+        }
+        assert_eq!(None, switch.last_error(Channel::Ch1).unwrap());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn feeds_watchdog_while_scanning() {
+        let mut transactions = vec![I2cTrans::read(DEV_ADDR, vec![0])];
+        transactions.extend(scan_transactions(4, &[]));
+        let mut switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut feed_count = 0;
+        let report = switch
+            .scan_all_with_watchdog(&mut || feed_count += 1)
+            .unwrap();
+        assert_eq!(0, report.device_count());
+        assert_eq!(4 * 111, feed_count);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn can_scan_configured_addresses() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0]),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::read(0x48, vec![0]).with_error(NACK),
+            I2cTrans::read(0x4A, vec![0]),
+            I2cTrans::write(DEV_ADDR, vec![0]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let config = ScanConfig::new()
+            .channels(0x02)
+            .addresses(0x48..=0x4A)
+            .skip(0x49);
+        let report = switch.scan(&config).unwrap();
+        assert_eq!(
+            vec![Channel::Ch1],
+            report.where_is(0x4A).collect::<Vec<_>>()
+        );
+        assert_eq!(1, report.device_count());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn skips_switch_address_range() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::read(0x6F, vec![0]).with_error(NACK),
+            I2cTrans::read(0x78, vec![0]).with_error(NACK),
+            I2cTrans::write(DEV_ADDR, vec![0]),
+        ];
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let config = ScanConfig::new()
+            .channels(0x01)
+            .addresses(0x6F..=0x78)
+            .skip_range(0x70..=0x77);
+        assert_eq!(0, switch.scan(&config).unwrap().device_count());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn ignores_skipping_out_of_range_addresses() {
+        assert_eq!(ScanConfig::new(), ScanConfig::new().skip(0xC8));
+    }
+
+    #[test]
+    fn can_stop_after_devices_found() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::read(0x20, vec![0]).with_error(NACK),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::read(0x20, vec![0]),
+            I2cTrans::write(DEV_ADDR, vec![0]),
+        ];
+        let mut switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let config = ScanConfig::new()
+            .addresses(0x20..=0x21)
+            .skip(0x21)
+            .stop_after(1);
+        let report = switch.scan(&config).unwrap();
+        assert!(report.contains(Channel::Ch1, 0x20));
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn restores_previous_selection_after_scan() {
+        let mut transactions = vec![I2cTrans::write(DEV_ADDR, vec![0x03])];
+        transactions.extend(scan_transactions(2, &[]));
+        *transactions.last_mut().unwrap() = I2cTrans::write(DEV_ADDR, vec![0x03]);
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x03).unwrap();
+        switch.scan_all().unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn restores_selection_read_back_after_scan() {
+        // the selection is unknown, e.g. after construction
+        let mut transactions = vec![I2cTrans::read(DEV_ADDR, vec![0x02])];
+        transactions.extend(scan_transactions(2, &[]));
+        *transactions.last_mut().unwrap() = I2cTrans::write(DEV_ADDR, vec![0x02]);
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.scan_all().unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn never_probes_reserved_addresses() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::read(0x08, vec![0]).with_error(NACK),
+            I2cTrans::write(DEV_ADDR, vec![0]),
+        ];
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let config = ScanConfig::new().channels(0x01).addresses(0x00..=0x08);
+        assert_eq!(0, switch.scan(&config).unwrap().device_count());
+        switch.destroy().done();
+    }
+}
+
+mod error_source {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c, NoAcknowledgeSource};
+    use xca9548a::Error;
+
+    const NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+
+    #[test]
+    fn reports_channel_select_error_from_part() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x01]).with_error(NACK)];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            let error = parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap_err();
+            assert!(matches!(error, Error::ChannelSelect(NACK)));
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn reports_transaction_error_from_part() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(NACK),
+        ];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            let error = parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap_err();
+            assert!(matches!(error, Error::I2C(NACK)));
+        }
+        switch.destroy().done();
+    }
+}
+
+mod error_trait {
+    use xca9548a::{AccessContext, AccessKind, Error};
+
+    #[derive(Debug)]
+    struct BusError;
+
+    impl core::fmt::Display for BusError {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            write!(f, "bus error")
+        }
+    }
+
+    impl core::error::Error for BusError {}
+
+    #[test]
+    fn source_is_bus_error() {
+        let error: Box<dyn core::error::Error> = Box::new(Error::I2C(BusError));
+        assert_eq!("bus error", error.source().unwrap().to_string());
+    }
+
+    #[test]
+    fn acquisition_error_has_no_source() {
+        let error: Box<dyn core::error::Error> =
+            Box::new(Error::<BusError>::CouldNotAcquireDevice(AccessContext {
+                kind: AccessKind::Read,
+                channel: None,
+            }));
+        assert!(error.source().is_none());
+    }
+}
+
+mod error_adapter {
+    use super::*;
+    use embedded_hal::i2c::{self, ErrorKind, I2c};
+    use xca9548a::{Error, ErrorAdapter};
+
+    #[derive(Debug, PartialEq)]
+    enum AppError {
+        Bus(ErrorKind),
+        Other,
+    }
+
+    impl i2c::Error for AppError {
+        fn kind(&self) -> ErrorKind {
+            match self {
+                AppError::Bus(kind) => *kind,
+                AppError::Other => ErrorKind::Other,
+            }
+        }
+    }
+
+    impl From<Error<ErrorKind>> for AppError {
+        fn from(e: Error<ErrorKind>) -> Self {
+            match e.into_inner() {
+                Some(kind) => AppError::Bus(kind),
+                None => AppError::Other,
+            }
+        }
+    }
+
+    #[test]
+    fn converts_part_errors() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_READ_DATA.to_vec()).with_error(ErrorKind::Bus),
+        ];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let parts = switch.split();
+            let mut i2c: ErrorAdapter<_, AppError> = ErrorAdapter::new(parts.i2c1);
+            i2c.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            let mut data = [0; 2];
+            assert_eq!(
+                Err(AppError::Bus(ErrorKind::Bus)),
+                i2c.read(SLAVE_ADDR, &mut data)
+            );
+        }
+        switch.destroy().done();
+    }
+}
+
+mod raw_address {
+    use super::*;
+    use xca9548a::AddressError;
+
+    #[test]
+    fn can_use_raw_address() {
+        let transactions = [I2cTrans::write(0x74, vec![0x01])];
+        let mut switch =
+            Xca9548a::try_new(I2cMock::new(&transactions), SlaveAddr::Raw(0x74)).unwrap();
+        switch.select_channels(0x01).unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn rejects_invalid_raw_address() {
+        let result = Xca9548a::try_new(I2cMock::new(&[]), SlaveAddr::Raw(0x80));
+        let (mut i2c, error) = result.unwrap_err();
+        assert_eq!(AddressError::OutOfRange(0x80), error);
+        i2c.done();
+    }
+
+    #[test]
+    fn rejects_reserved_address() {
+        let result = Xca9548a::try_new(I2cMock::new(&[]), SlaveAddr::Raw(0x00));
+        let (mut i2c, error) = result.unwrap_err();
+        assert_eq!(AddressError::Reserved(0x00), error);
+        i2c.done();
+    }
+
+    #[test]
+    fn rejects_reserved_address_with_base_address() {
+        let result = Xca9548a::try_new_with_base_address(
+            I2cMock::new(&[]),
+            0b111_1000,
+            SlaveAddr::default(),
+        );
+        let (mut i2c, error) = result.unwrap_err();
+        assert_eq!(AddressError::Reserved(0b111_1000), error);
+        i2c.done();
+    }
+}
+
+mod base_address {
+    use super::*;
+
+    #[test]
+    fn can_use_custom_base_address() {
+        let transactions = [I2cTrans::write(0b101_0010, vec![0x01])];
+        let mut switch = Xca9545a::new_with_base_address(
+            I2cMock::new(&transactions),
+            0b101_0000,
+            SlaveAddr::Alternative(false, true, false),
+        );
+        switch.select_channels(0x01).unwrap();
+        switch.destroy().done();
+    }
+}
+
+mod slave_addr {
+    use super::*;
+
+    #[test]
+    fn can_display_address() {
+        assert_eq!("0x70", format!("{}", SlaveAddr::default()));
+        assert_eq!(
+            "0x75",
+            format!("{}", SlaveAddr::from_pins(true, false, true))
+        );
+        assert_eq!("0x2A", format!("{}", SlaveAddr::Raw(0x2A)));
+    }
+
+    #[test]
+    fn can_get_effective_address() {
+        assert_eq!(0x72, SlaveAddr::from_pins(false, true, false).address(0x70));
+        assert_eq!(0x52, SlaveAddr::from_pins(false, true, false).address(0x50));
+    }
+}
+
+mod builder {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use embedded_hal_mock::eh1::digital::{Mock as PinMock, State, Transaction as PinTrans};
+    use xca9548a::{AddressError, CachePolicy, Error};
+
+    #[test]
+    fn can_build_with_address_and_initial_channels() {
+        let transactions = [I2cTrans::write(0x71, vec![0x05])];
+        let switch = Xca9548a::builder()
+            .address(SlaveAddr::Alternative(false, false, true))
+            .initial_channels(0x05)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn can_build_with_reset() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x02])];
+        let mut reset = PinMock::new(&[PinTrans::set(State::Low), PinTrans::set(State::High)]);
+        let switch = Xca9543a::builder()
+            .initial_channels(0x02)
+            .build_with_reset(I2cMock::new(&transactions), &mut reset)
+            .unwrap();
+        switch.destroy().done();
+        reset.done();
+    }
+
+    #[test]
+    fn cache_is_valid_after_building_with_reset() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x01])];
+        let mut reset = PinMock::new(&[PinTrans::set(State::Low), PinTrans::set(State::High)]);
+        let mut switch = Xca9543a::builder()
+            .build_with_reset(I2cMock::new(&transactions), &mut reset)
+            .unwrap();
+        switch.select_channels(0x00).unwrap();
+        switch.select_channels(0x01).unwrap();
+        switch.destroy().done();
+        reset.done();
+    }
+
+    #[test]
+    fn build_with_reset_rejects_invalid_address_before_using_pin() {
+        let mut reset = PinMock::new(&[]);
+        let result = Xca9543a::builder()
+            .address(SlaveAddr::Raw(0x7A))
+            .build_with_reset(I2cMock::new(&[]), &mut reset);
+        let (mut i2c, error) = result.unwrap_err();
+        assert!(matches!(error, Error::InvalidAddress(_)));
+        i2c.done();
+        reset.done();
+    }
+
+    #[test]
+    fn build_does_not_write_without_initial_channels() {
+        let switch = Xca9548a::builder().build(I2cMock::new(&[])).unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn build_rejects_invalid_address() {
+        let result = Xca9548a::builder()
+            .address(SlaveAddr::Raw(0x7A))
+            .build(I2cMock::new(&[]));
+        let (mut i2c, error) = result.unwrap_err();
+        assert_eq!(Error::InvalidAddress(AddressError::Reserved(0x7A)), error);
+        i2c.done();
+    }
+
+    #[test]
+    fn strict_mode_rejects_invalid_channels() {
+        let mut switch = Xca9543a::builder()
+            .strict(true)
+            .build(I2cMock::new(&[]))
+            .unwrap();
+        assert_eq!(
+            Err(Error::InvalidChannels(0x81)),
+            switch.select_channels(0x81)
+        );
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn strict_mode_rejects_invalid_initial_channels() {
+        let result = Xca9545a::builder()
+            .strict(true)
+            .initial_channels(0x10)
+            .build(I2cMock::new(&[]));
+        let (mut i2c, error) = result.unwrap_err();
+        assert_eq!(Error::InvalidChannels(0x10), error);
+        i2c.done();
+    }
+
+    #[test]
+    fn always_select_policy_writes_control_register_for_each_transfer() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = Xca9548a::builder()
+            .cache_policy(CachePolicy::AlwaysSelect)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        {
+            let mut parts = switch.split();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn auto_deselect_deselects_after_each_transfer() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_READ_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+        ];
+        let switch = Xca9548a::builder()
+            .auto_deselect(true)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        {
+            let mut parts = switch.split();
+            let mut data = [0; 2];
+            parts.i2c1.read(SLAVE_ADDR, &mut data).unwrap();
+            assert_eq!(SLAVE_READ_DATA, data);
+        }
+        switch.destroy().done();
+    }
+}
+
+mod initial_channels {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c};
+    use xca9548a::Error;
+
+    #[test]
+    fn can_create_with_channels_selected() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch =
+            Xca9548a::new_with_channels(I2cMock::new(&transactions), SlaveAddr::default(), 0x02)
+                .unwrap();
+        {
+            // channel 1 is already selected
+            let mut parts = switch.split();
+            parts.i2c1.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn writes_initial_selection_of_no_channels() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch =
+            Xca9548a::new_with_channels(I2cMock::new(&transactions), SlaveAddr::default(), 0)
+                .unwrap();
+        assert!(switch.is_cache_clean().unwrap());
+        // no channel is known to be selected
+        switch
+            .upstream()
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn builder_writes_initial_selection_of_no_channels() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0])];
+        let switch = Xca9543a::builder()
+            .initial_channels(0)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn returns_bus_if_selection_fails() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x02]).with_error(ErrorKind::Bus)];
+        let result =
+            Xca9548a::new_with_channels(I2cMock::new(&transactions), SlaveAddr::default(), 0x02);
+        let (mut i2c, error) = result.unwrap_err();
+        assert_eq!(Error::ChannelSelect(ErrorKind::Bus), error);
+        i2c.done();
+    }
+}
+
+mod init {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+    use xca9548a::Error;
+
+    #[test]
+    fn accepts_power_on_default() {
+        let transactions = [I2cTrans::read(DEV_ADDR, vec![0x00])];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.init().unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn ignores_interrupt_bits() {
+        let transactions = [I2cTrans::read(DEV_ADDR, vec![0xA0])];
+        let mut switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.init().unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn rejects_selected_channels() {
+        let transactions = [I2cTrans::read(DEV_ADDR, vec![0x24])];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        assert_eq!(Err(Error::NotPowerOnDefault(0x24)), switch.init());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn reports_missing_device() {
+        let nack = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+        let transactions = [I2cTrans::read(DEV_ADDR, vec![0x00]).with_error(nack)];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        assert!(switch.init().unwrap_err().is_nack());
+        switch.destroy().done();
+    }
+}
+
+mod shutdown {
+    use super::*;
+    use embedded_hal::i2c::ErrorKind;
+    use embedded_hal_mock::eh1::digital::{Mock as PinMock, State, Transaction as PinTrans};
+    use xca9548a::Error;
+
+    #[test]
+    fn deselects_all_channels() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x03]),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x03).unwrap();
+        switch.shutdown().unwrap().done();
+    }
+
+    #[test]
+    fn returns_bus_on_error() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x00]).with_error(ErrorKind::Bus)];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let (mut i2c, error) = switch.shutdown().unwrap_err();
+        assert_eq!(Error::ChannelSelect(ErrorKind::Bus), error);
+        i2c.done();
+    }
+
+    #[test]
+    fn asserts_reset() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x00])];
+        let mut reset = PinMock::new(&[PinTrans::set(State::Low)]);
+        let switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.shutdown_with_reset(&mut reset).unwrap().done();
+        reset.done();
+    }
+
+    #[test]
+    fn asserts_reset_even_if_deselect_fails() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x00]).with_error(ErrorKind::Bus)];
+        let mut reset = PinMock::new(&[PinTrans::set(State::Low)]);
+        let switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let (mut i2c, error) = switch.shutdown_with_reset(&mut reset).unwrap_err();
+        assert_eq!(Error::ChannelSelect(ErrorKind::Bus), error);
+        i2c.done();
+        reset.done();
+    }
+}
+
+mod park {
+    use super::*;
+    use embedded_hal_mock::eh1::digital::{Mock as PinMock, State, Transaction as PinTrans};
+
+    #[test]
+    fn can_park_and_unpark() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x05]),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x05]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x05).unwrap();
+        let parked = switch.park_low_power().unwrap();
+        assert_eq!(0x05, parked.channels());
+        switch.unpark(parked).unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn can_park_and_unpark_with_reset() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+        ];
+        let mut reset = PinMock::new(&[PinTrans::set(State::Low), PinTrans::set(State::High)]);
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x02).unwrap();
+        let parked = switch.park_low_power_with_reset(&mut reset).unwrap();
+        switch.unpark_with_reset(parked, &mut reset).unwrap();
+        switch.destroy().done();
+        reset.done();
+    }
+
+    #[test]
+    fn reads_selection_if_cache_is_dirty() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0x03]),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+        ];
+        let mut switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.invalidate_cache();
+        assert_eq!(0x03, switch.park_low_power().unwrap().channels());
+        switch.destroy().done();
+    }
+}
+
+mod constants {
+    use super::*;
+    use xca9548a::{DeviceType, DEFAULT_ADDRESS};
+
+    #[test]
+    fn default_address_is_correct() {
+        assert_eq!(DEV_ADDR, DEFAULT_ADDRESS);
+    }
+
+    #[test]
+    fn channel_constants_are_correct() {
+        assert_eq!(8, <Xca9548a<I2cMock> as DeviceType>::CHANNELS);
+        assert_eq!(0xff, <Xca9548a<I2cMock> as DeviceType>::CHANNEL_MASK);
+        assert_eq!(4, <Xca9545a<I2cMock> as DeviceType>::CHANNELS);
+        assert_eq!(0x0f, <Xca9545a<I2cMock> as DeviceType>::CHANNEL_MASK);
+        assert_eq!(2, <Xca9543a<I2cMock> as DeviceType>::CHANNELS);
+        assert_eq!(0x03, <Xca9543a<I2cMock> as DeviceType>::CHANNEL_MASK);
+    }
+
+    #[test]
+    fn channel_helpers_match_device_types() {
+        use xca9548a::{Channel, ChannelMask};
+        assert_eq!(8, Channel::iter().count());
+        assert_eq!(
+            vec![Channel::Ch0, Channel::Ch1],
+            Channel::all::<Xca9543a<I2cMock>>().collect::<Vec<_>>()
+        );
+        assert_eq!(8, Channel::all::<Xca9548a<I2cMock>>().count());
+        assert_eq!(0xff, ChannelMask::<Xca9548a<I2cMock>>::ALL);
+        assert_eq!(0x0f, ChannelMask::<Xca9545a<I2cMock>>::ALL);
+        assert_eq!(0, ChannelMask::<Xca9545a<I2cMock>>::NONE);
+        assert_eq!(
+            "{0,1}",
+            Channel::display_mask(ChannelMask::<Xca9543a<I2cMock>>::ALL).to_string()
+        );
+        assert_eq!("ch5", Channel::Ch5.to_string());
+        assert_eq!("{1,7}", Channel::display_mask(0x82).to_string());
+    }
+
+    fn has_interrupts<T: DeviceType>() -> bool {
+        T::HAS_INTERRUPTS
+    }
+
+    #[test]
+    fn interrupt_capability_is_correct() {
+        assert!(!has_interrupts::<Xca9548a<I2cMock>>());
+        assert!(has_interrupts::<Xca9545a<I2cMock>>());
+        assert!(has_interrupts::<Xca9543a<I2cMock>>());
+    }
+
+    #[test]
+    fn can_get_device_info() {
+        let switch = Xca9545a::new(I2cMock::new(&[]), SlaveAddr::Alternative(false, true, true));
+        let info = switch.device_info();
+        assert_eq!(0x73, info.address);
+        assert_eq!("TCA9545A/PCA9545A", info.name);
+        assert_eq!(4, info.channels);
+        assert!(info.has_interrupts);
+        assert_eq!("TCA9545A/PCA9545A at 0x73", info.to_string());
+        switch.destroy().done();
+    }
+}
+
+mod state {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+
+    #[test]
+    fn can_destroy_and_rebuild_keeping_cache() {
+        let transactions = [
+            I2cTrans::write(0x72, vec![0x04]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut switch = Xca9548a::new(
+            I2cMock::new(&transactions),
+            SlaveAddr::Alternative(false, true, false),
+        );
+        switch.select_channels(0x04).unwrap();
+        let (i2c, state) = switch.destroy_with_state();
+        assert_eq!(0x72, state.address());
+        assert_eq!(0x04, state.selected_channels());
+
+        let switch = Xca9548a::from_parts(i2c, state);
+        {
+            // channel 2 is still known to be selected
+            let mut parts = switch.split();
+            parts.i2c2.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+}
+
+#[cfg(feature = "ten-bit-address")]
+mod ten_bit_address {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, SevenBitAddress, TenBitAddress};
+
+    /// Bus supporting both addressing modes recording (address, is 10-bit, written data).
+    #[derive(Default)]
+    struct DualBus(Vec<(u16, bool, Vec<u8>)>);
+
+    impl DualBus {
+        fn record(&mut self, address: u16, ten_bit: bool, operations: &[Operation<'_>]) {
+            let mut written = Vec::new();
+            for op in operations {
+                if let Operation::Write(data) = op {
+                    written.extend_from_slice(data);
+                }
+            }
+            self.0.push((address, ten_bit, written));
+        }
+    }
+
+    impl ErrorType for DualBus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c<SevenBitAddress> for DualBus {
+        fn transaction(&mut self, address: u8, ops: &mut [Operation<'_>]) -> Result<(), ErrorKind> {
+            self.record(u16::from(address), false, ops);
+            Ok(())
+        }
+    }
+
+    impl I2c<TenBitAddress> for DualBus {
+        fn transaction(
+            &mut self,
+            address: u16,
+            ops: &mut [Operation<'_>],
+        ) -> Result<(), ErrorKind> {
+            self.record(address, true, ops);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn can_write_to_ten_bit_slave_through_device() {
+        let mut switch = Xca9548a::new(DualBus::default(), SlaveAddr::default());
+        I2c::<TenBitAddress>::write(&mut switch, 0x2AB, &[0x12]).unwrap();
+        let bus = switch.destroy();
+        assert_eq!(vec![(0x2AB, true, vec![0x12])], bus.0);
+    }
+
+    #[test]
+    fn can_write_to_ten_bit_slave_through_part() {
+        let switch = Xca9548a::new(DualBus::default(), SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            I2c::<TenBitAddress>::write(&mut parts.i2c1, 0x2AB, &[0x12]).unwrap();
+        }
+        let bus = switch.destroy();
+        assert_eq!(
+            vec![
+                (u16::from(DEV_ADDR), false, vec![0x02]),
+                (0x2AB, true, vec![0x12])
+            ],
+            bus.0
+        );
+    }
+}
+
+mod control_register {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+
+    #[test]
+    fn can_read_control_register_and_update_cache() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0x52]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        assert_eq!(0x52, switch.read_control_register().unwrap());
+        {
+            // channel 1 is known to be selected
+            let mut parts = switch.split();
+            parts.i2c1.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn can_write_raw_control_register_and_update_cache() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0xF4]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.write_control_register(0xF4).unwrap();
+        {
+            // channel 2 is known to be selected
+            let mut parts = switch.split();
+            parts.i2c2.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+}
+
+mod verify_writes {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use xca9548a::Error;
+
+    fn new_9545a(transactions: &[I2cTrans]) -> Xca9545a<I2cMock> {
+        Xca9545a::builder()
+            .verify_writes(true)
+            .build(I2cMock::new(transactions))
+            .unwrap_or_else(|_| panic!())
+    }
+
+    #[test]
+    fn accepts_matching_readback_ignoring_interrupt_bits() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0b0000_0101]),
+            I2cTrans::read(DEV_ADDR, vec![0b1010_0101]),
+        ];
+        let mut switch = new_9545a(&transactions);
+        switch.select_channels(0b0000_0101).unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn reports_mismatching_readback() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0b0000_0101]),
+            I2cTrans::read(DEV_ADDR, vec![0b0000_0001]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut switch = new_9545a(&transactions);
+        assert_eq!(
+            Err(Error::VerificationFailed {
+                expected: 0b0000_0101,
+                actual: 0b0000_0001
+            }),
+            switch.select_channels(0b0000_0101)
+        );
+        {
+            // the channels read back are cached
+            let mut parts = switch.split();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+}
+
+mod router {
+    use super::scan::scan_transactions;
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c, NoAcknowledgeSource};
+    use xca9548a::{Channel, Error, Route, RoutingError, RoutingTable};
+
+    const NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+
+    #[test]
+    fn routes_transfers_by_address() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(0x48, vec![0x01]),
+            I2cTrans::write_read(0x48, vec![0x02], vec![0x34]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::read(0x20, vec![0x56]),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(0x50, vec![0x03]),
+        ];
+        let routes = [
+            ("temp", Route::new(Channel::Ch1, 0x48)),
+            ("io", Route::new(Channel::Ch0, 0x20)),
+        ];
+        let table = RoutingTable::new(&routes);
+        let switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut router = switch.into_router_with_table(table);
+        router.write(0x48, &[0x01]).unwrap();
+        let mut data = [0];
+        router.write_read(0x48, &[0x02], &mut data).unwrap();
+        assert_eq!([0x34], data);
+        router.read(0x20, &mut data).unwrap();
+        assert_eq!([0x56], data);
+        // no route, talk upstream
+        router.write(0x50, &[0x03]).unwrap();
+        router.destroy().destroy().done();
+    }
+
+    #[test]
+    fn learns_routes_and_flags_duplicates() {
+        let mut transactions = vec![
+            I2cTrans::read(DEV_ADDR, vec![0]),
+            I2cTrans::write(DEV_ADDR, vec![0]),
+        ];
+        for address in (0x08..=0x77).filter(|a| *a != DEV_ADDR) {
+            let trans = I2cTrans::read(address, vec![0]);
+            if address == 0x50 {
+                transactions.push(trans);
+            } else {
+                transactions.push(trans.with_error(NACK));
+            }
+        }
+        transactions.push(I2cTrans::write(DEV_ADDR, vec![0]));
+        transactions.extend(scan_transactions(
+            2,
+            &[(0, 0x20), (0, 0x50), (1, 0x20), (1, 0x48), (1, 0x50)],
+        ));
+        transactions.extend([
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(0x48, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(0x50, vec![0x02]),
+        ]);
+        let switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut router = switch.into_router();
+        let report = router.learn().unwrap();
+        assert_eq!(5, report.device_count());
+        assert_eq!(Some(Channel::Ch1), router.channel_of(0x48));
+        assert_eq!(None, router.channel_of(0x50));
+        assert!(router.is_ambiguous(0x20));
+        assert_eq!(vec![0x20], router.ambiguous_addresses().collect::<Vec<_>>());
+
+        router.write(0x48, &[0x01]).unwrap();
+        assert_eq!(
+            Err(Error::AmbiguousAddress(0x20)),
+            router.write(0x20, &[0x01])
+        );
+        router.write(0x50, &[0x02]).unwrap();
+        router.destroy().destroy().done();
+    }
+
+    #[test]
+    fn table_takes_precedence_over_learned_routes() {
+        let mut transactions = vec![
+            I2cTrans::read(DEV_ADDR, vec![0]),
+            I2cTrans::write(DEV_ADDR, vec![0]),
+        ];
+        for address in (0x08..=0x77).filter(|a| *a != DEV_ADDR) {
+            transactions.push(I2cTrans::read(address, vec![0]).with_error(NACK));
+        }
+        transactions.push(I2cTrans::write(DEV_ADDR, vec![0]));
+        transactions.extend(scan_transactions(2, &[(0, 0x20), (1, 0x20), (1, 0x48)]));
+        transactions.extend([
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(0x20, vec![0x01]),
+        ]);
+        let routes = [("io", Route::new(Channel::Ch0, 0x20))];
+        let table = RoutingTable::new(&routes);
+        let switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut router = switch.into_router_with_table(table);
+        router.learn().unwrap();
+        assert!(!router.is_ambiguous(0x20));
+        assert_eq!(Some(Channel::Ch0), router.channel_of(0x20));
+        assert_eq!(Some(Channel::Ch1), router.channel_of(0x48));
+        router.write(0x20, &[0x01]).unwrap();
+        router.destroy().destroy().done();
+    }
+
+    #[test]
+    fn learning_restores_selection_read_back() {
+        // the selection is unknown, e.g. after construction
+        let mut transactions = vec![
+            I2cTrans::read(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(DEV_ADDR, vec![0]),
+        ];
+        for address in (0x08..=0x77).filter(|a| *a != DEV_ADDR) {
+            transactions.push(I2cTrans::read(address, vec![0]).with_error(NACK));
+        }
+        transactions.push(I2cTrans::write(DEV_ADDR, vec![0x02]));
+        transactions.extend(scan_transactions(2, &[]));
+        *transactions.last_mut().unwrap() = I2cTrans::write(DEV_ADDR, vec![0x02]);
+        let switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut router = switch.into_router();
+        assert_eq!(0, router.learn().unwrap().device_count());
+        router.destroy().destroy().done();
+    }
+
+    #[test]
+    fn rejects_table_using_the_switch_address() {
+        let routes = [("adc", Route::new(Channel::Ch0, 0x61))];
+        let table = RoutingTable::new(&routes);
+        let address = SlaveAddr::Alternative(false, false, true);
+        let switch = Xca9548a::new_with_base_address(I2cMock::new(&[]), 0x60, address);
+        let router = switch.into_router_with_table(table);
+        assert_eq!(Err(RoutingError::SwitchAddress(&"adc")), router.validate());
+        router.destroy().destroy().done();
+    }
+
+    #[test]
+    fn validates_table_with_the_switch_address() {
+        let routes = [("adc", Route::new(Channel::Ch0, DEV_ADDR))];
+        let table = RoutingTable::new(&routes);
+        let switch = Xca9548a::new_with_base_address(I2cMock::new(&[]), 0x60, SlaveAddr::default());
+        let router = switch.into_router_with_table(table);
+        assert_eq!(Ok(()), router.validate());
+        assert_eq!(
+            Err(RoutingError::SwitchAddress(&"adc")),
+            table.validate(SlaveAddr::default())
+        );
+        router.destroy().destroy().done();
+    }
+}
+
+mod mismatch_policy {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use xca9548a::{Error, MismatchPolicy, Observer};
+
+    #[derive(Default)]
+    struct Mismatches(Vec<(u8, u8)>);
+
+    impl Observer for Mismatches {
+        fn mismatch(&mut self, expected: u8, actual: u8) {
+            self.0.push((expected, actual));
+        }
+    }
+
+    #[test]
+    fn rewrite_retries_verified_write_once() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x05]),
+            I2cTrans::read(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x05]),
+            I2cTrans::read(DEV_ADDR, vec![0x05]),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::read(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::read(DEV_ADDR, vec![0x00]),
+        ];
+        let mut switch = Xca9545a::builder()
+            .verify_writes(true)
+            .mismatch_policy(MismatchPolicy::Rewrite)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        switch.select_channels(0x05).unwrap();
+        assert_eq!(
+            Err(Error::VerificationFailed {
+                expected: 0x02,
+                actual: 0x00
+            }),
+            switch.select_channels(0x02)
+        );
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn rewrite_restores_cached_channels_on_external_change() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::read(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = Xca9548a::builder()
+            .integrity_check_interval(1)
+            .mismatch_policy(MismatchPolicy::Rewrite)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        {
+            let mut parts = switch.split();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn notify_reports_to_observer_and_continues() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::read(DEV_ADDR, vec![0x03]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::read(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::read(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut mismatches = Mismatches::default();
+        let switch = Xca9548a::builder()
+            .verify_writes(true)
+            .integrity_check_interval(1)
+            .mismatch_policy(MismatchPolicy::Notify)
+            .build(I2cMock::new(&transactions))
+            .unwrap()
+            .with_observer(&mut mismatches);
+        {
+            let mut parts = switch.split();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+        assert_eq!(vec![(0x01, 0x03), (0x03, 0x02)], mismatches.0);
+    }
+}
+
+mod integrity_check {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use xca9548a::Error;
+
+    fn new_9548a(transactions: &[I2cTrans]) -> Xca9548a<I2cMock> {
+        Xca9548a::builder()
+            .integrity_check_interval(2)
+            .build(I2cMock::new(transactions))
+            .unwrap_or_else(|_| panic!())
+    }
+
+    #[test]
+    fn checks_every_interval() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::read(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = new_9548a(&transactions);
+        {
+            let mut parts = switch.split();
+            for _ in 0..3 {
+                parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            }
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn reports_external_change() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::read(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = new_9548a(&transactions);
+        {
+            let mut parts = switch.split();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            assert_eq!(
+                Err(Error::ExternalChange {
+                    cached: 0x01,
+                    actual: 0x00
+                }),
+                parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            );
+            // the channel is selected again on retry
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+}
+
+mod auto_recover {
+    use super::*;
+    use embedded_hal::delay::DelayNs;
+    use embedded_hal::i2c::{ErrorKind, I2c, NoAcknowledgeSource};
+    use xca9548a::{Error, RetryPolicy};
+
+    const NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+
+    fn new(transactions: &[I2cTrans]) -> Xca9548a<I2cMock> {
+        Xca9548a::builder()
+            .auto_recover(true)
+            .build(I2cMock::new(transactions))
+            .unwrap()
+    }
+
+    #[test]
+    fn retries_after_recovering() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(ErrorKind::Bus),
+            I2cTrans::read(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = new(&transactions);
+        switch
+            .split()
+            .i2c0
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn returns_error_if_retry_fails() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::read(SLAVE_ADDR, vec![0; 2]).with_error(ErrorKind::Bus),
+            I2cTrans::read(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::read(SLAVE_ADDR, vec![0; 2]).with_error(NACK),
+        ];
+        let switch = new(&transactions);
+        let mut data = [0; 2];
+        let error = switch.split().i2c1.read(SLAVE_ADDR, &mut data).unwrap_err();
+        assert_eq!(Error::I2C(NACK), error);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn does_not_recover_from_slave_nack() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(NACK),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = new(&transactions);
+        {
+            let mut parts = switch.split();
+            // acknowledge polling
+            assert_eq!(
+                Err(Error::I2C(NACK)),
+                parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            );
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+
+    #[derive(Default)]
+    struct RecordingDelay(Vec<u32>);
+
+    impl DelayNs for RecordingDelay {
+        fn delay_ns(&mut self, ns: u32) {
+            self.0.push(ns);
+        }
+    }
+
+    #[test]
+    fn backs_off_between_retries() {
+        let failure = I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec())
+            .with_error(ErrorKind::ArbitrationLoss);
+        let mut transactions = vec![I2cTrans::write(DEV_ADDR, vec![0x01]), failure.clone()];
+        for _ in 0..3 {
+            transactions.push(I2cTrans::read(DEV_ADDR, vec![0x01]));
+            transactions.push(I2cTrans::write(DEV_ADDR, vec![0x01]));
+            transactions.push(failure.clone());
+        }
+        let switch = Xca9548a::builder()
+            .auto_recover(true)
+            .retry_policy(RetryPolicy::Exponential {
+                retries: 3,
+                initial_ns: 1000,
+                max_ns: 3000,
+                jitter: false,
+            })
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        let switch = switch.with_settle_delay(RecordingDelay::default(), 0);
+        let error = switch
+            .split()
+            .i2c0
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap_err();
+        assert_eq!(Error::I2C(ErrorKind::ArbitrationLoss), error);
+        let (switch, delay) = switch.release_settle_delay();
+        assert_eq!(vec![1000, 2000, 3000], delay.0);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn returns_original_error_if_device_does_not_respond() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]).with_error(ErrorKind::Bus),
+            I2cTrans::read(DEV_ADDR, vec![0]).with_error(NACK),
+        ];
+        let switch = new(&transactions);
+        let error = switch
+            .split()
+            .i2c0
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap_err();
+        assert_eq!(Error::ChannelSelect(ErrorKind::Bus), error);
+        switch.destroy().done();
+    }
+}
+
+mod settle_delay {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use embedded_hal_mock::eh1::delay::{CheckedDelay, Transaction as DelayTrans};
+    use xca9548a::Channel;
+
+    #[test]
+    fn delays_after_selecting_channels() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+        ];
+        let delay = CheckedDelay::new(&[DelayTrans::delay_ns(5_000)]);
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default())
+            .with_settle_delay(delay, 5_000);
+        {
+            let mut parts = switch.split();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        // deselecting is not delayed
+        switch.select_channels(0).unwrap();
+        let (switch, mut delay) = switch.release_settle_delay();
+        delay.done();
+        switch.destroy().done();
+    }
+    #[test]
+    fn delays_per_channel() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x20]),
+            I2cTrans::write(DEV_ADDR, vec![0x21]),
+        ];
+        let delay =
+            CheckedDelay::new(&[DelayTrans::delay_ns(100_000), DelayTrans::delay_ns(100_000)]);
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default())
+            .with_settle_delay(delay, 0);
+        switch.set_settle_time(Channel::Ch5, 100_000);
+        // channel 0 needs no settle time
+        switch.select_channels(0x01).unwrap();
+        switch.select_channels(0x20).unwrap();
+        switch.select_channels(0x21).unwrap();
+        let (switch, mut delay) = switch.release_settle_delay();
+        delay.done();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn keeps_settle_times_of_channels() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x20]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x20]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.set_settle_time(Channel::Ch5, 100_000);
+        let delay =
+            CheckedDelay::new(&[DelayTrans::delay_ns(100_000), DelayTrans::delay_ns(5_000)]);
+        let mut switch = switch.with_settle_delay(delay, 5_000);
+        switch.select_channels(0x20).unwrap();
+        switch.select_channels(0x01).unwrap();
+        let (switch, mut delay) = switch.release_settle_delay();
+        delay.done();
+
+        let delay =
+            CheckedDelay::new(&[DelayTrans::delay_ns(100_000), DelayTrans::delay_ns(5_000)]);
+        let mut switch = switch.with_settle_delay(delay, 1_000);
+        switch.select_channels(0x20).unwrap();
+        switch.select_channels(0x01).unwrap();
+        let (switch, mut delay) = switch.release_settle_delay();
+        delay.done();
+        switch.destroy().done();
+    }
+}
+
+mod cascade {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use xca9548a::{Channel, ChannelPath, Error};
+
+    const CHILD_ADDR: u8 = DEV_ADDR | 1;
+
+    #[test]
+    fn selects_whole_path() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x08]),
+            I2cTrans::write(CHILD_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            // sibling branch on the parent
+            I2cTrans::write(DEV_ADDR, vec![0x20]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            // back to the leaf
+            I2cTrans::write(DEV_ADDR, vec![0x08]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let parent = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut parts = parent.split();
+            let child = Xca9543a::new(parts.i2c3, SlaveAddr::Alternative(false, false, true));
+            {
+                let mut child_parts = child.split();
+                child_parts
+                    .i2c1
+                    .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+                    .unwrap();
+                parts.i2c5.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+                child_parts
+                    .i2c1
+                    .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+                    .unwrap();
+            }
+        }
+        parent.destroy().done();
+    }
+
+    #[test]
+    fn can_select_path() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x08]),
+            I2cTrans::write(CHILD_ADDR, vec![0x02]),
+            I2cTrans::write(DEV_ADDR, vec![0x20]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x08]),
+            I2cTrans::write(CHILD_ADDR, vec![0x02]),
+        ];
+        let parent = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut parts = parent.split();
+            let mut child = Xca9543a::new(parts.i2c3, SlaveAddr::Alternative(false, false, true));
+            let path = ChannelPath::new(Channel::Ch3, Channel::Ch1);
+            child.select_path(path).unwrap();
+            parts.i2c5.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            child.select_path(path).unwrap();
+        }
+        parent.destroy().done();
+    }
+
+    #[test]
+    fn rejects_path_through_other_upstream_channel() {
+        let parent = Xca9548a::new(I2cMock::new(&[]), SlaveAddr::default());
+        {
+            let parts = parent.split();
+            let mut child = Xca9543a::new(parts.i2c3, SlaveAddr::Alternative(false, false, true));
+            let path = ChannelPath::new(Channel::Ch2, Channel::Ch1);
+            assert_eq!(Err(Error::InvalidPath(path)), child.select_path(path));
+        }
+        parent.destroy().done();
+    }
+}
+
+mod tree {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use xca9548a::mux_tree;
+
+    #[test]
+    fn can_declare_tree() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x08]),
+            I2cTrans::write(DEV_ADDR | 1, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let i2c = I2cMock::new(&transactions);
+        {
+            mux_tree! {
+                let root = Xca9548a(i2c, SlaveAddr::default()) {
+                    leaves { psu: i2c0 }
+                    children {
+                        slot: Xca9543a(i2c3, SlaveAddr::Alternative(false, false, true)) {
+                            leaves { sensor: i2c1 }
+                            children {}
+                        }
+                    }
+                }
+            }
+            psu.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            sensor.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            root.destroy().done();
+        }
+    }
+}
+
+mod group {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c};
+    use xca9548a::{AddressError, Channel, Error, MuxGroup, MuxSpec};
+
+    const OTHER_ADDR: u8 = DEV_ADDR | 1;
+
+    fn new(transactions: &[I2cTrans]) -> MuxGroup<I2cMock, 2> {
+        MuxGroup::new(
+            I2cMock::new(transactions),
+            [
+                MuxSpec::xca9548a(SlaveAddr::default()).unwrap(),
+                MuxSpec::xca9543a(SlaveAddr::Alternative(false, false, true)).unwrap(),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn tracks_selection_of_each_device() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x04]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(OTHER_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let group = new(&transactions);
+        {
+            let mut a = group.bus(0, Channel::Ch2).unwrap();
+            let mut b = group.bus(1, Channel::Ch1).unwrap();
+            a.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            b.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            a.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        assert_eq!([Some(0x04), Some(0x02)], group.selected_channels());
+        group.destroy().done();
+    }
+
+    #[test]
+    fn numbers_virtual_channels_consecutively() {
+        let group = new(&[]);
+        assert_eq!(10, group.virtual_channel_count());
+        assert_eq!(Some((0, Channel::Ch0)), group.locate_virtual_channel(0));
+        assert_eq!(Some((0, Channel::Ch7)), group.locate_virtual_channel(7));
+        assert_eq!(Some((1, Channel::Ch0)), group.locate_virtual_channel(8));
+        assert_eq!(Some((1, Channel::Ch1)), group.locate_virtual_channel(9));
+        assert_eq!(None, group.locate_virtual_channel(10));
+        assert!(group.virtual_channel(10).is_none());
+        group.destroy().done();
+    }
+
+    #[test]
+    fn can_transfer_on_virtual_channel() {
+        let transactions = [
+            I2cTrans::write(OTHER_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let group = new(&transactions);
+        group
+            .virtual_channel(9)
+            .unwrap()
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        assert_eq!([None, Some(0x02)], group.selected_channels());
+        group.destroy().done();
+    }
+
+    #[test]
+    fn can_select_channels() {
+        let transactions = [I2cTrans::write(OTHER_ADDR, vec![0x01])];
+        let mut group = new(&transactions);
+        group.select_channels(1, 0x81).unwrap();
+        assert_eq!([None, Some(0x01)], group.selected_channels());
+        group.destroy().done();
+    }
+
+    #[test]
+    fn writes_first_selection_of_no_channels() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x00])];
+        let mut group = new(&transactions);
+        group.select_channels(0, 0).unwrap();
+        assert_eq!([Some(0x00), None], group.selected_channels());
+        group.destroy().done();
+    }
+
+    #[test]
+    fn rejects_missing_device() {
+        let mut group = new(&[]);
+        assert_eq!(Err(Error::NoSuchDevice(2)), group.select_channels(2, 0x01));
+        group.destroy().done();
+    }
+
+    #[test]
+    fn rejects_invalid_addresses() {
+        assert_eq!(
+            Err(AddressError::Reserved(0x7C)),
+            MuxSpec::xca9548a(SlaveAddr::Raw(0x7C))
+        );
+        assert_eq!(
+            Err(AddressError::OutOfRange(0x80)),
+            MuxSpec::xca9545a(SlaveAddr::Raw(0x80))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_channels_in_strict_mode() {
+        let mut group = new(&[]);
+        group.set_strict(true);
+        assert_eq!(
+            Err(Error::InvalidChannels(0x04)),
+            group.select_channels(1, 0x04)
+        );
+        group.destroy().done();
+    }
+
+    #[test]
+    fn verifies_writes() {
+        let transactions = [
+            I2cTrans::write(OTHER_ADDR, vec![0x02]),
+            I2cTrans::read(OTHER_ADDR, vec![0x00]),
+        ];
+        let mut group = new(&transactions);
+        group.set_verify_writes(true);
+        assert_eq!(
+            Err(Error::VerificationFailed {
+                expected: 0x02,
+                actual: 0x00
+            }),
+            group.select_channels(1, 0x02)
+        );
+        group.destroy().done();
+    }
+
+    #[test]
+    fn calls_observer() {
+        #[derive(Default)]
+        struct Selections(Vec<(u8, Result<(), ErrorKind>)>);
+
+        impl xca9548a::Observer for Selections {
+            fn after_select(&mut self, channels: u8, result: Result<(), ErrorKind>) {
+                self.0.push((channels, result));
+            }
+        }
+
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(OTHER_ADDR, vec![0x02]).with_error(ErrorKind::Bus),
+        ];
+        let mut selections = Selections::default();
+        let mut group = new(&transactions).with_observer(&mut selections);
+        group.select_channels(0, 0x01).unwrap();
+        group.select_channels(1, 0x02).unwrap_err();
+        group.destroy().done();
+        assert_eq!(
+            vec![(0x01, Ok(())), (0x02, Err(ErrorKind::Bus))],
+            selections.0
+        );
+    }
+
+    #[test]
+    fn exclusive_mode_deselects_other_devices() {
+        let transactions = [
+            // the selection of the other device is unknown
+            I2cTrans::write(OTHER_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x04]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(OTHER_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(OTHER_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x04]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut group = new(&transactions);
+        group.set_exclusive(true);
+        {
+            let mut a = group.bus(0, Channel::Ch2).unwrap();
+            let mut b = group.bus(1, Channel::Ch1).unwrap();
+            a.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            b.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            a.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        assert_eq!([Some(0x04), Some(0x00)], group.selected_channels());
+        group.destroy().done();
+    }
+
+    #[test]
+    fn has_no_bus_for_missing_channels() {
+        let group = new(&[]);
+        assert!(group.bus(1, Channel::Ch2).is_none());
+        assert!(group.bus(2, Channel::Ch0).is_none());
+        group.destroy().done();
+    }
+
+    #[test]
+    fn rejects_duplicate_addresses() {
+        let spec = MuxSpec::xca9548a(SlaveAddr::default()).unwrap();
+        let other = MuxSpec::xca9543a(SlaveAddr::Alternative(false, false, true)).unwrap();
+        let result = MuxGroup::new(I2cMock::new(&[]), [spec, other, spec]);
+        let (mut i2c, error) = result.err().unwrap();
+        assert_eq!(AddressError::Duplicate(DEV_ADDR), error);
+        i2c.done();
+    }
+
+    #[test]
+    fn keeps_last_errors_of_each_device() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(OTHER_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(ErrorKind::Bus),
+        ];
+        let group = new(&transactions);
+        group
+            .bus(0, Channel::Ch1)
+            .unwrap()
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        group
+            .bus(1, Channel::Ch1)
+            .unwrap()
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap_err();
+        assert_eq!(None, group.last_error(0, Channel::Ch1));
+        let last_error = group.last_error(1, Channel::Ch1).unwrap();
+        assert_eq!(ErrorKind::Bus, last_error.kind());
+        assert_eq!(None, group.last_error(2, Channel::Ch1));
+        group.destroy().done();
+    }
+}
+
+mod upstream {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c};
+
+    #[test]
+    fn deselects_channels_before_transfer() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_READ_DATA.to_vec()),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x01).unwrap();
+        {
+            let mut upstream = switch.upstream();
+            upstream.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            let mut data = [0; 2];
+            upstream.read(SLAVE_ADDR, &mut data).unwrap();
+            assert_eq!(SLAVE_READ_DATA, data);
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn deselects_channels_on_new_driver() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut upstream = switch.upstream();
+            upstream.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            upstream.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn deselects_channels_after_failed_selection() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]).with_error(ErrorKind::Bus),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x00).unwrap();
+        assert!(switch.select_channels(0x01).is_err());
+        switch
+            .upstream()
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        switch.destroy().done();
+    }
+}
+
+mod with_bus {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+
+    #[test]
+    fn reselects_channels_afterwards() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x01).unwrap();
+        let result = switch.with_bus(|i2c| i2c.write(DEV_ADDR, &[0x02]));
+        assert!(result.is_ok());
+        {
+            let mut parts = switch.split();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.with_bus(|_| ());
+        switch.select_channels(0x01).unwrap();
+        switch.destroy().done();
+    }
+}
+
+mod cache_state {
+    use super::*;
+
+    #[test]
+    fn can_invalidate_cache() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x01).unwrap();
+        assert!(switch.is_cache_clean().unwrap());
+        switch.select_channels(0x01).unwrap();
+        switch.invalidate_cache();
+        assert!(!switch.is_cache_clean().unwrap());
+        switch.select_channels(0x01).unwrap();
+        assert!(switch.is_cache_clean().unwrap());
+        switch.destroy().done();
+    }
+}
+
+mod topology {
+    use super::*;
+    use xca9548a::{Channel, Conflict, Topology};
+
+    const DEVICES: [(Channel, u8); 4] = [
+        (Channel::Ch0, 0x48),
+        (Channel::Ch1, 0x48),
+        (Channel::Ch2, 0x50),
+        (Channel::Ch2, 0x51),
+    ];
+
+    #[test]
+    fn accepts_exclusive_channels() {
+        let topology = Topology::new(SlaveAddr::default(), &DEVICES);
+        assert_eq!(Ok(()), topology.validate(&[0x01, 0x02, 0x06]));
+    }
+
+    #[test]
+    fn reports_duplicates_on_enabled_channels() {
+        let topology = Topology::new(SlaveAddr::default(), &DEVICES);
+        assert_eq!(
+            Err(Conflict::DuplicateAddress {
+                address: 0x48,
+                channels: (Channel::Ch0, Channel::Ch1)
+            }),
+            topology.validate(&[0x04, 0x03])
+        );
+    }
+
+    #[test]
+    fn reports_duplicates_on_same_channel() {
+        let devices = [(Channel::Ch3, 0x20), (Channel::Ch3, 0x20)];
+        let topology = Topology::new(SlaveAddr::default(), &devices);
+        assert_eq!(1, topology.conflicts(&[]).count());
+    }
+
+    #[test]
+    fn reports_switch_address() {
+        let devices = [(Channel::Ch1, DEV_ADDR)];
+        let topology = Topology::new(SlaveAddr::default(), &devices);
+        assert_eq!(
+            Err(Conflict::SwitchAddress {
+                channel: Channel::Ch1,
+                address: DEV_ADDR
+            }),
+            topology.validate(&[])
+        );
+    }
+}
+
+mod checked_channels {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use xca9548a::Channel;
+
+    #[test]
+    fn can_get_checked_channel() {
+        assert_eq!(Channel::Ch7, Xca9548a::<I2cMock>::channel::<7>());
+        assert_eq!(Channel::Ch3, Xca9545a::<I2cMock>::channel::<3>());
+    }
+
+    #[test]
+    fn can_use_checked_part() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch
+            .part::<1>()
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        switch.destroy().done();
+    }
+}
+
+mod fanout {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c, NoAcknowledgeSource};
+    use xca9548a::{Channel, Error};
+
+    const NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+
+    #[test]
+    fn can_broadcast_write() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x0F]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+        ];
+        let mut switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x01).unwrap();
+        switch
+            .broadcast_write(0xFF, SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        switch.destroy().done();
+    }
+    #[test]
+    fn can_run_for_each_channel() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::read(SLAVE_ADDR, vec![1]),
+            I2cTrans::write(DEV_ADDR, vec![0x08]),
+            I2cTrans::read(SLAVE_ADDR, vec![3]),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+        ];
+        let mut switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut values = Vec::new();
+        switch
+            .for_each_channel(0x0A, |channel, i2c| {
+                let mut data = [0];
+                i2c.read(SLAVE_ADDR, &mut data)?;
+                values.push((channel, data[0]));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(vec![(Channel::Ch1, 1), (Channel::Ch3, 3)], values);
+        switch.destroy().done();
+    }
+    #[test]
+    fn can_write_each_channel() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(NACK),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+        ];
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let results = switch
+            .write_each(0x03, SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        assert!(!results.is_ok());
+        assert_eq!(Some(&Ok(())), results.get(Channel::Ch0));
+        assert_eq!(
+            vec![(Channel::Ch1, &Error::I2C(NACK))],
+            results.errors().collect::<Vec<_>>()
+        );
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn can_read_each_channel() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_READ_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x04]),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut buffers = [[0; 2]; 8];
+        let results = switch.read_each(0x05, SLAVE_ADDR, &mut buffers).unwrap();
+        assert!(results.is_ok());
+        assert_eq!(None, results.get(Channel::Ch1));
+        assert_eq!(SLAVE_READ_DATA, buffers[0]);
+        assert_eq!(SLAVE_WRITE_DATA, buffers[2]);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn keeps_results_when_restoring_fails() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_READ_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x00]).with_error(ErrorKind::Bus),
+        ];
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut buffers = [[0; 2]; 2];
+        let results = switch.read_each(0x01, SLAVE_ADDR, &mut buffers).unwrap();
+        assert!(!results.is_ok());
+        assert_eq!(Some(&Ok(())), results.get(Channel::Ch0));
+        assert_eq!(0, results.errors().count());
+        assert_eq!(
+            Some(&Error::ChannelSelect(ErrorKind::Bus)),
+            results.restore_error()
+        );
+        assert_eq!(SLAVE_READ_DATA, buffers[0]);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn reads_each_channel_into_buffers_of_present_channels() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_READ_DATA.to_vec()),
+        ];
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x02).unwrap();
+        let mut buffers = [[0; 2]; 2];
+        let results = switch.read_each(0x02, SLAVE_ADDR, &mut buffers).unwrap();
+        assert_eq!(Some(&Ok(())), results.get(Channel::Ch1));
+        assert_eq!(SLAVE_READ_DATA, buffers[1]);
+        switch.destroy().done();
+    }
+}
+
+mod poller {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c};
+    use std::future::Future;
+    use std::ops::ControlFlow;
+    use std::pin::pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use xca9548a::{Channel, Error, Poller};
+
+    type Bus<'a> = dyn I2c<Error = Error<ErrorKind>> + 'a;
+
+    #[test]
+    fn runs_due_jobs_in_rotation() {
+        let transactions = [
+            // tick 1
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, vec![1]),
+            I2cTrans::write(SLAVE_ADDR, vec![2]),
+            // tick 2: channel 1 goes first
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, vec![3]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, vec![1]),
+            I2cTrans::write(SLAVE_ADDR, vec![2]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut job1 = |i2c: &mut Bus| i2c.write(SLAVE_ADDR, &[1]);
+        let mut job2 = |i2c: &mut Bus| i2c.write(SLAVE_ADDR, &[2]);
+        let mut job3 = |i2c: &mut Bus| i2c.write(SLAVE_ADDR, &[3]);
+        let mut poller: Poller<_, 4> = Poller::new();
+        poller
+            .register(&switch, Channel::Ch0, 1, &mut job1)
+            .unwrap()
+            .unwrap();
+        poller
+            .register(&switch, Channel::Ch1, 2, &mut job3)
+            .unwrap()
+            .unwrap();
+        poller
+            .register(&switch, Channel::Ch0, 1, &mut job2)
+            .unwrap()
+            .unwrap();
+        assert_eq!(Ok(2), poller.tick(&mut switch));
+        assert_eq!(Ok(3), poller.tick(&mut switch));
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn records_job_errors() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x04]),
+            I2cTrans::write(SLAVE_ADDR, vec![1]).with_error(ErrorKind::Bus),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut job = |i2c: &mut Bus| i2c.write(SLAVE_ADDR, &[1]);
+        let mut poller: Poller<_, 1> = Poller::new();
+        poller
+            .register(&switch, Channel::Ch2, 1, &mut job)
+            .unwrap()
+            .unwrap();
+        assert_eq!(Err(Error::I2C(ErrorKind::Bus)), poller.tick(&mut switch));
+        let last_error = switch.last_error(Channel::Ch2).unwrap().unwrap();
+        assert_eq!(ErrorKind::Bus, last_error.kind());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn rejects_jobs_when_full() {
+        let switch = Xca9548a::new(I2cMock::new(&[]), SlaveAddr::default());
+        let mut job = |_: &mut Bus| Ok(());
+        let mut other = |_: &mut Bus| Ok(());
+        let mut poller: Poller<ErrorKind, 1> = Poller::new();
+        assert_eq!(
+            Ok(Some(0)),
+            poller.register(&switch, Channel::Ch0, 1, &mut job)
+        );
+        assert_eq!(
+            Ok(None),
+            poller.register(&switch, Channel::Ch0, 1, &mut other)
+        );
+        switch.destroy().done();
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct NoopWaker;
+
+        impl Wake for NoopWaker {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut context = Context::from_waker(&waker);
+        let mut future = pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn runs_ticks_after_each_sleep() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, vec![1]),
+            I2cTrans::write(SLAVE_ADDR, vec![1]).with_error(ErrorKind::Bus),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut job = |i2c: &mut Bus| i2c.write(SLAVE_ADDR, &[1]);
+        let mut poller: Poller<_, 1> = Poller::new();
+        poller
+            .register(&switch, Channel::Ch0, 1, &mut job)
+            .unwrap()
+            .unwrap();
+        let mut sleeps = 0;
+        let mut results = Vec::new();
+        let sleep = || {
+            sleeps += 1;
+            async {}
+        };
+        let error = block_on(poller.run(&mut switch, sleep, |result| match result {
+            Ok(count) => {
+                results.push(count);
+                ControlFlow::Continue(())
+            }
+            Err(e) => ControlFlow::Break(e),
+        }));
+        assert_eq!(Error::I2C(ErrorKind::Bus), error);
+        assert_eq!(vec![1], results);
+        assert_eq!(2, sleeps);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn rejects_missing_channel() {
+        let switch = Xca9543a::new(I2cMock::new(&[]), SlaveAddr::default());
+        let mut job = |_: &mut Bus| Ok(());
+        let mut poller: Poller<ErrorKind, 1> = Poller::new();
+        assert_eq!(
+            Err(Error::InvalidChannels(0x04)),
+            poller.register(&switch, Channel::Ch2, 1, &mut job)
+        );
+        switch.destroy().done();
+    }
+}
+
+#[cfg(feature = "std")]
+mod record {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c, NoAcknowledgeSource};
+    use xca9548a::record::{
+        ParseRecordingError, Record, RecordedOperation, Recorder, Recording, Replayer,
+    };
+    use xca9548a::Error;
+
+    const NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data);
+
+    fn record_session() -> Recording {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write_read(SLAVE_ADDR, vec![0x00], vec![0x12, 0x34]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(NACK),
+        ];
+        let bus = Recorder::new(I2cMock::new(&transactions), DEV_ADDR);
+        let switch = Xca9548a::new(bus, SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            let mut data = [0; 2];
+            parts
+                .i2c1
+                .write_read(SLAVE_ADDR, &[0x00], &mut data)
+                .unwrap();
+            assert_eq!([0x12, 0x34], data);
+            let error = parts.i2c1.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap_err();
+            assert!(matches!(error, Error::I2C(NACK)));
+        }
+        let (mut bus, recording) = switch.destroy().into_parts();
+        bus.done();
+        recording
+    }
+
+    #[test]
+    fn records_selection_and_transfers() {
+        let recording = record_session();
+        assert_eq!(
+            &[
+                Record::Select {
+                    address: DEV_ADDR,
+                    channels: 0x02,
+                    error: None,
+                },
+                Record::Transaction {
+                    channels: 0x02,
+                    address: SLAVE_ADDR,
+                    operations: vec![
+                        RecordedOperation::Write(vec![0x00]),
+                        RecordedOperation::Read(vec![0x12, 0x34]),
+                    ],
+                    error: None,
+                },
+                Record::Transaction {
+                    channels: 0x02,
+                    address: SLAVE_ADDR,
+                    operations: vec![RecordedOperation::Write(SLAVE_WRITE_DATA.to_vec())],
+                    error: Some(NACK),
+                },
+            ],
+            recording.records()
+        );
+    }
+
+    #[test]
+    fn converts_to_text_and_back() {
+        let recording = record_session();
+        let text = recording.to_string();
+        assert_eq!(
+            "select 70 02 ok\n\
+             transfer 02 20 w:00 r:1234 ok\n\
+             transfer 02 20 w:55aa err:nack-data\n",
+            text
+        );
+        assert_eq!(Ok(recording), text.parse());
+    }
+
+    #[test]
+    fn rejects_invalid_text() {
+        let text = "# comment\n\nselect 70 02 ok\ntransfer 02 50 x:00 ok\n";
+        assert_eq!(
+            Err(ParseRecordingError { line: 4 }),
+            text.parse::<Recording>()
+        );
+    }
+
+    #[test]
+    fn replays_responses_and_errors() {
+        let switch = Xca9548a::new(Replayer::new(record_session()), SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            let mut data = [0; 2];
+            parts
+                .i2c1
+                .write_read(SLAVE_ADDR, &[0x00], &mut data)
+                .unwrap();
+            assert_eq!([0x12, 0x34], data);
+            let error = parts.i2c1.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap_err();
+            assert!(matches!(error, Error::I2C(NACK)));
+        }
+        let replayer = switch.destroy();
+        assert_eq!(0, replayer.remaining());
+        assert_eq!(None, replayer.mismatch());
+    }
+
+    #[test]
+    fn reports_mismatch() {
+        let switch = Xca9548a::new(Replayer::new(record_session()), SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            let error = parts.i2c2.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap_err();
+            assert!(matches!(error, Error::ChannelSelect(ErrorKind::Other)));
+        }
+        let replayer = switch.destroy();
+        assert_eq!(Some(0), replayer.mismatch());
+        assert_eq!(3, replayer.remaining());
+    }
+}
+
+#[cfg(feature = "history")]
+mod history {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c, NoAcknowledgeSource};
+    use xca9548a::{AccessKind, HistoryEntry, HISTORY_LEN};
+
+    const NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+
+    #[test]
+    fn starts_empty() {
+        let switch = Xca9548a::new(I2cMock::new(&[]), SlaveAddr::default());
+        assert!(switch.history().unwrap().is_empty());
+        assert_eq!(None, switch.history().unwrap().last());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn records_selection_and_transfers() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x04]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::read(SLAVE_ADDR, vec![0]).with_error(NACK),
+        ];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            parts.i2c2.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            parts.i2c2.read(SLAVE_ADDR, &mut [0]).unwrap_err();
+        }
+        let history = switch.history().unwrap();
+        let entries: Vec<_> = history.iter().copied().collect();
+        assert_eq!(
+            vec![
+                HistoryEntry {
+                    channels: 0x04,
+                    address: u16::from(DEV_ADDR),
+                    kind: AccessKind::SelectChannels,
+                    error: None,
+                },
+                HistoryEntry {
+                    channels: 0x04,
+                    address: u16::from(SLAVE_ADDR),
+                    kind: AccessKind::Write,
+                    error: None,
+                },
+                HistoryEntry {
+                    channels: 0x04,
+                    address: u16::from(SLAVE_ADDR),
+                    kind: AccessKind::Read,
+                    error: Some(NACK),
+                },
+            ],
+            entries
+        );
+        assert_eq!(Some(NACK), history.last().unwrap().error);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn keeps_most_recent_operations() {
+        let count = HISTORY_LEN + 3;
+        let transactions: Vec<_> = (0..count)
+            .map(|i| I2cTrans::write(DEV_ADDR, vec![i as u8]))
+            .collect();
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        for i in 0..count {
+            switch.force_select(i as u8).unwrap();
+        }
+        let history = switch.history().unwrap();
+        assert_eq!(HISTORY_LEN, history.len());
+        let channels: Vec<_> = history.iter().map(|entry| entry.channels).collect();
+        let expected: Vec<_> = (3..count).map(|i| i as u8).collect();
+        assert_eq!(expected, channels);
+        switch.clear_history();
+        assert!(switch.history().unwrap().is_empty());
+        switch.destroy().done();
+    }
+}
+
+#[cfg(feature = "stats")]
+mod stats {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c};
+    use xca9548a::{Channel, MuxGroup, MuxSpec};
+
+    #[test]
+    fn counts_switches_transfers_and_errors() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(ErrorKind::Bus),
+            I2cTrans::write(DEV_ADDR, vec![0x02]).with_error(ErrorKind::Bus),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap_err();
+            parts.i2c1.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap_err();
+            switch
+                .upstream()
+                .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+                .unwrap();
+        }
+        let stats = switch.stats().unwrap();
+        assert_eq!(2, stats.channel_switches());
+        assert_eq!(1, stats.select_errors());
+        assert_eq!(2, stats.transactions(Channel::Ch0));
+        assert_eq!(1, stats.errors(Channel::Ch0));
+        assert_eq!(0, stats.transactions(Channel::Ch1));
+        assert_eq!(1, stats.upstream_transactions());
+        assert_eq!(0, stats.upstream_errors());
+        assert_eq!(0, stats.borrow_failures());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn counts_each_device_of_group() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR | 1, vec![0x02]),
+        ];
+        let mut group = MuxGroup::new(
+            I2cMock::new(&transactions),
+            [
+                MuxSpec::xca9548a(SlaveAddr::default()).unwrap(),
+                MuxSpec::xca9543a(SlaveAddr::Alternative(false, false, true)).unwrap(),
+            ],
+        )
+        .unwrap();
+        group
+            .bus(0, Channel::Ch0)
+            .unwrap()
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        group.select_channels(1, 0x02).unwrap();
+        let first = group.stats(0).unwrap();
+        assert_eq!(1, first.channel_switches());
+        assert_eq!(1, first.transactions(Channel::Ch0));
+        let second = group.stats(1).unwrap();
+        assert_eq!(1, second.channel_switches());
+        assert_eq!(0, second.transactions(Channel::Ch0));
+        assert_eq!(None, group.stats(2));
+        group.destroy().done();
+    }
+}
+
+#[cfg(feature = "trace")]
+mod trace {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c};
+    use xca9548a::Tracer;
+
+    #[test]
+    fn traces_selections_and_transfers() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(ErrorKind::Bus),
+            I2cTrans::write(DEV_ADDR, vec![0x02]).with_error(ErrorKind::Bus),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default())
+            .with_observer(Tracer::new(String::new()));
+        {
+            let mut parts = switch.split();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap_err();
+            parts.i2c1.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap_err();
+            switch
+                .upstream()
+                .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+                .unwrap();
+        }
+        let (switch, tracer) = switch.release_observer();
+        assert_eq!(
+            "select 0x01 ok\n\
+             Write 0x20 on 0x01 ok\n\
+             Write 0x20 on 0x01 err Bus\n\
+             select 0x02 err Bus\n\
+             select 0x00 ok\n\
+             Write 0x20 on 0x00 ok\n",
+            tracer.into_inner()
+        );
+        switch.destroy().done();
+    }
+}
+
+mod observer {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c};
+    use xca9548a::{AccessKind, Observer};
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        BeforeSelect(u8),
+        AfterSelect(u8, Result<(), ErrorKind>),
+        BeforeTransfer(u8, u16, AccessKind),
+        AfterTransfer(u8, u16, AccessKind, Result<(), ErrorKind>),
+    }
+
+    #[derive(Default)]
+    struct Recorder(Vec<Event>);
+
+    impl Observer for Recorder {
+        fn before_select(&mut self, channels: u8) {
+            self.0.push(Event::BeforeSelect(channels));
+        }
+
+        fn after_select(&mut self, channels: u8, result: Result<(), ErrorKind>) {
+            self.0.push(Event::AfterSelect(channels, result));
+        }
+
+        fn before_transfer(&mut self, channels: u8, address: u16, kind: AccessKind) {
+            self.0.push(Event::BeforeTransfer(channels, address, kind));
+        }
+
+        fn after_transfer(
+            &mut self,
+            channels: u8,
+            address: u16,
+            kind: AccessKind,
+            result: Result<(), ErrorKind>,
+        ) {
+            self.0
+                .push(Event::AfterTransfer(channels, address, kind, result));
+        }
+    }
+
+    #[test]
+    fn calls_hooks_around_selection_and_transfers() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_READ_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(ErrorKind::Bus),
+        ];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default())
+            .with_observer(Recorder::default());
+        {
+            let mut parts = switch.split();
+            let mut data = [0; 2];
+            parts.i2c1.read(SLAVE_ADDR, &mut data).unwrap();
+            parts.i2c1.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap_err();
+        }
+        let (switch, observer) = switch.release_observer();
+        let address = u16::from(SLAVE_ADDR);
+        assert_eq!(
+            vec![
+                Event::BeforeSelect(0x02),
+                Event::AfterSelect(0x02, Ok(())),
+                Event::BeforeTransfer(0x02, address, AccessKind::Read),
+                Event::AfterTransfer(0x02, address, AccessKind::Read, Ok(())),
+                Event::BeforeTransfer(0x02, address, AccessKind::Write),
+                Event::AfterTransfer(0x02, address, AccessKind::Write, Err(ErrorKind::Bus)),
+            ],
+            observer.0
+        );
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn calls_hooks_around_broadcast_write() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x06]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default())
+            .with_observer(Recorder::default());
+        switch
+            .broadcast_write(0x06, SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        let (switch, observer) = switch.release_observer();
+        let address = u16::from(SLAVE_ADDR);
+        assert_eq!(
+            vec![
+                Event::BeforeSelect(0x06),
+                Event::AfterSelect(0x06, Ok(())),
+                Event::BeforeTransfer(0x06, address, AccessKind::Write),
+                Event::AfterTransfer(0x06, address, AccessKind::Write, Ok(())),
+                Event::BeforeSelect(0x01),
+                Event::AfterSelect(0x01, Ok(())),
+            ],
+            observer.0
+        );
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn calls_hooks_around_transfers_on_each_channel() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x04]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x00).unwrap();
+        let mut switch = switch.with_observer(Recorder::default());
+        switch
+            .for_each_channel(0x04, |_, i2c| i2c.write(SLAVE_ADDR, &SLAVE_WRITE_DATA))
+            .unwrap();
+        let (switch, observer) = switch.release_observer();
+        let address = u16::from(SLAVE_ADDR);
+        assert_eq!(
+            vec![
+                Event::BeforeSelect(0x04),
+                Event::AfterSelect(0x04, Ok(())),
+                Event::BeforeTransfer(0x04, address, AccessKind::Write),
+                Event::AfterTransfer(0x04, address, AccessKind::Write, Ok(())),
+                Event::BeforeSelect(0x00),
+                Event::AfterSelect(0x00, Ok(())),
+            ],
+            observer.0
+        );
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn can_borrow_observer() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x01]).with_error(ErrorKind::Bus)];
+        let mut recorder = Recorder::default();
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default())
+            .with_observer(&mut recorder);
+        switch.select_channels(0x01).unwrap_err();
+        assert_eq!(2, switch.observer_mut().0.len());
+        switch.destroy().done();
+        assert_eq!(Event::AfterSelect(0x01, Err(ErrorKind::Bus)), recorder.0[1]);
+    }
+}
+
+mod warm_up {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c};
+    use xca9548a::{Channel, Error, Observer};
+
+    const REPEATER_ADDR: u8 = 0x2C;
+
+    #[derive(Default)]
+    struct Repeaters(Vec<Channel>);
+
+    impl Observer for Repeaters {
+        fn warm_up<I2C: I2c>(&mut self, channel: Channel, i2c: &mut I2C) -> Result<(), I2C::Error> {
+            self.0.push(channel);
+            i2c.write(REPEATER_ADDR, &[channel.index()])
+        }
+    }
+
+    #[test]
+    fn warms_up_each_channel_once() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x05]),
+            I2cTrans::write(REPEATER_ADDR, vec![0]),
+            I2cTrans::write(REPEATER_ADDR, vec![2]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(REPEATER_ADDR, vec![1]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default())
+            .with_observer(Repeaters::default());
+        switch.select_channels(0x05).unwrap();
+        switch.select_channels(0x01).unwrap();
+        switch
+            .split()
+            .i2c1
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        assert_eq!(0x07, switch.warmed_up_channels().unwrap());
+        let (switch, repeaters) = switch.release_observer();
+        assert_eq!(vec![Channel::Ch0, Channel::Ch2, Channel::Ch1], repeaters.0);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn retries_failed_warm_up() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(REPEATER_ADDR, vec![0]).with_error(ErrorKind::Bus),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(REPEATER_ADDR, vec![0]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default())
+            .with_observer(Repeaters::default());
+        let error = switch.select_channels(0x01).unwrap_err();
+        assert_eq!(Error::I2C(ErrorKind::Bus), error);
+        assert_eq!(0, switch.warmed_up_channels().unwrap());
+        switch.force_select(0x01).unwrap();
+        assert_eq!(0x01, switch.warmed_up_channels().unwrap());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn warms_up_again_after_reset() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(REPEATER_ADDR, vec![0]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(REPEATER_ADDR, vec![0]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default())
+            .with_observer(Repeaters::default());
+        switch.select_channels(0x01).unwrap();
+        switch.reset_warm_up();
+        switch.force_select(0x01).unwrap();
+        switch.destroy().done();
+    }
+}
+
+mod latency {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use xca9548a::{Channel, LatencyRecorder, TimestampSource};
+
+    struct Script(Vec<u32>);
+
+    impl TimestampSource for Script {
+        fn now(&mut self) -> u32 {
+            self.0.remove(0)
+        }
+    }
+
+    fn recorder(timestamps: &[u32]) -> LatencyRecorder<Script> {
+        LatencyRecorder::new(Script(timestamps.to_vec()))
+    }
+
+    #[test]
+    fn measures_per_channel() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let timestamps = [0, 5, 10, 13, 20, 120, 200, 204, 210, 1210];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default())
+            .with_observer(recorder(&timestamps));
+        {
+            let mut parts = switch.split();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            parts.i2c1.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        let latency = switch.observer_mut();
+        let ch0 = latency.channel(Channel::Ch0);
+        assert_eq!(2, ch0.count());
+        assert_eq!(Some(3), ch0.min());
+        assert_eq!(Some(100), ch0.max());
+        assert_eq!(Some(51), ch0.mean());
+        assert_eq!(1, ch0.histogram()[1]);
+        assert_eq!(1, ch0.histogram()[6]);
+        assert_eq!(Some(1000), latency.channel(Channel::Ch1).max());
+        assert_eq!(2, latency.selections().count());
+        assert_eq!(Some(4), latency.selections().min());
+        assert_eq!(0, latency.upstream().count());
+        assert_eq!(None, latency.channel(Channel::Ch2).mean());
+        latency.reset();
+        assert_eq!(0, latency.channel(Channel::Ch0).count());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn handles_timestamp_wrap_around() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default())
+            .with_observer(recorder(&[0, 1, u32::MAX - 1, 3]));
+        switch
+            .upstream()
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        assert_eq!(Some(5), switch.observer_mut().upstream().max());
+        switch.destroy().done();
+    }
+}
+
+#[cfg(feature = "test-util")]
+mod sim {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c, NoAcknowledgeSource};
+    use xca9548a::{Channel, Error, SimXca9548a};
+
+    const NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+
+    #[test]
+    fn routes_transactions_to_selected_channel() {
+        let ch0 = I2cMock::new(&[I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec())]);
+        let ch5 = I2cMock::new(&[I2cTrans::write_read(
+            SLAVE_ADDR,
+            vec![0x01],
+            SLAVE_READ_DATA.to_vec(),
+        )]);
+        let sim = SimXca9548a::default()
+            .attach(Channel::Ch0, ch0)
+            .attach(Channel::Ch5, ch5);
+        let switch = Xca9548a::new(sim, SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            let mut data = [0; 2];
+            parts
+                .i2c5
+                .write_read(SLAVE_ADDR, &[0x01], &mut data)
+                .unwrap();
+            assert_eq!(SLAVE_READ_DATA, data);
+        }
+        assert_eq!(0b0010_0000, switch.get_channel_status().unwrap());
+        let mut sim = switch.destroy();
+        sim.detach(Channel::Ch0).unwrap().done();
+        sim.detach(Channel::Ch5).unwrap().done();
+    }
+
+    #[test]
+    fn forwards_to_all_selected_channels() {
+        let transactions = [I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec())];
+        let sim = SimXca9548a::default()
+            .attach(Channel::Ch1, I2cMock::new(&transactions))
+            .attach(Channel::Ch2, I2cMock::new(&transactions));
+        let mut switch = Xca9548a::new(sim, SlaveAddr::default());
+        switch
+            .broadcast_write(0b0000_0110, SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        let mut sim = switch.destroy();
+        assert_eq!(0, sim.control());
+        sim.bus_mut(Channel::Ch1).unwrap().done();
+        sim.bus_mut(Channel::Ch2).unwrap().done();
+    }
+
+    #[test]
+    fn does_not_acknowledge_without_bus() {
+        let sim = SimXca9548a::<I2cMock>::new(DEV_ADDR);
+        let mut switch = Xca9548a::new(sim, SlaveAddr::default());
+        assert_eq!(
+            Err(Error::I2C(NACK)),
+            switch.write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+        );
+        let result = switch.split().i2c3.write(SLAVE_ADDR, &SLAVE_WRITE_DATA);
+        assert_eq!(Err(Error::I2C(NACK)), result);
+        assert_eq!(DEV_ADDR, switch.destroy().address());
+    }
+
+    fn sim_with_sensor(transactions: &[I2cTrans]) -> SimXca9548a<I2cMock> {
+        SimXca9548a::default().attach(Channel::Ch0, I2cMock::new(transactions))
+    }
+
+    #[test]
+    fn can_inject_control_nack() {
+        let mut sim = sim_with_sensor(&[]);
+        sim.set_control_nack(true);
+        let mut switch = Xca9548a::new(sim, SlaveAddr::default());
+        assert_eq!(
+            Err(Error::ChannelSelect(NACK)),
+            switch.select_channels(0x01)
+        );
+        switch.with_bus(|sim| sim.clear_faults());
+        switch.select_channels(0x01).unwrap();
+        switch.destroy().detach(Channel::Ch0).unwrap().done();
+    }
+
+    #[test]
+    fn can_inject_address_nack() {
+        let transactions = [I2cTrans::write(SLAVE_ADDR + 1, vec![0x01])];
+        let mut sim = sim_with_sensor(&transactions);
+        sim.set_address_nack(SLAVE_ADDR, true);
+        let switch = Xca9548a::new(sim, SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            let result = parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA);
+            assert_eq!(Err(Error::I2C(NACK)), result);
+            parts.i2c0.write(SLAVE_ADDR + 1, &[0x01]).unwrap();
+        }
+        switch.destroy().detach(Channel::Ch0).unwrap().done();
+    }
+
+    #[test]
+    fn can_inject_arbitration_loss_once() {
+        let transactions = [I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec())];
+        let mut sim = sim_with_sensor(&transactions);
+        sim.set_arbitration_loss_after(Some(2));
+        let switch = Xca9548a::new(sim, SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            let result = parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA);
+            assert_eq!(Err(Error::I2C(ErrorKind::ArbitrationLoss)), result);
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().detach(Channel::Ch0).unwrap().done();
+    }
+
+    #[test]
+    fn replays_recording_per_channel() {
+        use xca9548a::record::Recording;
+
+        let fixture = "\
+            select 70 01 ok\n\
+            transfer 01 20 w:00 r:1234 ok\n\
+            select 70 02 ok\n\
+            transfer 02 20 r:5678 ok\n\
+            transfer 02 20 w:01 err:nack-data\n\
+            transfer 00 21 w:ff ok\n\
+            transfer 01 20 w:00 w:01 ok\n";
+        let recording: Recording = fixture.parse().unwrap();
+        let sim = SimXca9548a::from_recording(DEV_ADDR, &recording);
+        let switch = Xca9548a::new(sim, SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            let mut data = [0; 2];
+            parts.i2c1.read(SLAVE_ADDR, &mut data).unwrap();
+            assert_eq!([0x56, 0x78], data);
+            parts
+                .i2c0
+                .write_read(SLAVE_ADDR, &[0x00], &mut data)
+                .unwrap();
+            assert_eq!([0x12, 0x34], data);
+            let result = parts.i2c1.write(SLAVE_ADDR, &[0x01]);
+            assert_eq!(
+                Err(Error::I2C(ErrorKind::NoAcknowledge(
+                    NoAcknowledgeSource::Data
+                ))),
+                result
+            );
+            parts
+                .i2c0
+                .transaction(
+                    SLAVE_ADDR,
+                    &mut [Operation::Write(&[0x00]), Operation::Write(&[0x01])],
+                )
+                .unwrap();
+        }
+        let mut sim = switch.destroy();
+        assert!(sim.bus_mut(Channel::Ch2).is_none());
+        sim.done();
+    }
+
+    #[test]
+    fn can_inject_stuck_channel_bit() {
+        let mut sim = sim_with_sensor(&[]);
+        sim.set_stuck_bits(0b0000_0100, 0);
+        let mut switch = Xca9548a::builder().verify_writes(true).build(sim).unwrap();
+        assert_eq!(
+            Err(Error::VerificationFailed {
+                expected: 0b0000_0101,
+                actual: 0b0000_0001,
+            }),
+            switch.select_channels(0b0000_0101)
+        );
+        assert_eq!(0b0000_0001, switch.get_channel_status().unwrap());
+        switch.destroy().detach(Channel::Ch0).unwrap().done();
+    }
+}
+
+#[cfg(feature = "test-util")]
+mod expectations {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use xca9548a::{CachePolicy, Channel, Expectations};
+
+    fn exercise<I2C: I2c>(switch: &Xca9548a<I2C>) {
+        let mut parts = switch.split();
+        parts.i2c2.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        parts.i2c2.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        parts.i2c4.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        switch
+            .upstream()
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+    }
+
+    fn steps(expectations: Expectations) -> Vec<I2cTrans> {
+        let write = || I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec());
+        expectations
+            .on(Channel::Ch2, write())
+            .on(Channel::Ch2, write())
+            .on(Channel::Ch4, write())
+            .upstream(write())
+            .build()
+    }
+
+    #[test]
+    fn inserts_control_writes_when_channel_changes() {
+        let transactions = steps(Expectations::new(SlaveAddr::default()));
+        assert_eq!(7, transactions.len());
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        exercise(&switch);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn follows_always_select_policy() {
+        let transactions =
+            steps(Expectations::new(SlaveAddr::default()).cache_policy(CachePolicy::AlwaysSelect));
+        assert_eq!(8, transactions.len());
+        let switch = Xca9548a::builder()
+            .cache_policy(CachePolicy::AlwaysSelect)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        exercise(&switch);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn follows_auto_deselect() {
+        let transactions = steps(Expectations::new(SlaveAddr::default()).auto_deselect(true));
+        assert_eq!(10, transactions.len());
+        let switch = Xca9548a::builder()
+            .auto_deselect(true)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        exercise(&switch);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn skips_selecting_same_channels() {
+        let alternative = SlaveAddr::Alternative(true, false, false);
+        let transactions = Expectations::new(alternative)
+            .select(0x03)
+            .select(0x03)
+            .direct(I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()))
+            .build();
+        assert_eq!(2, transactions.len());
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), alternative);
+        switch.select_channels(0x03).unwrap();
+        switch.select_channels(0x03).unwrap();
+        switch.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        switch.destroy().done();
+    }
+}
+
+#[cfg(feature = "test-util")]
+mod state_assertions {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+
+    #[test]
+    fn exposes_cache_state() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x08]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut switch = Xca9548a::builder()
+            .integrity_check_interval(5)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        assert_eq!(0, switch.cached_channels().unwrap());
+        // the state of a new device is unknown
+        assert!(!switch.is_cache_clean().unwrap());
+        switch
+            .split()
+            .i2c3
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        switch
+            .split()
+            .i2c3
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        assert_eq!(0x08, switch.cached_channels().unwrap());
+        assert!(switch.is_cache_clean().unwrap());
+        assert_eq!(1, switch.transfers_since_check().unwrap());
+        switch.with_bus(|_| ());
+        assert!(!switch.is_cache_clean().unwrap());
+        switch.destroy().done();
+    }
+}
+
+mod shared_reference {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+
+    fn read_with<I2C: I2c>(mut i2c: I2C) -> [u8; 2] {
+        let mut data = [0; 2];
+        i2c.read(SLAVE_ADDR, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn can_use_shared_reference_as_bus() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_READ_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x01).unwrap();
+        assert_eq!(SLAVE_READ_DATA, read_with(&switch));
+        let mut shared = &switch;
+        shared.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        switch.destroy().done();
+    }
+}
+
+mod part_tags {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+
+    #[test]
+    fn can_attach_tag_to_part() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut part = switch.split().i2c1.with_tag("J3");
+        part.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        assert_eq!("J3", *part.tag());
+        assert_eq!("J3", part.set_tag("J4"));
+        *part.tag_mut() = "J5";
+        assert_eq!("J5", *part.tag());
+        switch.destroy().done();
+    }
+}
+
+mod named_parts {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use xca9548a::{split, Channel};
+
+    #[test]
+    fn can_split_into_named_parts() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x08]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_READ_DATA.to_vec()),
+        ];
+        let switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut buses = split!(switch => { imu: 3, temperature: 0, });
+        assert_eq!(Channel::Ch3, buses.imu.channel());
+        buses.imu.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        let mut data = [0; 2];
+        buses.temperature.read(SLAVE_ADDR, &mut data).unwrap();
+        assert_eq!(SLAVE_READ_DATA, data);
+        switch.destroy().done();
+    }
+}
+
+mod persist {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use xca9548a::{CachePolicy, DeviceState, MismatchPolicy, RetryPolicy, StateStorage};
+
+    #[derive(Default)]
+    struct Ram(Vec<u8>);
+
+    impl StateStorage for Ram {
+        type Error = core::convert::Infallible;
+
+        fn save(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+            self.0 = data.to_vec();
+            Ok(())
+        }
+
+        fn load(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+            let len = self.0.len().min(buffer.len());
+            buffer[..len].copy_from_slice(&self.0[..len]);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn can_restore_saved_state() {
+        let transactions = [
+            I2cTrans::write(0x72, vec![0x04]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut switch = Xca9548a::builder()
+            .address(SlaveAddr::Alternative(false, true, false))
+            .strict(true)
+            .single_channel(true)
+            .retry_policy(RetryPolicy::Exponential {
+                retries: 3,
+                initial_ns: 1_000,
+                max_ns: 50_000,
+                jitter: true,
+            })
+            .mismatch_policy(MismatchPolicy::Notify)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        switch.select_channels(0x04).unwrap();
+        let mut storage = Ram::default();
+        let state = switch.state().unwrap();
+        state.save(&mut storage).unwrap();
+        let (i2c, _) = switch.destroy_with_state();
+
+        let restored = DeviceState::load(&mut storage).unwrap().unwrap();
+        assert_eq!(state, restored);
+        let switch = Xca9548a::from_parts(i2c, restored);
+        {
+            // channel 2 is still known to be selected
+            let mut parts = switch.split();
+            parts.i2c2.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn keeps_dirty_cache() {
+        let transactions = [I2cTrans::read(DEV_ADDR, vec![0x01])];
+        let mut switch = Xca9548a::builder()
+            .cache_policy(CachePolicy::AlwaysSelect)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        switch.invalidate_cache();
+        let state = DeviceState::from_bytes(&switch.state().unwrap().to_bytes()).unwrap();
+        let (i2c, _) = switch.destroy_with_state();
+        let switch = Xca9548a::from_parts(i2c, state);
+        assert!(!switch.is_cache_clean().unwrap());
+        assert_eq!(0x01, switch.get_channel_status().unwrap());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn nothing_stored_loads_none() {
+        assert_eq!(None, DeviceState::load(&mut Ram::default()).unwrap());
+    }
+
+    #[test]
+    fn rejects_corrupted_data() {
+        let switch = Xca9548a::new(I2cMock::new(&[]), SlaveAddr::default());
+        let mut bytes = switch.state().unwrap().to_bytes();
+        bytes[2] ^= 0x10;
+        assert_eq!(None, DeviceState::from_bytes(&bytes));
+        assert_eq!(None, DeviceState::from_bytes(&bytes[..10]));
+        switch.destroy().done();
+    }
+}
+
+mod last_error {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c, NoAcknowledgeSource};
+    use xca9548a::Channel;
+
+    #[test]
+    fn records_last_error_per_channel() {
+        let nack = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data);
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x04]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(nack),
+            I2cTrans::write(DEV_ADDR, vec![0x20]).with_error(ErrorKind::Bus),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            parts.i2c2.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap_err();
+            parts.i2c5.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap_err();
+        }
+        let ch2 = switch.last_error(Channel::Ch2).unwrap().unwrap();
+        let ch5 = switch.last_error(Channel::Ch5).unwrap().unwrap();
+        assert_eq!(nack, ch2.kind());
+        assert_eq!(ErrorKind::Bus, ch5.kind());
+        assert!(ch5.sequence() > ch2.sequence());
+        assert_eq!(None, switch.last_error(Channel::Ch0).unwrap());
+
+        switch.clear_last_errors();
+        assert_eq!(None, switch.last_error(Channel::Ch2).unwrap());
+        assert_eq!(None, switch.last_error(Channel::Ch5).unwrap());
+        switch.destroy().done();
+    }
+}
+
+mod lock {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use xca9548a::{AccessContext, AccessKind, Channel, Error};
+
+    #[test]
+    fn gives_exclusive_access_to_channel() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x08]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_READ_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut parts = switch.split();
+            let mut guard = switch.lock(Channel::Ch3).unwrap();
+            assert_eq!(Channel::Ch3, guard.channel());
+            guard.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            assert_eq!(
+                Err(Error::CouldNotAcquireDevice(AccessContext {
+                    kind: AccessKind::Write,
+                    channel: Some(Channel::Ch0),
+                })),
+                parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            );
+            let mut data = [0; 2];
+            guard.read(SLAVE_ADDR, &mut data).unwrap();
+            assert_eq!(SLAVE_READ_DATA, data);
+            drop(guard);
+            parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn cannot_lock_twice() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x01])];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let _guard = switch.lock(Channel::Ch0).unwrap();
+            assert!(matches!(
+                switch.lock(Channel::Ch1),
+                Err(Error::CouldNotAcquireDevice(AccessContext {
+                    kind: AccessKind::SelectChannels,
+                    channel: None,
+                }))
+            ));
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn cannot_lock_missing_channel() {
+        let switch = Xca9543a::new(I2cMock::new(&[]), SlaveAddr::default());
+        assert!(matches!(
+            switch.lock(Channel::Ch5),
+            Err(Error::InvalidChannels(0x20))
+        ));
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn state_queries_fail_while_locked() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x01])];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let busy = Error::CouldNotAcquireDevice(AccessContext {
+            kind: AccessKind::Inspect,
+            channel: None,
+        });
+        {
+            let _guard = switch.lock(Channel::Ch0).unwrap();
+            assert_eq!(DEV_ADDR, switch.device_info().address);
+            assert_eq!(Some(busy), switch.state().err());
+            assert_eq!(Err(busy), switch.last_error(Channel::Ch0));
+            assert_eq!(Err(busy), switch.is_cache_clean());
+            assert_eq!(Err(busy), switch.warmed_up_channels());
+        }
+        assert!(switch.is_cache_clean().unwrap());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn guard_is_stale_after_splitting_again() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x02])];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut guard = switch.lock(Channel::Ch1).unwrap();
+            let _parts = switch.split();
+            assert_eq!(
+                Err(Error::StaleHandle(Channel::Ch1)),
+                guard.write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            );
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn runs_deferred_read_after_unlocking() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::read(DEV_ADDR, vec![0x01]),
+        ];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let guard = switch.lock(Channel::Ch0).unwrap();
+        assert_eq!(Ok(None), switch.get_status_deferred());
+        drop(guard);
+        assert_eq!(Some(Ok(0x01)), switch.take_deferred_status());
+        switch.destroy().done();
+    }
+}
+
+mod channel_info {
+    use super::*;
+    use xca9548a::{Channel, ChannelInfo};
+
+    fn location(bus: &impl ChannelInfo) -> String {
+        format!(
+            "mux 0x{:02x} ch {}",
+            bus.mux_address(),
+            bus.channel().index()
+        )
+    }
+
+    #[test]
+    fn parts_report_their_location() {
+        let switch = Xca9548a::new(
+            I2cMock::new(&[]),
+            SlaveAddr::Alternative(false, false, true),
+        );
+        {
+            let mut parts = switch.split();
+            assert_eq!("mux 0x71 ch 4", location(&parts.i2c4));
+            assert_eq!("mux 0x71 ch 7", location(&&mut parts.i2c7));
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn lock_guard_reports_its_location() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x02])];
+        let switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let guard = switch.lock(Channel::Ch1).unwrap();
+            assert_eq!("mux 0x70 ch 1", location(&guard));
+        }
+        switch.destroy().done();
+    }
+}
+
+mod build_array {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use xca9548a::Channel;
+
+    struct Sensor<I2C>(I2C);
+
+    impl<I2C: I2c> Sensor<I2C> {
+        fn read(&mut self) -> Result<[u8; 2], I2C::Error> {
+            let mut data = [0; 2];
+            self.0.read(SLAVE_ADDR, &mut data)?;
+            Ok(data)
+        }
+    }
+
+    #[test]
+    fn builds_one_driver_per_channel() {
+        let mut transactions = Vec::new();
+        for channel in 0..4 {
+            transactions.push(I2cTrans::write(DEV_ADDR, vec![1 << channel]));
+            transactions.push(I2cTrans::read(SLAVE_ADDR, vec![channel, 0]));
+        }
+        let switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        {
+            let mut sensors: [Sensor<_>; 4] = switch.build_array(Sensor);
+            assert_eq!(Channel::Ch3, sensors[3].0.channel());
+            for (i, sensor) in sensors.iter_mut().enumerate() {
+                assert_eq!([i as u8, 0], sensor.read().unwrap());
+            }
+        }
+        switch.destroy().done();
+    }
+}
+
+mod sequence {
+    use super::*;
+    use embedded_hal::delay::DelayNs;
+    use embedded_hal::i2c::ErrorKind;
+    use xca9548a::{Channel, Error, Step};
+
+    #[derive(Default)]
+    struct RecordingDelay(Vec<u32>);
+
+    impl DelayNs for RecordingDelay {
+        fn delay_ns(&mut self, ns: u32) {
+            self.0.push(ns);
+        }
+    }
+
+    #[test]
+    fn runs_steps_switching_channels_only_when_needed() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_READ_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x04]),
+            I2cTrans::write_read(SLAVE_ADDR, vec![0x01], vec![0xAB]),
+            I2cTrans::write(DEV_ADDR, vec![0]),
+        ];
+        let switch = Xca9548a::builder()
+            .auto_deselect(true)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        let mut switch = switch.with_settle_delay(RecordingDelay::default(), 0);
+        let mut data = [0; 2];
+        let mut register = [0];
+        switch
+            .run_sequence(&mut [
+                Step::Write {
+                    channel: Channel::Ch1,
+                    address: SLAVE_ADDR,
+                    data: &SLAVE_WRITE_DATA,
+                },
+                Step::Delay { ns: 1000 },
+                Step::Read {
+                    channel: Channel::Ch1,
+                    address: SLAVE_ADDR,
+                    buffer: &mut data,
+                },
+                Step::WriteRead {
+                    channel: Channel::Ch2,
+                    address: SLAVE_ADDR,
+                    data: &[0x01],
+                    buffer: &mut register,
+                },
+            ])
+            .unwrap();
+        assert_eq!(SLAVE_READ_DATA, data);
+        assert_eq!([0xAB], register);
+        let (switch, delay) = switch.release_settle_delay();
+        assert_eq!(vec![1000], delay.0);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn reports_failed_step() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(ErrorKind::Bus),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let step = || Step::Write {
+            channel: Channel::Ch0,
+            address: SLAVE_ADDR,
+            data: &SLAVE_WRITE_DATA,
+        };
+        assert_eq!(
+            Err((1, Error::I2C(ErrorKind::Bus))),
+            switch.run_sequence(&mut [step(), step(), step()])
+        );
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn rejects_missing_channel_before_running() {
+        let mut switch = Xca9543a::new(I2cMock::new(&[]), SlaveAddr::default());
+        let mut steps = [
+            Step::Write {
+                channel: Channel::Ch0,
+                address: SLAVE_ADDR,
+                data: &SLAVE_WRITE_DATA,
+            },
+            Step::Write {
+                channel: Channel::Ch5,
+                address: SLAVE_ADDR,
+                data: &SLAVE_WRITE_DATA,
+            },
+        ];
+        assert_eq!(
+            Err((1, Error::InvalidChannels(0x20))),
+            switch.run_sequence(&mut steps)
+        );
+        switch.destroy().done();
+    }
+}
+
+mod raw_select {
+    use super::*;
+    use embedded_hal::i2c::ErrorKind;
+    use xca9548a::select_channels_raw;
+
+    #[test]
+    fn writes_control_register() {
+        let mut i2c = I2cMock::new(&[I2cTrans::write(0x72, vec![0x81])]);
+        select_channels_raw(&mut i2c, 0x72, 0x81).unwrap();
+        i2c.done();
+    }
+
+    #[test]
+    fn returns_bus_error() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0]).with_error(ErrorKind::Bus)];
+        let mut i2c = I2cMock::new(&transactions);
+        assert_eq!(
+            Err(ErrorKind::Bus),
+            select_channels_raw(&mut i2c, DEV_ADDR, 0)
+        );
+        i2c.done();
+    }
+}
+
+mod routing {
+    use xca9548a::{Channel, Route, RoutingError, RoutingTable, SlaveAddr};
+
+    const ROUTES: [(u8, Route); 3] = [
+        (1, Route::new(Channel::Ch0, 0x48)),
+        (2, Route::new(Channel::Ch1, 0x48)),
+        (3, Route::new(Channel::Ch1, 0x50)),
+    ];
+
+    #[test]
+    fn can_look_up_routes() {
+        let table = RoutingTable::new(&ROUTES);
+        table.validate(SlaveAddr::default()).unwrap();
+        assert_eq!(Some(Route::new(Channel::Ch1, 0x48)), table.route(&2));
+        assert_eq!(None, table.route(&4));
+        assert_eq!(Some(&1), table.id_at(Channel::Ch0, 0x48));
+        assert_eq!(None, table.id_at(Channel::Ch2, 0x48));
+        assert_eq!(
+            vec![(&2, 0x48), (&3, 0x50)],
+            table.on_channel(Channel::Ch1).collect::<Vec<_>>()
+        );
+        assert_eq!(0b11, table.channels());
+        assert_eq!(3, table.iter().count());
+    }
+
+    #[test]
+    fn detects_duplicate_ids() {
+        let routes = [
+            (1, Route::new(Channel::Ch0, 0x48)),
+            (1, Route::new(Channel::Ch1, 0x48)),
+        ];
+        let table = RoutingTable::new(&routes);
+        let error = table.validate(SlaveAddr::default()).unwrap_err();
+        assert_eq!(RoutingError::DuplicateId(&1), error);
+        assert_eq!("Device 1 is routed more than once", error.to_string());
+    }
+
+    #[test]
+    fn detects_shared_routes() {
+        let routes = [
+            (1, Route::new(Channel::Ch2, 0x48)),
+            (2, Route::new(Channel::Ch2, 0x48)),
+        ];
+        let table = RoutingTable::new(&routes);
+        let error = table.validate(SlaveAddr::default()).unwrap_err();
+        assert_eq!(
+            RoutingError::SharedRoute {
+                route: Route::new(Channel::Ch2, 0x48),
+                ids: (&1, &2),
+            },
+            error
+        );
+        assert_eq!(
+            "Devices 1 and 2 are both routed to 0x48 on channel 2",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn detects_switch_address() {
+        let routes = [(7, Route::new(Channel::Ch0, 0x71))];
+        let table = RoutingTable::new(&routes);
+        table.validate(SlaveAddr::default()).unwrap();
+        let error = table
+            .validate(SlaveAddr::Alternative(false, false, true))
+            .unwrap_err();
+        assert_eq!(RoutingError::SwitchAddress(&7), error);
+    }
+}
+
+mod single_channel {
+    use super::*;
+    use xca9548a::Error;
+
+    #[test]
+    fn rejects_multiple_channels_before_bus_access() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x02])];
+        let mut switch = Xca9548a::builder()
+            .single_channel(true)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        assert_eq!(
+            Err(Error::MultipleChannels(0x03)),
+            switch.select_channels(0x03)
+        );
+        assert_eq!(
+            Err(Error::MultipleChannels(0x81)),
+            switch.write_control_register(0x81)
+        );
+        switch.select_channels(0x02).unwrap();
+        assert_eq!(
+            Err(Error::MultipleChannels(0x05)),
+            switch.broadcast_write(0x05, SLAVE_ADDR, &SLAVE_WRITE_DATA)
+        );
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn ignores_channels_not_present() {
+        let transactions = [I2cTrans::write(DEV_ADDR, vec![0x01])];
+        let mut switch = Xca9543a::builder()
+            .single_channel(true)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        switch.select_channels(0x81).unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn can_display_error() {
+        let error: Error<()> = Error::MultipleChannels(0x03);
+        assert_eq!(
+            "Channels 0b00000011 can not be selected at the same time in single-channel mode",
+            error.to_string()
+        );
+        assert_eq!(None, error.into_inner());
+    }
+}
+
+mod one_shot {
+    use super::*;
+    use embedded_hal::i2c::ErrorKind;
+    use xca9548a::{Channel, Error};
+
+    #[test]
+    fn selects_transfers_and_restores() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x08]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x04]),
+            I2cTrans::read(SLAVE_ADDR, SLAVE_READ_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write_read(SLAVE_ADDR, vec![0x10], vec![0xAB]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x01).unwrap();
+        switch
+            .write_to(Channel::Ch3, SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        let mut data = [0; 2];
+        switch
+            .read_from(Channel::Ch2, SLAVE_ADDR, &mut data)
+            .unwrap();
+        assert_eq!(SLAVE_READ_DATA, data);
+        // already selected, so no switching
+        let mut register = [0];
+        switch
+            .write_read_on(Channel::Ch0, SLAVE_ADDR, &[0x10], &mut register)
+            .unwrap();
+        assert_eq!([0xAB], register);
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn restores_after_failed_transfer() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0]),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(ErrorKind::Bus),
+            I2cTrans::write(DEV_ADDR, vec![0]),
+        ];
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        assert_eq!(
+            Err(Error::I2C(ErrorKind::Bus)),
+            switch.write_to(Channel::Ch1, SLAVE_ADDR, &SLAVE_WRITE_DATA)
+        );
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn rejects_missing_channel() {
+        let mut switch = Xca9543a::new(I2cMock::new(&[]), SlaveAddr::default());
+        assert_eq!(
+            Err(Error::InvalidChannels(0x20)),
+            switch.write_to(Channel::Ch5, SLAVE_ADDR, &SLAVE_WRITE_DATA)
+        );
+        let mut data = [0; 2];
+        assert_eq!(
+            Err(Error::InvalidChannels(0x80)),
+            switch.read_from(Channel::Ch7, SLAVE_ADDR, &mut data)
+        );
+        assert_eq!(
+            Err(Error::InvalidChannels(0x04)),
+            switch.write_read_on(Channel::Ch2, SLAVE_ADDR, &[0x10], &mut data)
+        );
+        switch.destroy().done();
+    }
+}
+
+mod stale_handle {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c};
+    use xca9548a::{Channel, Error};
+
+    #[test]
+    fn parts_are_stale_after_splitting_again() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut old = switch.split();
+        let mut new = switch.split();
+        assert_eq!(
+            Err(Error::StaleHandle(Channel::Ch1)),
+            old.i2c1.write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+        );
+        assert_eq!(
+            Err(Error::StaleHandle(Channel::Ch1)),
+            old.i2c1.probe(SLAVE_ADDR)
+        );
+        new.i2c1.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn parts_are_stale_after_invalidating_them() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut old = switch.part::<0>().with_tag("sensor");
+        switch.invalidate_parts();
+        let mut new = switch.part::<0>();
+        assert_eq!(
+            Err(Error::StaleHandle(Channel::Ch0)),
+            old.write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+        );
+        new.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn parts_keep_working_after_recovering() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(ErrorKind::Bus),
+            I2cTrans::read(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let switch = Xca9548a::builder()
+            .auto_recover(true)
+            .build(I2cMock::new(&transactions))
+            .unwrap();
+        let mut parts = switch.split();
+        parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        parts.i2c0.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        parts.i2c1.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        switch.destroy().done();
+    }
+}
+
+mod release {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+
+    #[test]
+    fn part_deselects_its_channel() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x05]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x05).unwrap();
+        {
+            let mut parts = switch.split();
+            parts.i2c2.release().unwrap();
+            parts.i2c2.release().unwrap();
+            parts.i2c3.release().unwrap();
+        }
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn part_reads_control_register_if_cache_is_dirty() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.invalidate_cache();
+        {
+            let mut parts = switch.split();
+            parts.i2c1.release().unwrap();
+            parts.i2c1.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        switch.destroy().done();
+    }
+}
+
+mod hotplug {
+    use super::scan::scan_transactions;
+    use super::*;
+    use xca9548a::{Channel, HotPlugEvent, HotPlugMonitor, NoDelay};
+
+    #[test]
+    fn reports_devices_appearing_and_disappearing() {
+        let mut transactions = vec![I2cTrans::read(DEV_ADDR, vec![0])];
+        transactions.extend(scan_transactions(2, &[(0, 0x20), (1, 0x48)]));
+        transactions.extend(scan_transactions(2, &[(0, 0x20), (1, 0x48)]));
+        transactions.extend(scan_transactions(2, &[(0, 0x20), (0, 0x48), (1, 0x50)]));
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let mut events = Vec::new();
+        let mut callback = |event| events.push(event);
+        let mut monitor = HotPlugMonitor::new(100, &mut callback);
+        assert!(monitor.rescan(&mut switch).unwrap().is_empty());
+        assert_eq!(2, monitor.report().unwrap().device_count());
+        assert!(monitor
+            .wait_and_rescan(&mut switch, &mut NoDelay)
+            .unwrap()
+            .is_empty());
+        let diff = monitor.rescan(&mut switch).unwrap();
+        assert_eq!(0b11, diff.changed_channels());
+        assert_eq!(
+            vec![
+                HotPlugEvent::Added {
+                    channel: Channel::Ch0,
+                    address: 0x48
+                },
+                HotPlugEvent::Removed {
+                    channel: Channel::Ch1,
+                    address: 0x48
+                },
+                HotPlugEvent::Added {
+                    channel: Channel::Ch1,
+                    address: 0x50
+                },
+            ],
+            events
+        );
+        switch.destroy().done();
+    }
+}
+
+mod failover {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, I2c, NoAcknowledgeSource};
+    use xca9548a::{Active, Channel, ChannelInfo, Error, Failover, Observer};
+
+    const NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+
+    #[derive(Default)]
+    struct FailoverLog(Vec<u8>);
+
+    impl Observer for FailoverLog {
+        fn failover(&mut self, failed_address: u8) {
+            self.0.push(failed_address);
+        }
+    }
+
+    #[test]
+    fn switches_to_secondary_when_primary_does_not_respond() {
+        let primary = [
+            I2cTrans::write(DEV_ADDR, vec![0x04]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(NACK),
+            I2cTrans::read(DEV_ADDR, vec![0]).with_error(NACK),
+        ];
+        let secondary = [
+            I2cTrans::write(0x71, vec![0x04]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut primary_log = FailoverLog::default();
+        let mut secondary_log = FailoverLog::default();
+        let pair = Failover::new(
+            Xca9548a::new(I2cMock::new(&primary), SlaveAddr::default())
+                .with_observer(&mut primary_log),
+            Xca9548a::new(
+                I2cMock::new(&secondary),
+                SlaveAddr::Alternative(false, false, true),
+            )
+            .with_observer(&mut secondary_log),
+        );
+        {
+            let mut bus = pair.bus(Channel::Ch2);
+            assert_eq!(DEV_ADDR, bus.mux_address());
+            bus.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            assert_eq!(Active::Primary, pair.active());
+            bus.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+            assert_eq!(Active::Secondary, pair.active());
+            assert_eq!(0x71, bus.mux_address());
+            bus.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        }
+        let (primary, secondary) = pair.destroy();
+        primary.destroy().done();
+        secondary.destroy().done();
+        assert!(primary_log.0.is_empty());
+        assert_eq!(vec![DEV_ADDR], secondary_log.0);
+    }
+
+    #[test]
+    fn keeps_primary_on_downstream_errors() {
+        let primary = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()).with_error(NACK),
+            I2cTrans::read(DEV_ADDR, vec![0x01]),
+        ];
+        let pair = Failover::new(
+            Xca9543a::new(I2cMock::new(&primary), SlaveAddr::default()),
+            Xca9543a::new(
+                I2cMock::new(&[]),
+                SlaveAddr::Alternative(false, false, true),
+            ),
+        );
+        assert_eq!(
+            Err(Error::I2C(NACK)),
+            pair.bus(Channel::Ch0).write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+        );
+        assert_eq!(Active::Primary, pair.active());
+        let (primary, secondary) = pair.destroy();
+        primary.destroy().done();
+        secondary.destroy().done();
+    }
+
+    #[test]
+    fn can_restore_primary() {
+        let primary = [
+            I2cTrans::write(DEV_ADDR, vec![0x01]).with_error(NACK),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let secondary = [
+            I2cTrans::write(0x71, vec![0x01]),
+            I2cTrans::write(SLAVE_ADDR, SLAVE_WRITE_DATA.to_vec()),
+        ];
+        let mut pair = Failover::new(
+            Xca9543a::new(I2cMock::new(&primary), SlaveAddr::default()),
+            Xca9543a::new(
+                I2cMock::new(&secondary),
+                SlaveAddr::Alternative(false, false, true),
+            ),
+        );
+        pair.bus(Channel::Ch0)
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        assert_eq!(Active::Secondary, pair.active());
+        pair.restore_primary();
+        pair.bus(Channel::Ch0)
+            .write(SLAVE_ADDR, &SLAVE_WRITE_DATA)
+            .unwrap();
+        let (primary, secondary) = pair.destroy();
+        primary.destroy().done();
+        secondary.destroy().done();
+    }
+}
+
+mod deferred_status {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, I2c};
+    use std::rc::{Rc, Weak};
+
+    /// Bus reading the status of its own switch while a transfer to a slave
+    /// is in flight.
+    struct Reentrant(Weak<Xca9548a<Reentrant>>);
+
+    impl ErrorType for Reentrant {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Reentrant {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), ErrorKind> {
+            if address == DEV_ADDR {
+                if let [Operation::Read(data)] = operations {
+                    data[0] = 0x05;
+                }
+            } else {
+                let switch = self.0.upgrade().unwrap();
+                assert_eq!(Ok(None), switch.get_status_deferred());
+                assert_eq!(None, switch.take_deferred_status());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reads_right_away_when_available() {
+        let transactions = [I2cTrans::read(DEV_ADDR, vec![0x05])];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        assert_eq!(Some(0x05), switch.get_status_deferred().unwrap());
+        assert_eq!(None, switch.take_deferred_status());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn reads_once_transfer_is_done() {
+        let switch =
+            Rc::new_cyclic(|switch| Xca9548a::new(Reentrant(switch.clone()), SlaveAddr::default()));
+        let mut bus = &*switch;
+        bus.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        assert_eq!(Some(Ok(0x05)), switch.take_deferred_status());
+        assert_eq!(None, switch.take_deferred_status());
+    }
+}
+
+#[cfg(feature = "nb")]
+mod nonblocking {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, I2c};
+    use std::{cell::Cell, rc::Rc, rc::Weak};
+    use xca9548a::Error;
+
+    /// Bus polling its own switch while a transfer is in flight,
+    /// counting how many polls failed to acquire the device.
+    struct Reentrant {
+        switch: Weak<Xca9543a<Reentrant>>,
+        busy: Rc<Cell<u8>>,
+    }
+
+    impl ErrorType for Reentrant {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Reentrant {
+        fn transaction(&mut self, _: u8, _: &mut [Operation<'_>]) -> Result<(), ErrorKind> {
+            let switch = self.switch.upgrade().unwrap();
+            for result in [
+                switch.try_select_channels(0x01).map(|_| 0),
+                switch.try_get_channel_status(),
+                switch.try_get_interrupt_status(),
+            ] {
+                if let Err(nb::Error::Other(Error::CouldNotAcquireDevice(_))) = result {
+                    self.busy.set(self.busy.get() + 1);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn can_select_and_read_status() {
+        let transactions = [
+            I2cTrans::write(DEV_ADDR, vec![0x02]),
+            I2cTrans::read(DEV_ADDR, vec![0x32]),
+            I2cTrans::read(DEV_ADDR, vec![0x32]),
+        ];
+        let switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        nb::block!(switch.try_select_channels(0x02)).unwrap();
+        assert_eq!(0x02, nb::block!(switch.try_get_channel_status()).unwrap());
+        assert_eq!(0x03, nb::block!(switch.try_get_interrupt_status()).unwrap());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn fails_while_transfer_in_flight() {
+        let busy = Rc::new(Cell::new(0));
+        let switch = Rc::new_cyclic(|switch| {
+            Xca9543a::new(
+                Reentrant {
+                    switch: switch.clone(),
+                    busy: busy.clone(),
+                },
+                SlaveAddr::default(),
+            )
+        });
+        let mut bus = &*switch;
+        bus.write(SLAVE_ADDR, &SLAVE_WRITE_DATA).unwrap();
+        assert_eq!(3, busy.get());
+    }
+}
+
+mod self_test {
+    use super::*;
+    use xca9548a::Channel;
+
+    fn self_test_transactions(readback: &[(u8, u8)]) -> Vec<I2cTrans> {
+        let mut transactions = Vec::new();
+        for (pattern, value) in readback {
+            transactions.push(I2cTrans::write(DEV_ADDR, vec![*pattern]));
+            transactions.push(I2cTrans::read(DEV_ADDR, vec![*value]));
+        }
+        transactions
+    }
+
+    #[test]
+    fn passes_with_working_device() {
+        let mut transactions = self_test_transactions(&[(1, 1), (2, 0x22), (0, 0x10)]);
+        transactions.push(I2cTrans::write(DEV_ADDR, vec![0]));
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let report = switch.self_test().unwrap();
+        assert!(report.passed());
+        assert_eq!(0, report.failed_channels().count());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn reports_stuck_channels_and_unexpected_bits() {
+        let readback: Vec<_> = (0..8)
+            .map(|i| (1 << i, (1 << i) & !0x04 | 0x80))
+            .chain(core::iter::once((0, 0x80)))
+            .collect();
+        let mut transactions = self_test_transactions(&readback);
+        transactions.push(I2cTrans::write(DEV_ADDR, vec![0]));
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let report = switch.self_test().unwrap();
+        assert!(!report.passed());
+        assert_eq!(0x04, report.stuck_low());
+        assert_eq!(0x80, report.stuck_high());
+        assert_eq!(0, report.unexpected_bits());
+        assert_eq!(
+            vec![Channel::Ch2, Channel::Ch7],
+            report.failed_channels().collect::<Vec<_>>()
+        );
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn reports_reserved_bits() {
+        let mut transactions = vec![I2cTrans::write(DEV_ADDR, vec![0x02])];
+        transactions.extend(self_test_transactions(&[(1, 0x41), (2, 0x02), (0, 0)]));
+        transactions.push(I2cTrans::write(DEV_ADDR, vec![0x02]));
+        let mut switch = Xca9543a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.select_channels(0x02).unwrap();
+        let report = switch.self_test().unwrap();
+        assert_eq!(0x40, report.unexpected_bits());
+        assert_eq!(0, report.stuck_low() | report.stuck_high());
+        switch.destroy().done();
+    }
+}
+
+mod channel_enabled {
+    use super::*;
+    use xca9548a::Channel;
+
+    #[test]
+    fn can_check_single_channel() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0b0000_0100]),
+            I2cTrans::read(DEV_ADDR, vec![0b0000_0100]),
+        ];
+        let switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        assert!(switch.is_channel_enabled(Channel::Ch2).unwrap());
+        assert!(!switch.is_channel_enabled(Channel::Ch3).unwrap());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn ignores_interrupt_bits_and_missing_channels() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0b0100_0001]),
+            I2cTrans::read(DEV_ADDR, vec![0b0100_0001]),
+        ];
+        let switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        assert!(switch.is_channel_enabled(Channel::Ch0).unwrap());
+        assert!(!switch.is_channel_enabled(Channel::Ch6).unwrap());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn can_iterate_enabled_channels() {
+        let transactions = [I2cTrans::read(DEV_ADDR, vec![0b1010_0101])];
+        let switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        let channels: Vec<_> = switch.enabled_channels().unwrap().collect();
+        assert_eq!(vec![Channel::Ch0, Channel::Ch2], channels);
+        switch.destroy().done();
+    }
+}
+
+mod replace_channels {
+    use super::*;
+
+    #[test]
+    fn returns_previous_selection() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0x00]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+            I2cTrans::write(DEV_ADDR, vec![0x04]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+        ];
+        let mut switch = Xca9548a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        assert_eq!(0, switch.replace_channels(0x01).unwrap());
+        let previous = switch.replace_channels(0x04).unwrap();
+        assert_eq!(0x01, previous);
+        assert_eq!(0x04, switch.replace_channels(previous).unwrap());
+        switch.destroy().done();
+    }
+
+    #[test]
+    fn reads_previous_selection_if_cache_is_dirty() {
+        let transactions = [
+            I2cTrans::read(DEV_ADDR, vec![0x12]),
+            I2cTrans::write(DEV_ADDR, vec![0x01]),
+        ];
+        let mut switch = Xca9545a::new(I2cMock::new(&transactions), SlaveAddr::default());
+        switch.with_bus(|_| ());
+        assert_eq!(0x02, switch.replace_channels(0x01).unwrap());
+        switch.destroy().done();
+    }
+}